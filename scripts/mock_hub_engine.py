@@ -0,0 +1,36 @@
+#!/usr/bin/env python3
+# Stand in Hub engine for trying out --external-engine, it plays the moves given on
+# its command line in order, e.g. mock_hub_engine.py 23-19 22-18 19x10. A move of
+# "hang" never answers, to try out how a stuck engine is handled
+import sys
+
+
+def send(line):
+    sys.stdout.write(line + "\n")
+    sys.stdout.flush()
+
+
+def main():
+    moves = sys.argv[1:]
+    for line in sys.stdin:
+        command = line.split(" ", 1)[0].strip()
+        if command == "hub":
+            send("id name=Mock version=1.0")
+            send("wait")
+        elif command == "init":
+            send("ready")
+        elif command == "ping":
+            send("pong")
+        elif command == "go":
+            if moves and moves[0] == "hang":
+                continue
+            if moves:
+                send("done move=" + moves.pop(0))
+            else:
+                send("error message=\"out of moves\"")
+        elif command == "quit":
+            break
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,278 @@
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool, tasks::Task};
+use futures_lite::future;
+use crate::{
+    state::{GameState, CheckersState, PieceColor},
+    checkers_events::{ButtonSelectEvent, GameOverEvent, GameOverReason, SaveGameEvent},
+    ai::{AIStatus, accepts_draw},
+    logic::PossibleMoves,
+    ballot::Ballot,
+    start::{StartSettings, Handicap},
+    record::GameRecord,
+    ui::{spawn_button, ButtonLayout, BUTTON_BACKGROUND, BUTTON_HOVERED, BUTTON_FONT_COLOR, BUTTON_FONT_SIZE}
+};
+
+
+pub struct CheckersActionsPlugin;
+
+
+impl Plugin for CheckersActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(DrawOffer { from: PieceColor::Black, return_state: GameState::Input })
+        .add_system_set(SystemSet::on_enter(GameState::BoardSetup).with_system(setup_actions))
+        .add_system_set(SystemSet::on_enter(GameState::DrawOffer).with_system(setup_draw_prompt))
+        .add_system_set(SystemSet::on_exit(GameState::DrawOffer).with_system(cleanup_draw_prompt))
+        .add_system(action_button_system)
+        .add_system(draw_decision_system);
+    }
+}
+
+
+// A draw offer waiting for an answer in a two player game
+#[derive(Resource)]
+pub struct DrawOffer {
+    pub from: PieceColor,
+    pub return_state: GameState
+}
+
+
+// The AI thinking over a draw offer, the answer only counts if no move was made meanwhile
+#[derive(Component)]
+struct DrawDecision {
+    task: Task<bool>,
+    turns: usize
+}
+
+
+#[derive(Component)]
+struct ActionsPanel;
+
+
+#[derive(Component)]
+struct DrawPrompt;
+
+
+#[derive(Component)]
+struct ActionMessage;
+
+
+fn action_button() -> ButtonLayout {
+    return ButtonLayout::new(Size::new(Val::Px(180.), Val::Px(45.)), UiRect{top: Val::Px(10.), ..default()});
+}
+
+
+fn setup_actions(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ballot: Res<Ballot>,
+    start: Res<StartSettings>,
+    query: Query<Entity, With<ActionsPanel>>,
+    decision_query: Query<Entity, With<DrawDecision>>
+) {
+    for entity in query.iter().chain(decision_query.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    let font: Handle<Font> = asset_server.load("fonts/MunichRegular.ttf");
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { bottom: Val::Px(20.), right: Val::Px(20.), ..default() },
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        ..default()
+    })
+    .insert(ActionsPanel)
+    .with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: BUTTON_FONT_SIZE,
+                color: BUTTON_FONT_COLOR,
+            },
+        )).insert(ActionMessage);
+        spawn_button(parent, &font, "Save Game", "SAVE", action_button());
+        spawn_button(parent, &font, "Offer Draw", "DRAW", action_button());
+        spawn_button(parent, &font, "Resign", "RESIGN", action_button());
+        // balloted games come in pairs, the second one with the sides swapped
+        if ballot.opening.is_some() || start.removed.is_some() {
+            spawn_button(parent, &font, "Swap Sides", "REMATCH", action_button());
+        }
+    });
+}
+
+
+fn setup_draw_prompt(mut commands: Commands, asset_server: Res<AssetServer>, draw_offer: Res<DrawOffer>) {
+    let font: Handle<Font> = asset_server.load("fonts/MunichRegular.ttf");
+    let message = match draw_offer.from {
+        PieceColor::Black => "Black offers a draw",
+        PieceColor::Red => "Red offers a draw"
+    };
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: Color::rgba(0., 0., 0., 0.6).into(),
+        ..default()
+    })
+    .insert(DrawPrompt)
+    .with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            message,
+            TextStyle {
+                font: asset_server.load("fonts/Pixeboy.ttf"),
+                font_size: 50.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+            },
+        ));
+        spawn_button(parent, &font, "Accept", "ACCEPT", action_button());
+        spawn_button(parent, &font, "Decline", "DECLINE", action_button());
+    });
+}
+
+
+fn cleanup_draw_prompt(mut commands: Commands, query: Query<Entity, With<DrawPrompt>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+
+fn action_button_system(
+    mut commands: Commands,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &Children),
+        (Changed<Interaction>, With<Button>)
+    >,
+    text_query: Query<&Name>,
+    mut message_query: Query<&mut Text, With<ActionMessage>>,
+    mut game_state: ResMut<State<GameState>>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut ai_status: ResMut<AIStatus>,
+    mut possible_moves: ResMut<PossibleMoves>,
+    mut start: ResMut<StartSettings>,
+    mut draw_offer: ResMut<DrawOffer>,
+    game_record: Res<GameRecord>,
+    decision_query: Query<&DrawDecision>,
+    mut game_over_writer: EventWriter<GameOverEvent>,
+    mut save_writer: EventWriter<SaveGameEvent>,
+    mut select_event: EventWriter<ButtonSelectEvent>
+) {
+    // the menu handles its own buttons
+    if *game_state.current() == GameState::Menu {
+        return;
+    }
+
+    for (interaction, mut color, children) in &mut interaction_query {
+        let text = match text_query.get(children[0]) {
+            Ok(text) => text,
+            Err(_) => continue
+        };
+        match *interaction {
+            Interaction::Hovered => {
+                *color = BUTTON_HOVERED.into();
+            },
+            Interaction::None => {
+                *color = BUTTON_BACKGROUND.into();
+            },
+            Interaction::Clicked => {
+                let current = game_state.current().clone();
+                let human_to_move = current == GameState::Input || current == GameState::RestrictedInput;
+                match text.as_str() {
+                    "SAVE" => {
+                        select_event.send(ButtonSelectEvent);
+                        save_writer.send(SaveGameEvent);
+                        for mut message in message_query.iter_mut() {
+                            message.sections[0].value = String::from("Game saved");
+                        }
+                    },
+                    "RESIGN" if human_to_move => {
+                        select_event.send(ButtonSelectEvent);
+                        info!("{:?} resigned", checkers_state.turn);
+                        game_over_writer.send(GameOverEvent { winner: Some(checkers_state.turn.opponent()), reason: GameOverReason::Resignation });
+                        game_state.set(GameState::GameOver).unwrap();
+                    },
+                    // draws can't be offered in the middle of a multi jump
+                    "DRAW" if current == GameState::Input && decision_query.is_empty() => {
+                        select_event.send(ButtonSelectEvent);
+                        if ai_status.enabled {
+                            let state = checkers_state.clone();
+                            let contempt = ai_status.contempt;
+                            let task = AsyncComputeTaskPool::get().spawn(async move {
+                                return accepts_draw(&state, state.turn.opponent(), contempt);
+                            });
+                            commands.spawn(DrawDecision { task, turns: game_record.turns.len() });
+                            for mut message in message_query.iter_mut() {
+                                message.sections[0].value = String::from("Considering the draw");
+                            }
+                        } else {
+                            draw_offer.from = checkers_state.turn;
+                            draw_offer.return_state = current;
+                            game_state.set(GameState::DrawOffer).unwrap();
+                        }
+                    },
+                    "ACCEPT" if current == GameState::DrawOffer => {
+                        select_event.send(ButtonSelectEvent);
+                        game_over_writer.send(GameOverEvent { winner: None, reason: GameOverReason::AgreedDraw });
+                        game_state.set(GameState::GameOver).unwrap();
+                    },
+                    "DECLINE" if current == GameState::DrawOffer => {
+                        select_event.send(ButtonSelectEvent);
+                        game_state.set(draw_offer.return_state.clone()).unwrap();
+                    },
+                    // same ballot again, whoever played Black now plays Red and still gives any handicap
+                    "REMATCH" if current == GameState::GameOver => {
+                        select_event.send(ButtonSelectEvent);
+                        ai_status.color = ai_status.color.opponent();
+                        *checkers_state = CheckersState::new(checkers_state.rules);
+                        possible_moves.moves = None;
+                        start.handicap = start.handicap.map(|h| Handicap { giver: h.giver.opponent(), ..h });
+                        start.pending = true;
+                        game_state.set(GameState::BoardSetup).unwrap();
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+
+fn draw_decision_system(
+    mut commands: Commands,
+    mut decision_query: Query<(Entity, &mut DrawDecision)>,
+    mut message_query: Query<&mut Text, With<ActionMessage>>,
+    mut game_state: ResMut<State<GameState>>,
+    game_record: Res<GameRecord>,
+    mut game_over_writer: EventWriter<GameOverEvent>
+) {
+    for (entity, mut decision) in &mut decision_query {
+        let accepted = match future::block_on(future::poll_once(&mut decision.task)) {
+            Some(accepted) => accepted,
+            None => continue
+        };
+        commands.entity(entity).despawn();
+        // moving withdraws the offer
+        let still_open = *game_state.current() == GameState::Input && game_record.turns.len() == decision.turns && game_record.result.is_none();
+        for mut message in message_query.iter_mut() {
+            message.sections[0].value = String::from(match (still_open, accepted) {
+                (false, _) => "",
+                (true, true) => "Draw accepted",
+                (true, false) => "Draw declined"
+            });
+        }
+        if still_open && accepted {
+            game_over_writer.send(GameOverEvent { winner: None, reason: GameOverReason::AgreedDraw });
+            game_state.set(GameState::GameOver).unwrap();
+        }
+    }
+}
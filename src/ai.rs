@@ -1,6 +1,7 @@
 use bevy::{prelude::*, tasks::AsyncComputeTaskPool, tasks::Task};
-use crate::{logic::{Move, Position}, state::{GameState, CheckersState, PieceType, PieceColor}, checkers_events::TryMoveEvent, alphabeta::{minimax_alpha_beta, TwoPlayerGameState}};
-use std::{collections::VecDeque};
+use crate::{logic::{Move, Position}, state::{GameState, CheckersState, PieceType, PieceColor}, checkers_events::TryMoveEvent, alphabeta::{minimax_alpha_beta, iterative_deepening, TwoPlayerGameState, SearchLimit}, mcts::{monte_carlo_tree_search, MctsResult}, notation::{moves_to_string, to_fen}, network::{Network, network_path}, clock::GameClock, controller::AIController, save::save_dir, tablebase::{probe, tablebase_move, TablebaseResult}};
+use std::{collections::VecDeque, fs, path::{Path, PathBuf}, sync::{Arc, OnceLock}, time::{Duration, Instant}};
+use serde::{Serialize, Deserialize};
 use futures_lite::future;
 
 
@@ -11,17 +12,54 @@ pub struct CheckersAIPlugin;
 impl Plugin for CheckersAIPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(AIMoves{moves: VecDeque::<Move>::new()})
-        .insert_resource(AIStatus{enabled: true})
+        .insert_resource(AIStatus{enabled: true, contempt: contempt_from_args(), color: PieceColor::Red})
+        .insert_resource(AIController::from_args())
         .add_system_set(SystemSet::on_enter(GameState::AIMove).with_system(queue_compute_move))
         .add_system_set(SystemSet::on_update(GameState::AIMove).with_system(make_ai_move.after(add_ai_move)))
-        .add_system_set(SystemSet::on_update(GameState::AIMove).with_system(add_ai_move));
+        .add_system_set(SystemSet::on_update(GameState::AIMove).with_system(add_ai_move))
+        // a move still being worked out belongs to the game that just ended
+        .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(cancel_ai_moves))
+        .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(cancel_ai_moves))
+        .add_system_set(SystemSet::on_enter(GameState::BoardSetup).with_system(cancel_ai_moves));
     }
 }
 
 
 #[derive(Resource)]
 pub struct AIStatus {
-    pub enabled: bool
+    pub enabled: bool,
+    // How much worse than equal the AI must think it is before it takes a draw
+    pub contempt: f32,
+    // side the AI plays, players swap sides in the second game of a ballot
+    pub color: PieceColor
+}
+
+
+// Contempt values the menu cycles through, a man is worth 50
+const CONTEMPT_PRESETS: [f32; 4] = [0., 25., 50., -25.];
+
+
+impl AIStatus {
+    pub fn next_contempt(&self) -> f32 {
+        let idx = CONTEMPT_PRESETS.iter().position(|&c| c == self.contempt).map_or(0, |i| i + 1);
+        return CONTEMPT_PRESETS[idx % CONTEMPT_PRESETS.len()];
+    }
+
+    pub fn contempt_label(&self) -> String {
+        return format!("Draw Contempt: {}", self.contempt);
+    }
+}
+
+
+// Set how far behind the AI has to be to take a draw with --contempt <score>
+fn contempt_from_args() -> f32 {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(idx) = args.iter().position(|a| a == "--contempt") {
+        if let Some(contempt) = args.get(idx + 1).and_then(|c| c.parse().ok()) {
+            return contempt;
+        }
+    }
+    return 0.;
 }
 
 
@@ -32,19 +70,149 @@ struct AIMoves {
 
 #[derive(Component)]
 struct ComputeMove {
-    task: Task<Vec<Move>>
+    task: Task<Vec<Move>>,
+    // position the move is for, a result for any other position is dropped
+    fen: String
+}
+
+
+// Values the evaluation gives to material and king placement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EvalWeights {
+    pub man: f32,
+    pub king: f32,
+    pub king_middle: f32
+}
+
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        return EvalWeights { man: 50., king: 75., king_middle: 2. };
+    }
+}
+
+
+const WEIGHTS_FILE: &str = "weights.json";
+static LOADED_WEIGHTS: OnceLock<EvalWeights> = OnceLock::new();
+
+
+impl EvalWeights {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        return serde_json::from_str(&text).map_err(|e| e.to_string());
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        return fs::write(path, text).map_err(|e| e.to_string());
+    }
+
+    // Weights written by the tuner if there are any, read once and used by every new position
+    pub fn loaded() -> Self {
+        return *LOADED_WEIGHTS.get_or_init(|| {
+            match EvalWeights::load(&weights_path()) {
+                Ok(weights) => {
+                    info!("Using evaluation weights from {:?}", weights_path());
+                    weights
+                },
+                Err(_) => EvalWeights::default()
+            }
+        });
+    }
 }
 
 
-fn queue_compute_move(mut commands: Commands, ai_moves: Res<AIMoves>, checkers_state: Res<CheckersState>){
+pub fn weights_path() -> PathBuf {
+    return save_dir().join(WEIGHTS_FILE);
+}
+
+
+// Scores a position that is still being played for one side, the search handles won and lost positions
+pub trait Evaluator: Send + Sync {
+    fn name(&self) -> String;
+    fn evaluate(&self, state: &CheckersState, turn: PieceColor) -> f32;
+}
+
+
+impl Evaluator for EvalWeights {
+    fn name(&self) -> String {
+        return String::from("handcrafted");
+    }
+
+    fn evaluate(&self, state: &CheckersState, turn: PieceColor) -> f32 {
+        let features = eval_features(state, turn);
+        let mut score = 0.;
+        score += features.men * self.man;
+        score += features.kings * self.king;
+
+        if state.rules.giveaway {
+            score = -score;
+        }
+
+        score += features.kings_middle * self.king_middle;
+        return score;
+    }
+}
+
+
+static DEFAULT_EVALUATOR: OnceLock<Arc<dyn Evaluator>> = OnceLock::new();
+
+
+// Evaluator new positions start with, the trained network with --nn-eval or the handcrafted weights
+pub fn default_evaluator() -> Arc<dyn Evaluator> {
+    return DEFAULT_EVALUATOR.get_or_init(|| {
+        if std::env::args().any(|a| a == "--nn-eval") {
+            match Network::load(&network_path()) {
+                Ok(network) => {
+                    info!("Using the evaluation network from {:?}", network_path());
+                    return Arc::new(network);
+                },
+                Err(e) => info!("Could not load {:?}: {}", network_path(), e)
+            }
+        }
+        return Arc::new(EvalWeights::loaded());
+    }).clone();
+}
+
+
+const AI_MAX_DEPTH: u32 = 10;
+// Time the AI takes for a move when the game isn't on the clock
+const UNTIMED_MOVE_TIME: Duration = Duration::from_secs(3);
+const DRAW_OFFER_DEPTH: u32 = 6;
+// Playouts the tree search runs for a move when there is no clock
+pub const MCTS_PLAYOUTS: u32 = 20000;
+// Score of a tablebase win, less the plies it takes so quicker wins rank higher
+pub const TABLEBASE_WIN: f32 = 10000.;
+
+
+// Larger boards have many more moves to look at, so search them less deep
+fn max_depth(state: &CheckersState) -> u32 {
+    let extra = state.board.len().saturating_sub(8) as u32;
+    return AI_MAX_DEPTH.saturating_sub(extra).max(4);
+}
+
+
+fn queue_compute_move(mut commands: Commands, ai_moves: Res<AIMoves>, checkers_state: Res<CheckersState>, clock: Res<GameClock>, controller: Res<AIController>){
     if ai_moves.moves.len() == 0 {
         info!("Queueing up compute move");
         let pool = AsyncComputeTaskPool::get();
         let state_clone = checkers_state.clone();
+        let budget = clock.ai_budget(checkers_state.turn);
+        let controller = controller.0.clone();
         let task: Task<Vec<Move>> = pool.spawn(async move {
-            find_best_moves(&state_clone)
+            match controller.choose_moves(&state_clone, budget) {
+                Ok(moves) => moves,
+                Err(e) => {
+                    // the game goes on with the built in search rather than getting stuck
+                    info!("{} failed to move: {}", controller.name(), e);
+                    find_best_moves(&state_clone, budget)
+                }
+            }
         });
-        commands.spawn(ComputeMove{task});
+        commands.spawn(ComputeMove{task, fen: to_fen(&checkers_state)});
     }
 }
 
@@ -52,20 +220,35 @@ fn queue_compute_move(mut commands: Commands, ai_moves: Res<AIMoves>, checkers_s
 fn add_ai_move(
     mut commands: Commands,
     mut compute_tasks: Query<(Entity, &mut ComputeMove)>,
-    mut ai_moves: ResMut<AIMoves>
+    mut ai_moves: ResMut<AIMoves>,
+    checkers_state: Res<CheckersState>
 ){
     for (entity, mut compute_task) in &mut compute_tasks{
+        if compute_task.fen != to_fen(&checkers_state) {
+            info!("Dropping a move computed for another position");
+            commands.entity(entity).despawn();
+            continue;
+        }
         if let Some(best_moves) = future::block_on(future::poll_once(&mut compute_task.task)){
             info!("Move computed");
             for m in best_moves {
                 ai_moves.moves.push_back(m);
             }
-            commands.entity(entity).remove::<ComputeMove>();
+            commands.entity(entity).despawn();
         }
     }
 }
 
 
+// Forgets moves being worked out or waiting to be played, they were for a game that is over
+fn cancel_ai_moves(mut commands: Commands, compute_tasks: Query<Entity, With<ComputeMove>>, mut ai_moves: ResMut<AIMoves>) {
+    for entity in compute_tasks.iter() {
+        commands.entity(entity).despawn();
+    }
+    ai_moves.moves.clear();
+}
+
+
 fn make_ai_move(
     mut ai_moves: ResMut<AIMoves>,
     mut trymove_writer: EventWriter<TryMoveEvent>,
@@ -81,43 +264,112 @@ fn make_ai_move(
 }
 
 
-fn find_best_moves(state: &CheckersState) -> Vec<Move>{
-    let (_, best_move) = minimax_alpha_beta(state, 10, f32::NEG_INFINITY, f32::INFINITY, true, &state.turn);
+// Deepens until the budget is spent, a few seconds when the game isn't on the clock
+pub fn find_best_moves(state: &CheckersState, budget: Option<Duration>) -> Vec<Move>{
+    return find_best_moves_to_depth(state, max_depth(state), Some(budget.unwrap_or(UNTIMED_MOVE_TIME)));
+}
+
+
+// Without a budget the search runs to the full depth, the tournament compares engines that way
+pub fn find_best_moves_to_depth(state: &CheckersState, depth: u32, budget: Option<Duration>) -> Vec<Move>{
+    if let Some(best_move) = tablebase_move(state) {
+        info!("Playing {} from the tablebase", moves_to_string(state, &best_move));
+        return best_move;
+    }
+    let (_, best_move) = match budget {
+        Some(budget) => iterative_deepening(state, depth, Instant::now() + budget, &state.turn),
+        None => minimax_alpha_beta(state, depth, f32::NEG_INFINITY, f32::INFINITY, true, &state.turn)
+    };
     return best_move.unwrap();
 }
 
 
-struct JumpNode {
-    game_move: Option<Move>,
-    children: Option<Vec<JumpNode>>
+// Monte Carlo tree search for the side to move, the playouts only count when there is no budget
+pub fn mcts_search(state: &CheckersState, budget: Option<Duration>, playouts: u32) -> MctsResult<Vec<Move>> {
+    let limit = SearchLimit { deadline: budget.map(|b| Instant::now() + b), stop: None };
+    let max_playouts = if budget.is_none() { Some(playouts) } else { None };
+    return monte_carlo_tree_search(state, &limit, max_playouts, &state.turn);
+}
+
+
+// The most visited root moves with their visit counts, e.g. "11-15 412, 9-13 300"
+pub fn visits_to_string(state: &CheckersState, result: &MctsResult<Vec<Move>>, count: usize) -> String {
+    return result.root_moves.iter().take(count).map(|root_move| {
+        return format!("{} {}", moves_to_string(state, &root_move.game_move), root_move.visits);
+    }).collect::<Vec<String>>().join(", ");
+}
+
+
+pub fn find_best_moves_mcts(state: &CheckersState, budget: Option<Duration>, playouts: u32) -> Vec<Move>{
+    return find_best_moves_mcts_with(state, budget, playouts, |result| {
+        for root_move in result.root_moves.iter() {
+            info!("{} visited {} times, value {:.3}", moves_to_string(state, &root_move.game_move), root_move.visits, root_move.value);
+        }
+        info!("{} playouts", result.playouts);
+    });
 }
 
-impl JumpNode {
-    fn new(game_move: Move) -> Self {
-        JumpNode { game_move: Some(game_move), children: None }
+
+// Tree search that hands the root moves to report before playing the most visited one
+pub fn find_best_moves_mcts_with<F: FnOnce(&MctsResult<Vec<Move>>)>(state: &CheckersState, budget: Option<Duration>, playouts: u32, report: F) -> Vec<Move>{
+    if let Some(best_move) = tablebase_move(state) {
+        info!("Playing {} from the tablebase", moves_to_string(state, &best_move));
+        return best_move;
     }
+    let result = mcts_search(state, budget, playouts);
+    report(&result);
+    return result.best_move.unwrap();
+}
 
-    fn build_jump_tree(checkers_state: &CheckersState) -> Self {
-        let mut root = JumpNode{game_move: None, children: None};
-        let possible_captures = checkers_state.possible_captures();
-        root.children = Self::build_tree_helper(checkers_state, &possible_captures);
-        return root;
+
+// Evaluation and best move for the side to move, used by analysis mode
+pub fn analyse(state: &CheckersState, budget: Duration) -> (f32, Vec<Move>) {
+    if state.is_game_over() {
+        return (state.score_state(&state.turn), Vec::new());
     }
+    let (score, best_move) = iterative_deepening(state, max_depth(state), Instant::now() + budget, &state.turn);
+    return (score, best_move.unwrap_or_default());
+}
+
+
+// Decide on a draw offer with a shallow search from the AI's point of view
+pub fn accepts_draw(state: &CheckersState, ai_player: PieceColor, contempt: f32) -> bool {
+    let (score, _) = minimax_alpha_beta(state, DRAW_OFFER_DEPTH, f32::NEG_INFINITY, f32::INFINITY, state.turn == ai_player, &ai_player);
+    info!("Draw offer evaluation {} with contempt {}", score, contempt);
+    return score < -contempt;
+}
+
 
-    fn build_tree_helper(checkers_state: &CheckersState, next_capture_moves: &Vec<Move>) -> Option<Vec<JumpNode>> {
-        if next_capture_moves.len() > 0 {
-            let mut children = Vec::new();
-            for m in next_capture_moves.iter() {
-                let mut node = JumpNode::new(*m);
-                let mut next_state = checkers_state.clone();
-                let (_, _, next_jumps) = next_state.update_with_move(m);
-                node.children = Self::build_tree_helper(&next_state, &next_jumps);
-                children.push(node);
+// Differences between the side's pieces and the opponent's that the evaluation weighs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvalFeatures {
+    pub men: f32,
+    pub kings: f32,
+    pub kings_middle: f32
+}
+
+
+pub fn eval_features(state: &CheckersState, turn: PieceColor) -> EvalFeatures {
+    let mut features = EvalFeatures::default();
+    for row in 0..state.board.len(){
+        for col in 0..state.board.len(){
+            if let Some(piece) = state.board[row][col]{
+                let sign = if piece.col == turn { 1. } else { -1. };
+                match piece.typ {
+                    PieceType::Man => {
+                        features.men += sign;
+                    }
+                    PieceType::King => {
+                        features.kings += sign;
+                        if state.is_in_middle(Position::new(row, col)){
+                            features.kings_middle += sign;
+                        }
+                    }
+                }
             }
-            return Some(children);
         }
-        return None
     }
+    return features;
 }
 
 
@@ -127,56 +379,9 @@ impl TwoPlayerGameState for CheckersState {
     type Player = PieceColor;
     
 
+    // Move generation follows the variant rules set in the state
     fn get_possible_moves(&self) -> Vec<Self::GameMove>{
-        let mut move_vectors = Vec::<Self::GameMove>::new();
-
-        // create a tree of jump nodes
-        let tree = JumpNode::build_jump_tree(self);
-        let mut current = VecDeque::<(&JumpNode, Option<Vec<Move>>)>::new();
-        current.push_front((&tree, None));
-        while current.len() > 0 {
-            while let Some(val) = current.pop_front(){
-                let (node, path) = val;
-
-                if let Some(ref children) = node.children {
-                    for child_node in children.iter(){
-                        let mut new_path = match path {
-                            Some(ref p) => p.clone(),
-                            None => Vec::new()
-                        };
-                        new_path.push(child_node.game_move.unwrap());
-                        current.push_front((child_node, Some(new_path)));
-                    }
-                } else {
-                    if let Some(p) = path {
-                        let mut v = Vec::<Move>::with_capacity(p.len());
-                        for m in p.iter() {
-                            v.push(*m);
-                        }
-                        move_vectors.push(v);
-                    }
-                }
-            }
-        }
-
-        // if no captures are possible
-        if move_vectors.len() == 0 {
-            for row in 0..self.board.len(){
-                for col in 0..self.board.len(){
-                    if let Some(piece) = self.board[row][col] {
-                        if piece.col == self.turn {
-                            let valid_step_moves = self.valid_steps(&Position::new(row, col), self.turn);
-                            for step_move in valid_step_moves {
-                                let mut v = Vec::<Move>::with_capacity(1);
-                                v.push(step_move);
-                                move_vectors.push(v);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        return move_vectors;
+        return self.legal_sequences();
     }
 
 
@@ -189,70 +394,74 @@ impl TwoPlayerGameState for CheckersState {
     }
 
 
+    fn player_to_move(&self) -> Self::Player {
+        return self.turn;
+    }
+
+
     fn score_state(&self, turn: &Self::Player) -> f32 {
-        let mut my_men = 0.;
-        let mut my_kings = 0.;
-        let mut opp_men = 0.;
-        let mut opp_kings = 0.;
-        let mut opp_kings_middle = 0.;
-        let mut my_kings_middle = 0.;
-
-
-        for row in 0..self.board.len(){
-            for col in 0..self.board.len(){
-                if let Some(piece) =  self.board[row][col]{
-                    if *turn == piece.col {
-                        match piece.typ {
-                            PieceType::Man => {
-                                my_men += 1.;
-                            }
-                            PieceType::King => {
-                                my_kings += 1.;
-                                if self.is_in_middle(Position::new(row, col)){
-                                    my_kings_middle += 1.;
-                                }
-                            }
-                        }
-                    } else {
-                        match piece.typ {
-                            PieceType::Man => {
-                                opp_men += 1.;
-                            }
-                            PieceType::King => {
-                                opp_kings += 1.;
-                                if self.is_in_middle(Position::new(row, col)){
-                                    opp_kings_middle += 1.;
-                                }
-                            }
-                        }
-                    }
-                }
+        // getting blocked is the usual way to win a giveaway game, material doesn't show it
+        if self.rules.giveaway {
+            match self.get_winner() {
+                Some(winner) if winner == *turn => return f32::MAX,
+                Some(_) => return f32::MIN,
+                None => {}
             }
         }
-
-        let my_pieces = my_men + my_kings;
-        let opp_pieces = opp_men + opp_kings;
-
-        if my_pieces as i32 == 0 {
-            return f32::MIN;
-        } else if opp_pieces as i32 == 0 {
-            return f32::MAX;
+        // giving all the pieces away wins
+        if self.count_pieces(*turn) == 0 {
+            return if self.rules.giveaway { f32::MAX } else { f32::MIN };
+        } else if self.count_pieces(turn.opponent()) == 0 {
+            return if self.rules.giveaway { f32::MIN } else { f32::MAX };
+        }
+        if let Some(result) = probe(self) {
+            let score = match result {
+                TablebaseResult::Win(plies) => TABLEBASE_WIN - plies as f32,
+                TablebaseResult::Loss(plies) => plies as f32 - TABLEBASE_WIN,
+                TablebaseResult::Draw => 0.
+            };
+            return if self.turn == *turn { score } else { -score };
         }
-        
-        let mut score = 0.;
-        score += my_men * 50.;
-        score += my_kings * 75.;
-
-        score -= opp_men * 50.;
-        score -= opp_kings * 75.;
-
-        score += my_kings_middle * 2.;
-        score -= opp_kings_middle * 2.;
 
-        return score;
+        return self.evaluator.evaluate(self, *turn);
     }
     
     fn is_game_over(&self) -> bool {
         return self.get_winner().is_some();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rules::Variant, notation::from_fen};
+
+    #[test]
+    fn draw_taken_only_when_behind() {
+        let rules = Variant::American.rules();
+        // Black offers with three men against one
+        let ahead = from_fen("B:W32:B1,2,3", rules).unwrap();
+        assert!(accepts_draw(&ahead, PieceColor::Red, 0.));
+        assert!(!accepts_draw(&ahead, PieceColor::Black, 0.));
+        // a level position is only taken by an AI that wants the draw
+        let level = from_fen("B:W30:B3", rules).unwrap();
+        assert!(!accepts_draw(&level, PieceColor::Red, 0.));
+        assert!(accepts_draw(&level, PieceColor::Red, -25.));
+        // contempt makes the AI play on a man down
+        let man_down = from_fen("B:W30:B2,3", rules).unwrap();
+        assert!(accepts_draw(&man_down, PieceColor::Red, 25.));
+        assert!(!accepts_draw(&man_down, PieceColor::Red, 75.));
+    }
+
+    #[test]
+    fn contempt_presets_cycle() {
+        let mut status = AIStatus { enabled: true, contempt: 0., color: PieceColor::Red };
+        for _ in 0..CONTEMPT_PRESETS.len() {
+            status.contempt = status.next_contempt();
+        }
+        assert_eq!(status.contempt, 0.);
+        // a value from the command line goes back to the first preset
+        status.contempt = 10.;
+        assert_eq!(status.next_contempt(), 0.);
+    }
+}
@@ -1,3 +1,6 @@
+use std::{time::Instant, sync::atomic::{AtomicBool, Ordering}};
+
+
 pub trait TwoPlayerGameState {
     type GameState: TwoPlayerGameState;
     type GameMove;
@@ -7,17 +10,81 @@ pub trait TwoPlayerGameState {
     fn next_state_with_move(&self, m: &Self::GameMove) -> Self::GameState;
     fn score_state(&self, turn: &Self::Player) -> f32;
     fn is_game_over(&self) -> bool;
+    // Turns don't always alternate, a handicap can give one side two moves in a row
+    fn player_to_move(&self) -> Self::Player;
 }
 
 
 pub fn minimax_alpha_beta<S>(state: &S, depth: u32, alpha: f32, beta: f32, is_maximizing: bool, player: &S::Player) -> (f32, Option<S::GameMove>)
     where S: TwoPlayerGameState<GameState=S>
+{
+    return minimax_alpha_beta_until(state, depth, alpha, beta, is_maximizing, player, &SearchLimit::default()).unwrap();
+}
+
+
+// When a search has to give up, at a deadline or when another thread asks it to stop
+#[derive(Clone, Copy, Default)]
+pub struct SearchLimit<'a> {
+    pub deadline: Option<Instant>,
+    pub stop: Option<&'a AtomicBool>
+}
+
+
+impl<'a> SearchLimit<'a> {
+    pub fn reached(&self) -> bool {
+        if let Some(deadline) = self.deadline {
+            if Instant::now() > deadline {
+                return true;
+            }
+        }
+        if let Some(stop) = self.stop {
+            return stop.load(Ordering::Relaxed);
+        }
+        return false;
+    }
+}
+
+
+// Searches deeper until the deadline passes, returns the result of the last completed depth
+pub fn iterative_deepening<S>(state: &S, max_depth: u32, deadline: Instant, player: &S::Player) -> (f32, Option<S::GameMove>)
+    where S: TwoPlayerGameState<GameState=S>
+{
+    let limit = SearchLimit { deadline: Some(deadline), stop: None };
+    return iterative_deepening_with(state, max_depth, &limit, player, |_, _, _| {});
+}
+
+
+// Same as iterative_deepening, reporting the depth, score and best move after every completed depth
+pub fn iterative_deepening_with<S, F>(state: &S, max_depth: u32, limit: &SearchLimit, player: &S::Player, mut report: F) -> (f32, Option<S::GameMove>)
+    where S: TwoPlayerGameState<GameState=S>, F: FnMut(u32, f32, &Option<S::GameMove>)
+{
+    // first depth always completes so there is a move to play
+    let mut result = minimax_alpha_beta(state, 1, f32::NEG_INFINITY, f32::INFINITY, true, player);
+    report(1, result.0, &result.1);
+    for depth in 2..(max_depth + 1) {
+        match minimax_alpha_beta_until(state, depth, f32::NEG_INFINITY, f32::INFINITY, true, player, limit) {
+            Some(r) => result = r,
+            None => break
+        }
+        report(depth, result.0, &result.1);
+    }
+    return result;
+}
+
+
+// Returns None if the limit was reached before the search completed
+pub fn minimax_alpha_beta_until<S>(state: &S, depth: u32, alpha: f32, beta: f32, is_maximizing: bool, player: &S::Player, limit: &SearchLimit) -> Option<(f32, Option<S::GameMove>)>
+    where S: TwoPlayerGameState<GameState=S>
 {
     let mut a = alpha;
     let mut b = beta;
 
     if depth == 0 || state.is_game_over(){
-        return (state.score_state(player), None);
+        return Some((state.score_state(player), None));
+    }
+
+    if limit.reached() {
+        return None;
     }
 
 
@@ -28,7 +95,7 @@ pub fn minimax_alpha_beta<S>(state: &S, depth: u32, alpha: f32, beta: f32, is_ma
         for m in state.get_possible_moves(){
             let next_state = state.next_state_with_move(&m);
 
-            let (score, _) = minimax_alpha_beta(&next_state, depth - 1, a, b, false, player);
+            let (score, _) = minimax_alpha_beta_until(&next_state, depth - 1, a, b, false, player, limit)?;
 
             if score > max_score  {
                 max_score = score;
@@ -43,13 +110,13 @@ pub fn minimax_alpha_beta<S>(state: &S, depth: u32, alpha: f32, beta: f32, is_ma
                 break;
             }
         }
-        return (max_score, best_move);
+        return Some((max_score, best_move));
     } else {
         let mut best_move: Option<S::GameMove> = None;
         let mut min_score = f32::INFINITY;
         for m in state.get_possible_moves(){
             let next_state = state.next_state_with_move(&m);
-            let (score, _) = minimax_alpha_beta(&next_state, depth - 1, a, b, true, player);
+            let (score, _) = minimax_alpha_beta_until(&next_state, depth - 1, a, b, true, player, limit)?;
             if score < min_score {
                 min_score = score;
                 best_move = Some(m)
@@ -63,6 +130,6 @@ pub fn minimax_alpha_beta<S>(state: &S, depth: u32, alpha: f32, beta: f32, is_ma
                 break;
             }
         }
-        return (min_score, best_move);
+        return Some((min_score, best_move));
     }
 }
\ No newline at end of file
@@ -0,0 +1,148 @@
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool, tasks::Task};
+use futures_lite::future;
+use std::time::Duration;
+use crate::{
+    state::{GameState, CheckersState, PieceColor},
+    logic::Move,
+    ai::{analyse, mcts_search, visits_to_string, MCTS_PLAYOUTS},
+    notation::moves_to_string,
+    tablebase::{probe, TablebaseResult},
+    controller::AIController
+};
+
+
+pub struct CheckersAnalysisPlugin;
+
+
+impl Plugin for CheckersAnalysisPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(AnalysisMode { enabled: false })
+        .add_system_set(SystemSet::on_enter(GameState::BoardSetup).with_system(setup_analysis_display))
+        .add_system(queue_analysis)
+        .add_system(show_analysis);
+    }
+}
+
+
+// Time spent analysing each position
+const ANALYSIS_BUDGET: Duration = Duration::from_millis(1500);
+// Root moves of the tree search shown with their visit counts
+const ANALYSIS_VISITS: usize = 5;
+
+
+// Both sides are played by hand while the engine evaluates every position
+#[derive(Resource)]
+pub struct AnalysisMode {
+    pub enabled: bool
+}
+
+
+#[derive(Component)]
+struct AnalysisDisplay;
+
+
+#[derive(Component)]
+struct ComputeAnalysis {
+    // evaluation, best move and the tree search's most visited moves when the AI plays with it
+    task: Task<(f32, Vec<Move>, Option<String>)>,
+    state: CheckersState
+}
+
+
+fn setup_analysis_display(mut commands: Commands, asset_server: Res<AssetServer>, analysis: Res<AnalysisMode>, query: Query<Entity, With<AnalysisDisplay>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !analysis.enabled {
+        return;
+    }
+    commands.spawn(
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/Pixeboy.ttf"),
+                font_size: 40.0,
+                color: Color::rgb(0.8, 0.8, 0.8)
+            }
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { top: Val::Px(20.), left: Val::Px(20.), ..default() },
+            ..default()
+        })
+    ).insert(AnalysisDisplay);
+}
+
+
+fn queue_analysis(
+    mut commands: Commands,
+    analysis: Res<AnalysisMode>,
+    checkers_state: Res<CheckersState>,
+    game_state: Res<State<GameState>>,
+    controller: Res<AIController>,
+    running: Query<Entity, With<ComputeAnalysis>>
+){
+    if !analysis.enabled || !checkers_state.is_changed() {
+        return;
+    }
+    match game_state.current() {
+        GameState::Input | GameState::RestrictedInput | GameState::Move | GameState::Animating => {},
+        _ => return
+    }
+    // a newer position makes older results useless
+    for entity in running.iter() {
+        commands.entity(entity).despawn();
+    }
+    let pool = AsyncComputeTaskPool::get();
+    let state_clone = checkers_state.clone();
+    let mcts = controller.0.search_settings().map_or(false, |settings| settings.mcts);
+    let task = pool.spawn(async move {
+        let (score, best) = analyse(&state_clone, ANALYSIS_BUDGET);
+        let visits = match mcts {
+            true => Some(visits_to_string(&state_clone, &mcts_search(&state_clone, Some(ANALYSIS_BUDGET), MCTS_PLAYOUTS), ANALYSIS_VISITS)),
+            false => None
+        };
+        (score, best, visits)
+    });
+    commands.spawn(ComputeAnalysis { task, state: checkers_state.clone() });
+}
+
+
+fn show_analysis(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut ComputeAnalysis)>,
+    mut display_query: Query<&mut Text, With<AnalysisDisplay>>
+){
+    for (entity, mut compute) in &mut tasks {
+        if let Some((score, best, visits)) = future::block_on(future::poll_once(&mut compute.task)) {
+            // scores come from the side to move, show them from Black's side
+            let black_score = match compute.state.turn {
+                PieceColor::Black => score,
+                PieceColor::Red => -score
+            };
+            let eval = if black_score >= f32::MAX {
+                String::from("Black wins")
+            } else if black_score <= f32::MIN {
+                String::from("Red wins")
+            } else {
+                format!("{:+.2}", black_score / 100.)
+            };
+            let side_to_move = compute.state.turn;
+            let tablebase = match probe(&compute.state) {
+                Some(TablebaseResult::Win(plies)) => format!("   Tablebase: {:?} wins in {} plies", side_to_move, plies),
+                Some(TablebaseResult::Loss(plies)) => format!("   Tablebase: {:?} wins in {} plies", side_to_move.opponent(), plies),
+                Some(TablebaseResult::Draw) => String::from("   Tablebase: draw"),
+                None => String::new()
+            };
+            let visits = match visits {
+                Some(visits) => format!("\nVisits {}", visits),
+                None => String::new()
+            };
+            for mut text in display_query.iter_mut() {
+                text.sections[0].value = format!("Eval {}   Best {}{}{}", eval, moves_to_string(&compute.state, &best), tablebase, visits);
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use bevy::prelude::*;
+use std::{fs, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+use crate::{
+    state::CheckersState,
+    rules::Variant,
+    logic::Move,
+    record::GameRecord,
+    notation::{resolve_move, moves_to_string, to_fen},
+    save::save_dir,
+    alphabeta::TwoPlayerGameState
+};
+
+
+pub struct CheckersBallotPlugin;
+
+
+impl Plugin for CheckersBallotPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Ballot { mode: ballot_from_args(), opening: None });
+    }
+}
+
+
+// Number of turns in a ballot opening, one move each for Black, Red and Black again
+const BALLOT_TURNS: usize = 3;
+const DECK_FILE: &str = "ballots.txt";
+// Three-move openings from the American start with the ones that lose a man left out
+const AMERICAN_DECK: &str = include_str!("../assets/ballots_american.txt");
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BallotMode {
+    Off,
+    Random,
+    // 1 based number of the opening in the deck
+    Fixed(usize)
+}
+
+
+impl BallotMode {
+    pub fn next(&self) -> BallotMode {
+        match self {
+            BallotMode::Off => BallotMode::Random,
+            BallotMode::Random => BallotMode::Fixed(1),
+            BallotMode::Fixed(_) => BallotMode::Off
+        }
+    }
+
+    // Steps a selected opening through the deck, back to the first one after the last
+    pub fn next_opening(&self, deck_len: usize) -> BallotMode {
+        match self {
+            BallotMode::Fixed(num) if *num < deck_len => BallotMode::Fixed(num + 1),
+            _ => BallotMode::Fixed(1)
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            BallotMode::Off => String::from("Ballot: Off"),
+            BallotMode::Random => String::from("Ballot: Random"),
+            BallotMode::Fixed(num) => format!("Ballot: #{}", num)
+        }
+    }
+}
+
+
+// Opening played out before the players take over, the same one is used for both games of a pair
+#[derive(Resource)]
+pub struct Ballot {
+    pub mode: BallotMode,
+    pub opening: Option<Vec<Vec<Move>>>
+}
+
+
+// Pick a deck entry with --ballot <n>
+fn ballot_from_args() -> BallotMode {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(idx) = args.iter().position(|a| a == "--ballot") {
+        if let Some(num) = args.get(idx + 1).and_then(|n| n.parse().ok()) {
+            return BallotMode::Fixed(num);
+        }
+    }
+    return BallotMode::Off;
+}
+
+
+// A deck can be dropped in here to replace the built in one, one opening per line, e.g. 11-15 23-19 8-11
+pub fn deck_path() -> PathBuf {
+    return save_dir().join(DECK_FILE);
+}
+
+
+// Reads the openings in a deck, every one has to be playable from the starting position
+pub fn parse_deck(text: &str, start: &CheckersState) -> Result<Vec<Vec<Vec<Move>>>, String> {
+    let mut deck = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut state = start.clone();
+        let mut opening = Vec::new();
+        for token in line.split_whitespace() {
+            let turn = resolve_move(&state, token).map_err(|e| format!("Ballot on line {}: {}", idx + 1, e))?;
+            state = state.next_state_with_move(&turn);
+            opening.push(turn);
+        }
+        if opening.len() != BALLOT_TURNS {
+            return Err(format!("Ballot on line {} has {} moves instead of {}", idx + 1, opening.len(), BALLOT_TURNS));
+        }
+        deck.push(opening);
+    }
+    return Ok(deck);
+}
+
+
+// Every sequence of three turns the rules allow from the starting position
+pub fn generated_deck(start: &CheckersState) -> Vec<Vec<Vec<Move>>> {
+    let mut openings = vec![(start.clone(), Vec::<Vec<Move>>::new())];
+    for _ in 0..BALLOT_TURNS {
+        let mut next = Vec::new();
+        for (state, opening) in openings.iter() {
+            for turn in state.legal_sequences() {
+                let mut longer = opening.clone();
+                longer.push(turn.clone());
+                next.push((state.next_state_with_move(&turn), longer));
+            }
+        }
+        openings = next;
+    }
+    return openings.into_iter().map(|(_, opening)| opening).collect();
+}
+
+
+// A deck in the save folder replaces the built in American one, other rules and starts use every legal opening
+pub fn load_deck(start: &CheckersState) -> Vec<Vec<Vec<Move>>> {
+    let rules = start.rules;
+    let standard = to_fen(start) == to_fen(&CheckersState::new(rules)) && start.passes.is_none();
+    if rules.variant == Variant::American && !rules.giveaway && standard {
+        if let Ok(text) = fs::read_to_string(deck_path()) {
+            match parse_deck(&text, start) {
+                Ok(deck) if deck.len() > 0 => return deck,
+                Ok(_) => info!("No openings in {:?}", deck_path()),
+                Err(e) => info!("Could not use {:?}: {}", deck_path(), e)
+            }
+        }
+        match parse_deck(AMERICAN_DECK, start) {
+            Ok(deck) => return deck,
+            Err(e) => info!("Could not use the built in deck: {}", e)
+        }
+    }
+    return generated_deck(start);
+}
+
+
+// Moves of the selected opening for the menu, the deck depends on the rules being played
+pub fn opening_label(mode: BallotMode, start: &CheckersState) -> String {
+    let num = match mode {
+        BallotMode::Fixed(num) => num,
+        _ => return String::from("Opening: Any")
+    };
+    let deck = load_deck(start);
+    return match deck.get(num.wrapping_sub(1)) {
+        Some(opening) => format!("Opening: {}", opening_to_string(start, opening)),
+        None => format!("Opening: No #{} in {}", num, deck.len())
+    };
+}
+
+
+pub fn opening_to_string(start: &CheckersState, opening: &Vec<Vec<Move>>) -> String {
+    let mut state = start.clone();
+    let mut turns = Vec::new();
+    for turn in opening.iter() {
+        turns.push(moves_to_string(&state, turn));
+        state = state.next_state_with_move(turn);
+    }
+    return turns.join(" ");
+}
+
+
+fn choose_opening(mode: BallotMode, start: &CheckersState) -> Option<Vec<Vec<Move>>> {
+    let deck = load_deck(start);
+    match mode {
+        BallotMode::Off => return None,
+        BallotMode::Fixed(num) => {
+            if num == 0 || num > deck.len() {
+                info!("There is no ballot {}, the deck has {}", num, deck.len());
+                return None;
+            }
+            return Some(deck[num - 1].clone());
+        },
+        BallotMode::Random => {
+            let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos() as usize).unwrap_or(0);
+            return deck.get(seed % deck.len().max(1)).cloned();
+        }
+    }
+}
+
+
+// Plays the ballot out on a new game, the moves are recorded as the first turns of the game
+pub fn play_opening(ballot: &mut Ballot, checkers_state: &mut CheckersState, game_record: &mut GameRecord) {
+    // the second game of a pair reuses the opening of the first
+    if ballot.opening.is_none() {
+        ballot.opening = choose_opening(ballot.mode, checkers_state);
+    }
+    let opening = match ballot.opening {
+        Some(ref opening) => opening.clone(),
+        None => return
+    };
+    info!("Playing ballot {}", opening_to_string(checkers_state, &opening));
+    game_record.tags.push((String::from("Ballot"), opening_to_string(checkers_state, &opening)));
+    for turn in opening.iter() {
+        let player = checkers_state.turn;
+        for (idx, m) in turn.iter().enumerate() {
+            game_record.push_move(player, *m, idx == turn.len() - 1);
+        }
+        *checkers_state = checkers_state.next_state_with_move(turn);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_deck_is_playable() {
+        let start = CheckersState::new(Variant::American.rules());
+        let deck = parse_deck(AMERICAN_DECK, &start).unwrap();
+        assert_eq!(deck.len(), 156);
+        assert!(deck.len() < generated_deck(&start).len());
+        // no two openings reach the same position
+        let mut positions: Vec<String> = deck.iter().map(|opening| {
+            return to_fen(&opening.iter().fold(start.clone(), |state, turn| state.next_state_with_move(turn)));
+        }).collect();
+        positions.sort();
+        positions.dedup();
+        assert_eq!(positions.len(), 156);
+    }
+}
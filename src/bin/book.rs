@@ -0,0 +1,93 @@
+use std::{fs, path::PathBuf, time::Instant};
+use checkers::{
+    rules::Variant,
+    book::{OpeningBook, book_path},
+    notation::parse_pdn
+};
+
+
+// Builds an opening book, run with
+// cargo run --release --bin book -- games.pdn [more.pdn] [--analyse 8] [--plies 12] [--variant American] [--out file]
+// Moves come from the first turns of the games, from engine analysis of the start position or
+// both. The file goes to the save folder by default, where the game finds it.
+
+
+const DEFAULT_PLIES: usize = 12;
+// Analysis branches out from every move kept, so it goes less deep than the games
+const DEFAULT_ANALYSIS_PLIES: usize = 6;
+// Moves this much worse than the best one in analysis are left out of the book
+const DEFAULT_MARGIN: f32 = 10.;
+
+
+struct Settings {
+    files: Vec<String>,
+    variant: Variant,
+    plies: Option<usize>,
+    analysis_depth: Option<u32>,
+    margin: f32,
+    out: Option<PathBuf>
+}
+
+
+fn parse_args() -> Result<Settings, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut settings = Settings { files: Vec::new(), variant: Variant::American, plies: None, analysis_depth: None, margin: DEFAULT_MARGIN, out: None };
+    let mut idx = 0;
+    while idx < args.len() {
+        if !args[idx].starts_with("--") {
+            settings.files.push(args[idx].clone());
+            idx += 1;
+            continue;
+        }
+        let value = args.get(idx + 1).ok_or(format!("{} needs a value", args[idx]))?;
+        match args[idx].as_str() {
+            "--variant" => {
+                settings.variant = *Variant::ALL.iter().find(|v| v.name().eq_ignore_ascii_case(value)).ok_or(format!("Unknown variant {}", value))?;
+            },
+            "--plies" => settings.plies = Some(value.parse().map_err(|_| format!("Invalid number of plies {}", value))?),
+            "--analyse" => settings.analysis_depth = Some(value.parse().map_err(|_| format!("Invalid depth {}", value))?),
+            "--margin" => settings.margin = value.parse().map_err(|_| format!("Invalid margin {}", value))?,
+            "--out" => settings.out = Some(PathBuf::from(value)),
+            arg => return Err(format!("Unknown argument {}", arg))
+        }
+        idx += 2;
+    }
+    if settings.files.len() == 0 && settings.analysis_depth.is_none() {
+        return Err(String::from("Give PDN files of games or an analysis depth with --analyse"));
+    }
+    return Ok(settings);
+}
+
+
+fn main() {
+    let settings = match parse_args() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let rules = settings.variant.rules();
+    let mut book = OpeningBook::new(rules);
+    for file in settings.files.iter() {
+        match fs::read_to_string(file).map_err(|e| e.to_string()).and_then(|text| parse_pdn(&text)) {
+            Ok(games) => {
+                let used = book.add_games(&games, settings.plies.unwrap_or(DEFAULT_PLIES));
+                println!("{}: {} of {} games are finished {} games", file, used, games.len(), settings.variant.name());
+            },
+            Err(e) => println!("Skipping {}: {}", file, e)
+        }
+    }
+    if let Some(depth) = settings.analysis_depth {
+        let started = Instant::now();
+        let searched = book.add_analysis(depth, settings.plies.unwrap_or(DEFAULT_ANALYSIS_PLIES), settings.margin);
+        println!("Analysed {} positions to depth {} in {:.1}s", searched, depth, started.elapsed().as_secs_f32());
+    }
+    let (positions, moves) = book.size();
+    println!("{} moves from {} positions", moves, positions);
+    let out = settings.out.unwrap_or(book_path(&rules));
+    match book.save(&out) {
+        Ok(_) => println!("Book written to {:?}", out),
+        Err(e) => println!("Could not write {:?}: {}", out, e)
+    }
+}
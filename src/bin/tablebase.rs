@@ -0,0 +1,73 @@
+use std::{path::PathBuf, time::Instant};
+use checkers::{
+    rules::Variant,
+    tablebase::{Tablebase, tablebase_path}
+};
+
+
+// Generates endgame tablebases for a variant, run with
+// cargo run --release --bin tablebase -- [--variant American] [--pieces 4] [--out file]
+// The file goes to the save folder by default, where the game and the tournament find it.
+// Tables are written as they are solved and only the ones in use are kept in memory, but each
+// extra piece still takes far longer and more space, on 8x8 the five piece file is about a
+// gigabyte and the six piece one over twenty.
+
+
+const DEFAULT_PIECES: usize = 4;
+const MAX_PIECES: usize = 6;
+
+
+struct Settings {
+    variant: Variant,
+    pieces: usize,
+    out: Option<PathBuf>
+}
+
+
+fn parse_args() -> Result<Settings, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut settings = Settings { variant: Variant::American, pieces: DEFAULT_PIECES, out: None };
+    let mut idx = 0;
+    while idx < args.len() {
+        let value = args.get(idx + 1).ok_or(format!("{} needs a value", args[idx]))?;
+        match args[idx].as_str() {
+            "--variant" => {
+                settings.variant = *Variant::ALL.iter().find(|v| v.name().eq_ignore_ascii_case(value)).ok_or(format!("Unknown variant {}", value))?;
+            },
+            "--pieces" => settings.pieces = value.parse().map_err(|_| format!("Invalid number of pieces {}", value))?,
+            "--out" => settings.out = Some(PathBuf::from(value)),
+            arg => return Err(format!("Unknown argument {}", arg))
+        }
+        idx += 2;
+    }
+    if settings.pieces < 2 || settings.pieces > MAX_PIECES {
+        return Err(format!("Pieces must be between 2 and {}", MAX_PIECES));
+    }
+    return Ok(settings);
+}
+
+
+fn main() {
+    let settings = match parse_args() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let rules = settings.variant.rules();
+    if rules.king_move_limit.is_some() {
+        println!("{} limits king moves, the board alone doesn't say who may move", settings.variant.name());
+        return;
+    }
+    let started = Instant::now();
+    let out = settings.out.unwrap_or(tablebase_path(&rules));
+    let generated = Tablebase::generate(&rules, settings.pieces, &out, |material, counts| {
+        println!("{}: {} wins, {} losses, {} draws for the side to move, longest win {} plies ({:.1}s)",
+            material.label(), counts.wins, counts.losses, counts.draws, counts.longest, started.elapsed().as_secs_f32());
+    });
+    match generated {
+        Ok(_) => println!("Tablebase written to {:?}", out),
+        Err(e) => println!("Could not write {:?}: {}", out, e)
+    }
+}
@@ -0,0 +1,521 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+    sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}, mpsc},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use checkers::{
+    state::{CheckersState, PieceColor},
+    rules::Variant,
+    logic::Move,
+    network::Network,
+    book::OpeningBook,
+    mcts::Random,
+    ai::{EvalWeights, find_best_moves_to_depth, find_best_moves_mcts_with, visits_to_string, MCTS_PLAYOUTS},
+    alphabeta::{minimax_alpha_beta, TwoPlayerGameState},
+    ballot::{load_deck, opening_to_string},
+    record::{GameRecord, GameResult},
+    checkers_events::GameOverReason,
+    notation::to_fen
+};
+
+
+// Headless self-play between engine configurations, run with
+// cargo run --release --bin tournament -- --engine name=new,depth=8 --engine name=old,depth=6 --games 200
+// --visits 5 prints the five most visited root moves of every tree search move
+
+
+// Turns after which a game that is still going is called a draw
+const MAX_TURNS: usize = 200;
+const REPETITIONS_FOR_DRAW: u32 = 3;
+// Openings that leave one side more than this far behind in a shallow search are left out
+const OPENING_CHECK_DEPTH: u32 = 4;
+const MAX_OPENING_IMBALANCE: f32 = 25.;
+// Error rates of the sequential probability ratio test
+const SPRT_ALPHA: f64 = 0.05;
+const SPRT_BETA: f64 = 0.05;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SearchType {
+    AlphaBeta,
+    Mcts
+}
+
+
+#[derive(Debug, Clone)]
+struct EngineConfig {
+    name: String,
+    search: SearchType,
+    // playouts per move of the tree search without a move time
+    playouts: u32,
+    depth: u32,
+    // searched by iterative deepening for this long when set, otherwise to the full depth
+    move_time: Option<Duration>,
+    weights: EvalWeights,
+    // evaluates with a trained network instead of the weights when set
+    network: Option<Arc<Network>>,
+    // plays book moves without searching when set
+    book: Option<Arc<OpeningBook>>
+}
+
+
+impl EngineConfig {
+    // A spec like name=deep,depth=8,time=500,man=50,king=75,middle=2, time in milliseconds,
+    // weights=<file> reads the weights from a file written by the tuner, network=<file> evaluates with a
+    // trained network, book=<file> plays from an opening book and type=mcts plays with the tree search
+    fn parse(spec: &str, number: usize) -> Result<Self, String> {
+        let mut config = EngineConfig { name: format!("engine{}", number), search: SearchType::AlphaBeta, playouts: MCTS_PLAYOUTS, depth: 6, move_time: None, weights: EvalWeights::loaded(), network: None, book: None };
+        for part in spec.split(',').filter(|p| p.len() > 0) {
+            let (key, value) = part.split_once('=').ok_or(format!("Expected key=value in {}", part))?;
+            let float = || value.parse::<f32>().map_err(|_| format!("Invalid {} {}", key, value));
+            match key {
+                "name" => config.name = value.to_string(),
+                "type" => config.search = match value {
+                    "alphabeta" => SearchType::AlphaBeta,
+                    "mcts" => SearchType::Mcts,
+                    _ => return Err(format!("Unknown search type {}", value))
+                },
+                "playouts" => config.playouts = value.parse().map_err(|_| format!("Invalid playouts {}", value))?,
+                "depth" => config.depth = value.parse().map_err(|_| format!("Invalid depth {}", value))?,
+                "time" => config.move_time = Some(Duration::from_millis(value.parse().map_err(|_| format!("Invalid time {}", value))?)),
+                "weights" => config.weights = EvalWeights::load(Path::new(value)).map_err(|e| format!("Could not read {}: {}", value, e))?,
+                "network" => config.network = Some(Arc::new(Network::load(Path::new(value)).map_err(|e| format!("Could not read {}: {}", value, e))?)),
+                "book" => config.book = Some(Arc::new(OpeningBook::load(Path::new(value)).map_err(|e| format!("Could not read {}: {}", value, e))?)),
+                "man" => config.weights.man = float()?,
+                "king" => config.weights.king = float()?,
+                "middle" => config.weights.king_middle = float()?,
+                _ => return Err(format!("Unknown engine setting {}", key))
+            }
+        }
+        return Ok(config);
+    }
+
+    // The tree search prints its most visited root moves when visits isn't 0
+    fn choose_moves(&self, state: &CheckersState, round: usize, visits: usize) -> Vec<Move> {
+        if let Some(moves) = self.book.as_ref().and_then(|book| book.choose(state, &mut Random::new())) {
+            return moves;
+        }
+        let mut state = state.clone();
+        state.evaluator = match self.network {
+            Some(ref network) => network.clone(),
+            None => Arc::new(self.weights)
+        };
+        match self.search {
+            SearchType::AlphaBeta => return find_best_moves_to_depth(&state, self.depth, self.move_time),
+            SearchType::Mcts => return find_best_moves_mcts_with(&state, self.move_time, self.playouts, |result| {
+                if visits > 0 {
+                    println!("Game {}, {}: {} ({} playouts)", round, self.name, visits_to_string(&state, result, visits), result.playouts);
+                }
+            })
+        }
+    }
+}
+
+
+struct Settings {
+    engines: Vec<EngineConfig>,
+    // games each pair of engines plays, half with each colour
+    games: usize,
+    threads: usize,
+    variant: Variant,
+    pdn_path: String,
+    // Elo difference of the null and alternative hypotheses, tested on the first two engines
+    sprt: Option<(f64, f64)>,
+    seed: u64,
+    // root moves of the tree search to print for every move
+    visits: usize
+}
+
+
+fn parse_args() -> Result<Settings, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut settings = Settings {
+        engines: Vec::new(),
+        games: 100,
+        threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        variant: Variant::American,
+        pdn_path: String::from("tournament.pdn"),
+        sprt: None,
+        seed: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(1),
+        visits: 0
+    };
+    let mut idx = 0;
+    while idx < args.len() {
+        let value = args.get(idx + 1).ok_or(format!("{} needs a value", args[idx]))?;
+        match args[idx].as_str() {
+            "--engine" => settings.engines.push(EngineConfig::parse(value, settings.engines.len() + 1)?),
+            "--games" => settings.games = value.parse().map_err(|_| format!("Invalid number of games {}", value))?,
+            "--threads" => settings.threads = value.parse::<usize>().map_err(|_| format!("Invalid number of threads {}", value))?.max(1),
+            "--variant" => {
+                settings.variant = *Variant::ALL.iter().find(|v| v.name().eq_ignore_ascii_case(value)).ok_or(format!("Unknown variant {}", value))?;
+            },
+            "--pdn" => settings.pdn_path = value.clone(),
+            "--sprt" => {
+                let (elo0, elo1) = value.split_once(',').ok_or("--sprt takes elo0,elo1")?;
+                let elo0 = elo0.parse().map_err(|_| format!("Invalid Elo {}", elo0))?;
+                let elo1 = elo1.parse().map_err(|_| format!("Invalid Elo {}", elo1))?;
+                settings.sprt = Some((elo0, elo1));
+            },
+            "--seed" => settings.seed = value.parse().map_err(|_| format!("Invalid seed {}", value))?,
+            "--visits" => settings.visits = value.parse().map_err(|_| format!("Invalid number of moves {}", value))?,
+            arg => return Err(format!("Unknown argument {}", arg))
+        }
+        idx += 2;
+    }
+    if settings.engines.len() < 2 {
+        return Err(String::from("At least two --engine settings are needed"));
+    }
+    // games come in pairs with colours swapped
+    settings.games += settings.games % 2;
+    return Ok(settings);
+}
+
+
+// Openings from the ballot deck that don't already decide the game, in a shuffled order
+fn balanced_openings(start: &CheckersState, seed: u64) -> Vec<Vec<Vec<Move>>> {
+    let mut openings: Vec<Vec<Vec<Move>>> = load_deck(start).into_iter().filter(|opening| {
+        let state = opening.iter().fold(start.clone(), |state, turn| state.next_state_with_move(turn));
+        if state.is_game_over() {
+            return false;
+        }
+        let (score, _) = minimax_alpha_beta(&state, OPENING_CHECK_DEPTH, f32::NEG_INFINITY, f32::INFINITY, true, &state.turn);
+        return score.abs() <= MAX_OPENING_IMBALANCE;
+    }).collect();
+    let mut random = seed.max(1);
+    for idx in (1..openings.len()).rev() {
+        // xorshift is plenty for picking an order
+        random ^= random << 13;
+        random ^= random >> 7;
+        random ^= random << 17;
+        openings.swap(idx, (random % (idx as u64 + 1)) as usize);
+    }
+    return openings;
+}
+
+
+struct Job {
+    round: usize,
+    // engine playing the side that moves first, and its opponent
+    first: usize,
+    second: usize,
+    opening: Vec<Vec<Move>>
+}
+
+
+struct Finished {
+    first: usize,
+    second: usize,
+    record: GameRecord,
+    pdn: String
+}
+
+
+fn play_turn(state: &mut CheckersState, record: &mut GameRecord, turn: &Vec<Move>) {
+    for (idx, m) in turn.iter().enumerate() {
+        record.push_move(state.turn, *m, idx == turn.len() - 1);
+    }
+    *state = state.next_state_with_move(turn);
+}
+
+
+fn play_game(settings: &Settings, start: &CheckersState, job: &Job) -> Finished {
+    let mut record = GameRecord::default();
+    record.start = Some(start.clone());
+    record.tags.push((String::from("Ballot"), opening_to_string(start, &job.opening)));
+    let mut state = start.clone();
+    let mut seen = HashMap::<String, u32>::new();
+    let mut turns = 0;
+    for turn in job.opening.iter() {
+        play_turn(&mut state, &mut record, turn);
+    }
+    let first_player = start.turn;
+    let result = loop {
+        if let Some(winner) = state.get_winner() {
+            break GameResult { winner: Some(winner), reason: GameOverReason::NoMoves };
+        }
+        let count = seen.entry(to_fen(&state)).or_insert(0);
+        *count += 1;
+        if *count >= REPETITIONS_FOR_DRAW || turns >= MAX_TURNS {
+            break GameResult { winner: None, reason: GameOverReason::Adjudication };
+        }
+        let engine = match state.turn == first_player {
+            true => &settings.engines[job.first],
+            false => &settings.engines[job.second]
+        };
+        let turn = engine.choose_moves(&state, job.round, settings.visits);
+        play_turn(&mut state, &mut record, &turn);
+        turns += 1;
+    };
+    record.result = Some(result);
+
+    let (black, red) = match first_player {
+        PieceColor::Black => (job.first, job.second),
+        PieceColor::Red => (job.second, job.first)
+    };
+    let mut game = record.to_pdn_game(&settings.engines[black].name, &settings.engines[red].name);
+    game.set_tag("Event", "Tournament");
+    game.set_tag("Round", &job.round.to_string());
+    return Finished { first: job.first, second: job.second, pdn: game.to_pdn(), record };
+}
+
+
+// Wins, draws and losses of one engine against another
+#[derive(Debug, Clone, Copy, Default)]
+struct Score {
+    wins: u32,
+    draws: u32,
+    losses: u32
+}
+
+
+impl Score {
+    fn games(&self) -> u32 {
+        return self.wins + self.draws + self.losses;
+    }
+
+    fn points(&self) -> f64 {
+        return self.wins as f64 + self.draws as f64 / 2.;
+    }
+
+    // Fraction of the points scored, and the variance of a single game's score around it
+    fn mean_and_variance(&self) -> (f64, f64) {
+        let n = self.games() as f64;
+        let mean = self.points() / n;
+        let variance = (self.wins as f64 * (1. - mean).powi(2) + self.draws as f64 * (0.5 - mean).powi(2) + self.losses as f64 * mean.powi(2)) / n;
+        return (mean, variance);
+    }
+
+    // Elo difference with a 95% interval, from the logistic model
+    fn elo(&self) -> (f64, f64, f64) {
+        let (mean, variance) = self.mean_and_variance();
+        let margin = 1.96 * (variance / self.games() as f64).sqrt();
+        return (elo_from_score(mean), elo_from_score(mean - margin), elo_from_score(mean + margin));
+    }
+
+    // Log likelihood ratio of elo1 against elo0, with the scores taken as normally distributed
+    fn sprt_llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let (mean, variance) = self.mean_and_variance();
+        if variance <= 0. {
+            return 0.;
+        }
+        let (score0, score1) = (score_from_elo(elo0), score_from_elo(elo1));
+        return self.games() as f64 * (score1 - score0) * (2. * mean - score0 - score1) / (2. * variance);
+    }
+}
+
+
+fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1. - 1e-6);
+    return -400. * (1. / score - 1.).log10();
+}
+
+
+fn score_from_elo(elo: f64) -> f64 {
+    return 1. / (1. + 10f64.powf(-elo / 400.));
+}
+
+
+fn sprt_bounds() -> (f64, f64) {
+    return ((SPRT_BETA / (1. - SPRT_ALPHA)).ln(), ((1. - SPRT_BETA) / SPRT_ALPHA).ln());
+}
+
+
+fn print_crosstable(settings: &Settings, scores: &Vec<Vec<Score>>) {
+    let width = settings.engines.iter().map(|e| e.name.len()).max().unwrap_or(0).max(8);
+    let mut header = format!("{:<width$}", "", width = width);
+    for engine in settings.engines.iter() {
+        header.push_str(&format!(" {:>12}", engine.name));
+    }
+    println!("{} {:>8}", header, "Points");
+    for (idx, engine) in settings.engines.iter().enumerate() {
+        let mut line = format!("{:<width$}", engine.name, width = width);
+        let mut total = 0.;
+        for (opp, score) in scores[idx].iter().enumerate() {
+            if opp == idx {
+                line.push_str(&format!(" {:>12}", "-"));
+                continue;
+            }
+            total += score.points();
+            line.push_str(&format!(" {:>12}", format!("{}-{}-{}", score.wins, score.draws, score.losses)));
+        }
+        println!("{} {:>8.1}", line, total);
+    }
+}
+
+
+fn print_results(settings: &Settings, scores: &Vec<Vec<Score>>) {
+    println!();
+    print_crosstable(settings, scores);
+    println!();
+    for a in 0..settings.engines.len() {
+        for b in (a + 1)..settings.engines.len() {
+            let score = scores[a][b];
+            if score.games() == 0 {
+                continue;
+            }
+            let (elo, low, high) = score.elo();
+            println!("{} vs {}: {:+.1} Elo, 95% interval {:+.1} to {:+.1} over {} games", settings.engines[a].name, settings.engines[b].name, elo, low, high, score.games());
+        }
+    }
+    if let Some((elo0, elo1)) = settings.sprt {
+        let score = scores[0][1];
+        let llr = score.sprt_llr(elo0, elo1);
+        let (lower, upper) = sprt_bounds();
+        let verdict = match llr {
+            llr if llr >= upper => format!("H1 accepted, {} is at least {} Elo stronger", settings.engines[0].name, elo1),
+            llr if llr <= lower => format!("H0 accepted, {} is not {} Elo stronger", settings.engines[0].name, elo1),
+            _ => String::from("inconclusive, more games are needed")
+        };
+        println!("SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2}), {}", elo0, elo1, llr, lower, upper, verdict);
+    }
+}
+
+
+fn main() {
+    let settings = match parse_args() {
+        Ok(settings) => Arc::new(settings),
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let start = CheckersState::new(settings.variant.rules());
+    let openings = balanced_openings(&start, settings.seed);
+    if openings.len() == 0 {
+        println!("No balanced openings for {}", settings.variant.name());
+        return;
+    }
+    println!("{} openings, seed {}, {} threads", openings.len(), settings.seed, settings.threads);
+
+    // every pair of engines plays both colours of the same opening
+    let mut jobs = VecDeque::new();
+    let mut round = 0;
+    for pair in 0..(settings.games / 2) {
+        let opening = &openings[pair % openings.len()];
+        for a in 0..settings.engines.len() {
+            for b in (a + 1)..settings.engines.len() {
+                for (first, second) in [(a, b), (b, a)] {
+                    round += 1;
+                    jobs.push_back(Job { round, first, second, opening: opening.clone() });
+                }
+            }
+        }
+    }
+    let total = jobs.len();
+    let jobs = Arc::new(Mutex::new(jobs));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel::<Finished>();
+    let mut workers = Vec::new();
+    for _ in 0..settings.threads {
+        let (settings, start, jobs, stop, sender) = (settings.clone(), start.clone(), jobs.clone(), stop.clone(), sender.clone());
+        workers.push(thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let job = match jobs.lock().unwrap().pop_front() {
+                    Some(job) => job,
+                    None => break
+                };
+                if sender.send(play_game(&settings, &start, &job)).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+    drop(sender);
+
+    let mut scores = vec![vec![Score::default(); settings.engines.len()]; settings.engines.len()];
+    let mut pdn = String::new();
+    let mut played = 0;
+    for finished in receiver {
+        played += 1;
+        let winner = finished.record.result.and_then(|r| r.winner);
+        let first_player = start.turn;
+        let (first, second) = (finished.first, finished.second);
+        match winner {
+            None => {
+                scores[first][second].draws += 1;
+                scores[second][first].draws += 1;
+            },
+            Some(col) if col == first_player => {
+                scores[first][second].wins += 1;
+                scores[second][first].losses += 1;
+            },
+            Some(_) => {
+                scores[second][first].wins += 1;
+                scores[first][second].losses += 1;
+            }
+        }
+        pdn.push_str(&finished.pdn);
+        pdn.push('\n');
+        let outcome = match winner {
+            None => "1/2-1/2",
+            Some(col) if col == first_player => "1-0",
+            Some(_) => "0-1"
+        };
+        println!("Game {} of {}: {} - {} {} in {} turns", played, total, settings.engines[first].name, settings.engines[second].name, outcome, finished.record.turns.len());
+
+        if let Some((elo0, elo1)) = settings.sprt {
+            let llr = scores[0][1].sprt_llr(elo0, elo1);
+            let (lower, upper) = sprt_bounds();
+            if (llr >= upper || llr <= lower) && !stop.load(Ordering::Relaxed) {
+                println!("SPRT finished, stopping after the games being played");
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    print_results(&settings, &scores);
+    match fs::write(&settings.pdn_path, pdn) {
+        Ok(_) => println!("Games written to {}", settings.pdn_path),
+        Err(e) => println!("Could not write {}: {}", settings.pdn_path, e)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        return (a - b).abs() < tolerance;
+    }
+
+    #[test]
+    fn even_score_is_no_difference() {
+        let score = Score { wins: 30, draws: 40, losses: 30 };
+        let (elo, low, high) = score.elo();
+        assert!(close(elo, 0., 1e-9));
+        assert!(close(low, -high, 1e-9) && low < 0.);
+        // the interval narrows with more games
+        let (_, more_low, _) = Score { wins: 300, draws: 400, losses: 300 }.elo();
+        assert!(more_low > low);
+    }
+
+    #[test]
+    fn three_quarters_is_about_191() {
+        let (elo, low, high) = Score { wins: 3, draws: 0, losses: 1 }.elo();
+        assert!(close(elo, 190.85, 0.01));
+        assert!(low < elo && elo < high);
+        // draws count as half a point
+        assert!(close(Score { wins: 2, draws: 2, losses: 0 }.elo().0, elo, 1e-9));
+        assert_eq!(Score { wins: 2, draws: 2, losses: 0 }.mean_and_variance(), (0.75, 0.0625));
+    }
+
+    #[test]
+    fn sprt_leans_towards_the_nearer_hypothesis() {
+        let (lower, upper) = sprt_bounds();
+        assert!(close(lower, -2.944, 0.001));
+        assert!(close(upper, 2.944, 0.001));
+        let winning = Score { wins: 60, draws: 20, losses: 20 };
+        assert!(close(winning.sprt_llr(0., 10.), 1.7337, 0.0001));
+        let losing = Score { wins: 20, draws: 20, losses: 60 };
+        assert!(losing.sprt_llr(0., 10.) < 0.);
+        // all draws tell nothing either way
+        assert_eq!(Score { wins: 0, draws: 10, losses: 0 }.sprt_llr(0., 10.), 0.);
+    }
+}
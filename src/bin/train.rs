@@ -0,0 +1,162 @@
+use std::{path::PathBuf, time::Instant};
+use checkers::{
+    ai::{EvalWeights, Evaluator},
+    network::{Network, network_path},
+    training::{LabelledPosition, read_labelled_positions}
+};
+
+
+// Trains the evaluation network on finished games, run with
+// cargo run --release --bin train -- tournament.pdn [more.pdn] [--out network.json]
+// Every tenth position is held back to check the network on, and at the end the network is
+// compared with the handcrafted evaluation for accuracy and speed.
+
+
+const DEFAULT_SKIP_TURNS: usize = 6;
+const DEFAULT_HIDDEN: usize = 32;
+const DEFAULT_EPOCHS: usize = 30;
+const DEFAULT_RATE: f32 = 0.01;
+const VALIDATION_EVERY: usize = 10;
+// Evaluations timed for the speed comparison
+const BENCHMARK_EVALUATIONS: usize = 200000;
+
+
+struct Settings {
+    files: Vec<String>,
+    out: PathBuf,
+    skip_turns: usize,
+    hidden: usize,
+    epochs: usize,
+    rate: f32,
+    seed: u64
+}
+
+
+fn parse_args() -> Result<Settings, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut settings = Settings {
+        files: Vec::new(), out: network_path(), skip_turns: DEFAULT_SKIP_TURNS, hidden: DEFAULT_HIDDEN, epochs: DEFAULT_EPOCHS, rate: DEFAULT_RATE, seed: 1
+    };
+    let mut idx = 0;
+    while idx < args.len() {
+        if !args[idx].starts_with("--") {
+            settings.files.push(args[idx].clone());
+            idx += 1;
+            continue;
+        }
+        let value = args.get(idx + 1).ok_or(format!("{} needs a value", args[idx]))?;
+        match args[idx].as_str() {
+            "--out" => settings.out = PathBuf::from(value),
+            "--skip" => settings.skip_turns = value.parse().map_err(|_| format!("Invalid number of turns {}", value))?,
+            "--hidden" => settings.hidden = value.parse().map_err(|_| format!("Invalid hidden layer size {}", value))?,
+            "--epochs" => settings.epochs = value.parse().map_err(|_| format!("Invalid number of epochs {}", value))?,
+            "--rate" => settings.rate = value.parse().map_err(|_| format!("Invalid learning rate {}", value))?,
+            "--seed" => settings.seed = value.parse().map_err(|_| format!("Invalid seed {}", value))?,
+            arg => return Err(format!("Unknown argument {}", arg))
+        }
+        idx += 2;
+    }
+    if settings.files.len() == 0 {
+        return Err(String::from("Give at least one PDN file of finished games"));
+    }
+    return Ok(settings);
+}
+
+
+fn sigmoid(x: f32) -> f32 {
+    return 1. / (1. + (-x).exp());
+}
+
+
+// Mean squared difference between the results and the predicted win chances
+fn mean_squared_error<F: Fn(&LabelledPosition) -> f32>(positions: &[LabelledPosition], predict: F) -> f64 {
+    let total: f64 = positions.iter().map(|p| ((p.result - predict(p)) as f64).powi(2)).sum();
+    return total / positions.len().max(1) as f64;
+}
+
+
+// Win chances from the handcrafted evaluation, with the scale that fits the positions best
+fn handcrafted_error(weights: &EvalWeights, positions: &[LabelledPosition]) -> f64 {
+    let scores: Vec<f32> = positions.iter().map(|p| weights.evaluate(&p.state, p.state.turn)).collect();
+    let mut best = f64::MAX;
+    for step in 1..=200 {
+        let scale = step as f32 * 0.001;
+        let total: f64 = positions.iter().zip(scores.iter()).map(|(p, s)| ((p.result - sigmoid(scale * s)) as f64).powi(2)).sum();
+        best = best.min(total / positions.len().max(1) as f64);
+    }
+    return best;
+}
+
+
+// Nanoseconds per evaluation, going round the positions until enough have been done
+fn time_evaluator(evaluator: &dyn Evaluator, positions: &[LabelledPosition]) -> f64 {
+    let started = Instant::now();
+    let mut total = 0.;
+    for idx in 0..BENCHMARK_EVALUATIONS {
+        let position = &positions[idx % positions.len()];
+        total += evaluator.evaluate(&position.state, position.state.turn);
+    }
+    let elapsed = started.elapsed();
+    // keeps the evaluations from being optimised away
+    if total.is_nan() {
+        println!("Evaluation gave NaN");
+    }
+    return elapsed.as_nanos() as f64 / BENCHMARK_EVALUATIONS as f64;
+}
+
+
+fn main() {
+    let settings = match parse_args() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let (positions, skipped) = read_labelled_positions(&settings.files, settings.skip_turns);
+    for reason in skipped.iter() {
+        println!("{}", reason);
+    }
+    if positions.len() < VALIDATION_EVERY {
+        println!("Not enough positions to train on");
+        return;
+    }
+    let rules = positions[0].state.rules;
+    let squares = rules.squares_per_row() * rules.board_dim;
+    // the network only fits one number of squares, the first game decides which
+    let (validation, training): (Vec<(usize, LabelledPosition)>, Vec<(usize, LabelledPosition)>) = positions.into_iter()
+        .filter(|p| p.state.rules.squares_per_row() * p.state.rules.board_dim == squares)
+        .enumerate()
+        .partition(|(idx, _)| idx % VALIDATION_EVERY == 0);
+    let validation: Vec<LabelledPosition> = validation.into_iter().map(|(_, p)| p).collect();
+    let training: Vec<LabelledPosition> = training.into_iter().map(|(_, p)| p).collect();
+    println!("{} training and {} validation positions on {} squares", training.len(), validation.len(), squares);
+
+    let mut network = Network::new(squares, settings.hidden, settings.seed);
+    let inputs: Vec<Vec<usize>> = training.iter().map(|p| network.inputs(&p.state, p.state.turn).unwrap()).collect();
+    let mut order: Vec<usize> = (0..training.len()).collect();
+    let mut random = settings.seed.max(1);
+    for epoch in 1..=settings.epochs {
+        for idx in (1..order.len()).rev() {
+            random ^= random << 13;
+            random ^= random >> 7;
+            random ^= random << 17;
+            order.swap(idx, (random % (idx as u64 + 1)) as usize);
+        }
+        let mut loss = 0.;
+        for &idx in order.iter() {
+            loss += network.train(&inputs[idx], training[idx].result, settings.rate);
+        }
+        let validation_error = mean_squared_error(&validation, |p| sigmoid(network.forward(&network.inputs(&p.state, p.state.turn).unwrap())));
+        println!("Epoch {}: loss {:.5}, validation error {:.5}", epoch, loss / training.len() as f32, validation_error);
+    }
+
+    let weights = EvalWeights::loaded();
+    let network_error = mean_squared_error(&validation, |p| sigmoid(network.forward(&network.inputs(&p.state, p.state.turn).unwrap())));
+    println!("Validation error: network {:.5}, handcrafted {:.5}", network_error, handcrafted_error(&weights, &validation));
+    println!("Time per evaluation: network {:.0}ns, handcrafted {:.0}ns", time_evaluator(&network, &validation), time_evaluator(&weights, &validation));
+    match network.save(&settings.out) {
+        Ok(_) => println!("Network written to {:?}", settings.out),
+        Err(e) => println!("Could not write {:?}: {}", settings.out, e)
+    }
+}
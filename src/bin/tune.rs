@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+use checkers::{
+    ai::{EvalWeights, EvalFeatures, eval_features, weights_path},
+    training::read_labelled_positions
+};
+
+
+// Texel tuning of the evaluation weights from finished games, run with
+// cargo run --release --bin tune -- tournament.pdn [more.pdn] [--out weights.json] [--install]
+// Every quiet position is labelled with the result of its game, and the weights are moved one
+// step at a time while that lowers the error of predicting the result from the evaluation.
+
+
+// Turns at the start of each game that are left out, they come from the ballot rather than the engines
+const DEFAULT_SKIP_TURNS: usize = 6;
+const DEFAULT_PASSES: usize = 200;
+const WEIGHT_STEP: f32 = 1.;
+// Written to the working directory, the game only picks the weights up once they are installed
+const DEFAULT_OUT: &str = "weights.json";
+
+
+// A position seen from the side to move, with the points that side got from the game
+struct Sample {
+    features: EvalFeatures,
+    result: f32
+}
+
+
+struct Settings {
+    files: Vec<String>,
+    out: PathBuf,
+    skip_turns: usize,
+    passes: usize
+}
+
+
+fn parse_args() -> Result<Settings, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut settings = Settings { files: Vec::new(), out: PathBuf::from(DEFAULT_OUT), skip_turns: DEFAULT_SKIP_TURNS, passes: DEFAULT_PASSES };
+    let mut idx = 0;
+    while idx < args.len() {
+        if !args[idx].starts_with("--") {
+            settings.files.push(args[idx].clone());
+            idx += 1;
+            continue;
+        }
+        // --install writes the weights to the save folder, where the game and the tournament use them
+        if args[idx] == "--install" {
+            settings.out = weights_path();
+            idx += 1;
+            continue;
+        }
+        let value = args.get(idx + 1).ok_or(format!("{} needs a value", args[idx]))?;
+        match args[idx].as_str() {
+            "--out" => settings.out = PathBuf::from(value),
+            "--skip" => settings.skip_turns = value.parse().map_err(|_| format!("Invalid number of turns {}", value))?,
+            "--passes" => settings.passes = value.parse().map_err(|_| format!("Invalid number of passes {}", value))?,
+            arg => return Err(format!("Unknown argument {}", arg))
+        }
+        idx += 2;
+    }
+    if settings.files.len() == 0 {
+        return Err(String::from("Give at least one PDN file of finished games"));
+    }
+    return Ok(settings);
+}
+
+
+fn read_samples(settings: &Settings) -> Vec<Sample> {
+    let (positions, skipped) = read_labelled_positions(&settings.files, settings.skip_turns);
+    for reason in skipped.iter() {
+        println!("{}", reason);
+    }
+    return positions.into_iter().map(|position| Sample {
+        features: eval_features(&position.state, position.state.turn),
+        result: position.result
+    }).collect();
+}
+
+
+fn evaluate(features: &EvalFeatures, weights: &EvalWeights) -> f32 {
+    return features.men * weights.man + features.kings * weights.king + features.kings_middle * weights.king_middle;
+}
+
+
+// Mean squared difference between the results and the win chances the evaluation predicts
+fn error(samples: &Vec<Sample>, weights: &EvalWeights, scale: f32) -> f64 {
+    let total: f64 = samples.iter().map(|sample| {
+        let predicted = 1. / (1. + (-scale * evaluate(&sample.features, weights)).exp());
+        return ((sample.result - predicted) as f64).powi(2);
+    }).sum();
+    return total / samples.len() as f64;
+}
+
+
+// The scale turning evaluations into win chances that fits the starting weights best
+fn fit_scale(samples: &Vec<Sample>, weights: &EvalWeights) -> f32 {
+    let mut best = (0.01, error(samples, weights, 0.01));
+    for step in 1..=200 {
+        let scale = step as f32 * 0.001;
+        let err = error(samples, weights, scale);
+        if err < best.1 {
+            best = (scale, err);
+        }
+    }
+    return best.0;
+}
+
+
+fn tune(samples: &Vec<Sample>, start: EvalWeights, scale: f32, passes: usize) -> EvalWeights {
+    let mut weights = start;
+    let mut best = error(samples, &weights, scale);
+    for pass in 0..passes {
+        let mut improved = false;
+        for idx in 0..3 {
+            for delta in [WEIGHT_STEP, -WEIGHT_STEP] {
+                let mut candidate = weights;
+                match idx {
+                    0 => candidate.man += delta,
+                    1 => candidate.king += delta,
+                    _ => candidate.king_middle += delta
+                }
+                let err = error(samples, &candidate, scale);
+                if err < best {
+                    best = err;
+                    weights = candidate;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            println!("Converged after {} passes", pass);
+            break;
+        }
+    }
+    return weights;
+}
+
+
+fn main() {
+    let settings = match parse_args() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let samples = read_samples(&settings);
+    if samples.len() == 0 {
+        println!("No positions to tune on");
+        return;
+    }
+    let start = EvalWeights::loaded();
+    let scale = fit_scale(&samples, &start);
+    println!("{} positions, scale {}, error {:.5} with {:?}", samples.len(), scale, error(&samples, &start, scale), start);
+
+    let weights = tune(&samples, start, scale, settings.passes);
+    println!("Error {:.5} with {:?}", error(&samples, &weights, scale), weights);
+    match weights.save(&settings.out) {
+        Ok(_) => println!("Weights written to {:?}", settings.out),
+        Err(e) => println!("Could not write {:?}: {}", settings.out, e)
+    }
+}
@@ -0,0 +1,330 @@
+use bevy::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock
+};
+use crate::{
+    state::{CheckersState, PieceColor, PieceType},
+    rules::RulesConfig,
+    logic::Move,
+    alphabeta::{minimax_alpha_beta, TwoPlayerGameState},
+    notation::{PdnGame, square_number},
+    training::first_player_points,
+    mcts::Random,
+    dxp::captured_squares,
+    save::save_dir
+};
+
+
+// Opening book of moves to play without searching, keyed by a hash of the position. A move is
+// stored as the square the turn starts from, the square it ends on and a hash of the squares
+// it captures, with a weight giving how often it should be picked.
+
+const FILE_PREFIX: &str = "book_";
+const FILE_MAGIC: &[u8; 4] = b"CKBK";
+const FILE_VERSION: u8 = 2;
+// Position hash, start square, end square, captures hash and weight
+const RECORD_SIZE: usize = 16;
+
+
+// Keys for the position hash, the same on every run so book files stay valid
+fn hash_key(n: u64) -> u64 {
+    let mut z = n.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
+
+
+// Zobrist hash of the pieces on the board and the side to move
+pub fn position_hash(state: &CheckersState) -> u64 {
+    let mut hash = if state.turn == PieceColor::Black { 0 } else { hash_key(u64::MAX - 1) };
+    for row in 0..state.board.len() {
+        for col in 0..state.board.len() {
+            if let Some(piece) = state.board[row][col] {
+                let kind = match (piece.col, piece.typ) {
+                    (PieceColor::Black, PieceType::Man) => 0,
+                    (PieceColor::Black, PieceType::King) => 1,
+                    (PieceColor::Red, PieceType::Man) => 2,
+                    (PieceColor::Red, PieceType::King) => 3
+                };
+                hash ^= hash_key(((row * state.board.len() + col) * 4 + kind) as u64);
+            }
+        }
+    }
+    return hash;
+}
+
+
+// Start and end squares of a turn and a hash of the squares it captures, which tells apart
+// captures that start and end on the same squares
+fn turn_key(state: &CheckersState, turn: &Vec<Move>) -> (u8, u8, u32) {
+    let from = square_number(&turn[0].from, &state.rules);
+    let to = square_number(&turn[turn.len() - 1].to, &state.rules);
+    let captures = captured_squares(state, turn).iter().fold(0, |hash, &square| hash ^ hash_key(u64::MAX - 2 - square as u64));
+    return (from as u8, to as u8, captures as u32);
+}
+
+
+#[derive(Debug, Clone, Copy)]
+struct BookRecord {
+    hash: u64,
+    from: u8,
+    to: u8,
+    captures: u32,
+    weight: u16
+}
+
+
+#[derive(Debug)]
+pub struct OpeningBook {
+    pub rules: RulesConfig,
+    // sorted by hash so a position's moves are found by binary search
+    records: Vec<BookRecord>,
+    // weights added since the records were last sorted
+    pending: HashMap<(u64, u8, u8, u32), u32>
+}
+
+
+impl OpeningBook {
+    pub fn new(rules: RulesConfig) -> Self {
+        return OpeningBook { rules, records: Vec::new(), pending: HashMap::new() };
+    }
+
+    pub fn add_move(&mut self, state: &CheckersState, turn: &Vec<Move>, weight: u32) {
+        let (from, to, captures) = turn_key(state, turn);
+        *self.pending.entry((position_hash(state), from, to, captures)).or_insert(0) += weight;
+    }
+
+    // Adds the first turns of every game played under the book's rules, a move counts twice
+    // for each win of the side that played it and once for a draw, returns the games used
+    pub fn add_games(&mut self, games: &[PdnGame], plies: usize) -> usize {
+        let mut used = 0;
+        for game in games.iter() {
+            let result = game.tag("Result").or(game.result.as_deref()).and_then(first_player_points);
+            let (result, start) = match (result, game.start_state()) {
+                (Some(result), Ok(start)) if start.rules == self.rules => (result, start),
+                _ => continue
+            };
+            let turns = match game.resolve_turns(&start) {
+                Ok(turns) => turns,
+                Err(_) => continue
+            };
+            let mut state = start;
+            for turn in turns.iter().take(plies) {
+                let points = if state.turn == self.rules.first_player { result } else { 1. - result };
+                if points > 0. {
+                    self.add_move(&state, turn, (points * 2.) as u32);
+                }
+                state = state.next_state_with_move(turn);
+            }
+            used += 1;
+        }
+        return used;
+    }
+
+    // Searches every move from the start position to the depth, keeps those within the margin of
+    // the best and goes on from them until plies turns are in the book, returns the positions searched
+    pub fn add_analysis(&mut self, depth: u32, plies: usize, margin: f32) -> usize {
+        let mut searched = HashSet::new();
+        let mut frontier = vec![CheckersState::new(self.rules)];
+        for _ in 0..plies {
+            let mut next_frontier = Vec::new();
+            for state in frontier.iter() {
+                if state.is_game_over() || !searched.insert(position_hash(state)) {
+                    continue;
+                }
+                let scored: Vec<(f32, Vec<Move>)> = state.get_possible_moves().into_iter().map(|turn| {
+                    let next = state.next_state_with_move(&turn);
+                    let (score, _) = minimax_alpha_beta(&next, depth.saturating_sub(1), f32::NEG_INFINITY, f32::INFINITY, next.turn == state.turn, &state.turn);
+                    return (score, turn);
+                }).collect();
+                let best = scored.iter().map(|(score, _)| *score).fold(f32::NEG_INFINITY, f32::max);
+                for (score, turn) in scored.into_iter() {
+                    if score >= best - margin {
+                        // the best move gets picked most, those at the edge of the margin least
+                        self.add_move(state, &turn, (margin - (best - score)).max(0.) as u32 + 1);
+                        next_frontier.push(state.next_state_with_move(&turn));
+                    }
+                }
+            }
+            frontier = next_frontier;
+        }
+        return searched.len();
+    }
+
+    fn sort_pending(&mut self) {
+        if self.pending.len() == 0 {
+            return;
+        }
+        for record in self.records.drain(..) {
+            *self.pending.entry((record.hash, record.from, record.to, record.captures)).or_insert(0) += record.weight as u32;
+        }
+        self.records = self.pending.drain().map(|((hash, from, to, captures), weight)| BookRecord { hash, from, to, captures, weight: weight.min(u16::MAX as u32) as u16 }).collect();
+        self.records.sort_by_key(|r| (r.hash, r.from, r.to, r.captures));
+    }
+
+    // Positions and moves in the book
+    pub fn size(&mut self) -> (usize, usize) {
+        self.sort_pending();
+        let mut positions = self.records.iter().map(|r| r.hash).collect::<Vec<u64>>();
+        positions.dedup();
+        return (positions.len(), self.records.len());
+    }
+
+    // Every book move from the position with its weight
+    pub fn moves(&self, state: &CheckersState) -> Vec<(Vec<Move>, u16)> {
+        if state.rules != self.rules || state.pending_captures.len() > 0 {
+            return Vec::new();
+        }
+        let hash = position_hash(state);
+        let start = self.records.partition_point(|r| r.hash < hash);
+        if self.records.get(start).map_or(true, |r| r.hash != hash) {
+            return Vec::new();
+        }
+        let legal = state.get_possible_moves();
+        let mut moves = Vec::new();
+        for record in self.records[start..].iter().take_while(|r| r.hash == hash) {
+            // a hash collision or an outdated book can give moves that aren't legal here
+            if let Some(turn) = legal.iter().find(|turn| turn_key(state, turn) == (record.from, record.to, record.captures)) {
+                moves.push((turn.clone(), record.weight));
+            }
+        }
+        return moves;
+    }
+
+    // A book move picked at random with the chances given by the weights
+    pub fn choose(&self, state: &CheckersState, random: &mut Random) -> Option<Vec<Move>> {
+        let moves = self.moves(state);
+        let total: usize = moves.iter().map(|(_, weight)| *weight as usize).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = random.below(total);
+        for (turn, weight) in moves.into_iter() {
+            if pick < weight as usize {
+                return Some(turn);
+            }
+            pick -= weight as usize;
+        }
+        return None;
+    }
+
+    pub fn save(&mut self, path: &Path) -> Result<(), String> {
+        self.sort_pending();
+        let mut bytes = Vec::with_capacity(self.records.len() * RECORD_SIZE);
+        bytes.extend_from_slice(FILE_MAGIC);
+        bytes.push(FILE_VERSION);
+        let rules = serde_json::to_string(&self.rules).map_err(|e| e.to_string())?;
+        bytes.extend_from_slice(&(rules.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(rules.as_bytes());
+        bytes.extend_from_slice(&(self.records.len() as u32).to_le_bytes());
+        for record in self.records.iter() {
+            bytes.extend_from_slice(&record.hash.to_le_bytes());
+            bytes.push(record.from);
+            bytes.push(record.to);
+            bytes.extend_from_slice(&record.captures.to_le_bytes());
+            bytes.extend_from_slice(&record.weight.to_le_bytes());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        return fs::write(path, bytes).map_err(|e| e.to_string());
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let mut at = 0;
+        let mut take = |count: usize| -> Result<&[u8], String> {
+            if at + count > bytes.len() {
+                return Err(String::from("Book file is cut short"));
+            }
+            at += count;
+            return Ok(&bytes[(at - count)..at]);
+        };
+        if take(4)? != FILE_MAGIC || take(1)?[0] != FILE_VERSION {
+            return Err(String::from("Not a book file of this version"));
+        }
+        let rules_len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+        let rules: RulesConfig = serde_json::from_slice(take(rules_len)?).map_err(|e| e.to_string())?;
+        let count = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let records = take(count * RECORD_SIZE)?.chunks(RECORD_SIZE).map(|r| BookRecord {
+            hash: u64::from_le_bytes(r[0..8].try_into().unwrap()),
+            from: r[8],
+            to: r[9],
+            captures: u32::from_le_bytes(r[10..14].try_into().unwrap()),
+            weight: u16::from_le_bytes([r[14], r[15]])
+        }).collect();
+        return Ok(OpeningBook { rules, records, pending: HashMap::new() });
+    }
+}
+
+
+pub fn book_path(rules: &RulesConfig) -> PathBuf {
+    return save_dir().join(format!("{}{}.bin", FILE_PREFIX, rules.name().to_lowercase().replace(' ', "_")));
+}
+
+
+static LOADED: OnceLock<Vec<OpeningBook>> = OnceLock::new();
+
+
+// Every book in the save folder, read the first time the AI moves
+fn loaded() -> &'static Vec<OpeningBook> {
+    return LOADED.get_or_init(|| {
+        let mut books = Vec::new();
+        if let Ok(entries) = fs::read_dir(save_dir()) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with(FILE_PREFIX) {
+                    match OpeningBook::load(&entry.path()) {
+                        Ok(book) => books.push(book),
+                        Err(e) => info!("Could not load {}: {}", name, e)
+                    }
+                }
+            }
+        }
+        return books;
+    });
+}
+
+
+pub fn book_move(state: &CheckersState) -> Option<Vec<Move>> {
+    let mut random = Random::new();
+    return loaded().iter().find_map(|book| book.choose(state, &mut random));
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rules::Variant, notation::{from_fen, square_position}};
+
+    #[test]
+    fn book_survives_saving() {
+        let rules = Variant::American.rules();
+        let mut book = OpeningBook::new(rules);
+        book.add_analysis(2, 2, 10.);
+        let path = std::env::temp_dir().join("checkers_book_test.bin");
+        book.save(&path).unwrap();
+        let loaded = OpeningBook::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let start = CheckersState::new(rules);
+        assert!(book.moves(&start).len() > 0);
+        assert_eq!(loaded.moves(&start), book.moves(&start));
+    }
+
+    #[test]
+    fn captures_are_told_apart_by_what_they_take() {
+        let state = from_fen("B:W14,23,32:B9,1", Variant::American.rules()).unwrap();
+        let turn = state.get_possible_moves().remove(0);
+        let (from, to, captures) = turn_key(&state, &turn);
+        assert_eq!((from, to), (9, 27));
+        assert_ne!(captures, 0);
+        let start = CheckersState::new(state.rules);
+        let step = Move { from: square_position(11, &start.rules).unwrap(), to: square_position(15, &start.rules).unwrap() };
+        assert_eq!(turn_key(&start, &vec![step]), (11, 15, 0));
+    }
+}
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
-use crate::{logic::{Move, Position}, state::PieceColor};
+use serde::{Serialize, Deserialize};
+use crate::{logic::{Move, Position}, state::{PieceColor, CheckersPiece}};
 
 pub struct CheckersEventsPlugin;
 
@@ -7,8 +8,12 @@ impl Plugin for CheckersEventsPlugin {
     fn build(&self, app: &mut App){
         app
         .add_event::<ButtonSelectEvent>()
+        .add_event::<SaveGameEvent>()
+        .add_event::<ReplayStepEvent>()
+        .add_event::<RedrawPiecesEvent>()
+        .add_event::<EditSquareEvent>()
         .add_event::<InvalidMoveEvent>()
-        .add_event::<VictoryEvent>()
+        .add_event::<GameOverEvent>()
         .add_event::<HighlightEntityEvent>()
         .add_event::<RemoveHighlightEntityEvent>()
         .add_event::<UpgradePieceEvent>()
@@ -35,7 +40,8 @@ pub struct TryMoveEvent{
 }
 
 pub struct PieceMoveEvent{
-    pub game_move: Move
+    pub game_move: Move,
+    pub is_capture: bool
 }
 
 pub struct KillPieceEvent {
@@ -57,12 +63,44 @@ pub struct UpgradePieceEvent {
 }
 
 
+// A board square clicked in the position editor
+pub struct EditSquareEvent {
+    pub pos: Position
+}
+
 
-pub struct VictoryEvent {
-    pub winner: PieceColor
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GameOverReason {
+    NoMoves,
+    FlagFall,
+    Resignation,
+    AgreedDraw,
+    // drawn by the tournament runner after a repetition or too many moves
+    Adjudication
+}
+
+
+// Winner is None when the game is drawn
+pub struct GameOverEvent {
+    pub winner: Option<PieceColor>,
+    pub reason: GameOverReason
 }
 
 pub struct ButtonSelectEvent;
 
+pub struct SaveGameEvent;
+
+
+// One step of a replay, reverse undoes the step
+pub struct ReplayStepEvent {
+    pub game_move: Move,
+    pub captured: Option<(Position, CheckersPiece)>,
+    pub upgraded: bool,
+    pub reverse: bool
+}
+
+pub struct RedrawPiecesEvent;
+
 pub struct InvalidMoveEvent;
 
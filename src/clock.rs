@@ -0,0 +1,358 @@
+use bevy::prelude::*;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use crate::{
+    state::{GameState, CheckersState, PieceColor},
+    checkers_events::{GameOverEvent, GameOverReason}
+};
+
+
+pub struct CheckersClockPlugin;
+
+
+impl Plugin for CheckersClockPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(GameClock::new(TimeControl::Unlimited))
+        .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(reset_clock))
+        .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(reset_clock))
+        .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(reset_clock))
+        .add_system_set(SystemSet::on_enter(GameState::BoardSetup).with_system(setup_clock))
+        .add_system(tick_clock)
+        .add_system(update_clock_display.after(tick_clock));
+    }
+}
+
+
+// Number of moves the AI assumes are left when the time control does not say
+const AI_EXPECTED_MOVES: u32 = 25;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TimeControl {
+    Unlimited,
+    // Whole game must be played within base time
+    SuddenDeath { base: Duration },
+    // Increment is added after every completed move
+    Fischer { base: Duration, increment: Duration },
+    // Clock only starts running after the delay has passed each turn
+    Delay { base: Duration, delay: Duration },
+    // Base time is added again after every `moves` completed moves
+    MovesPerPeriod { base: Duration, moves: u32 }
+}
+
+
+impl TimeControl {
+    pub const PRESETS: [TimeControl; 6] = [
+        TimeControl::Unlimited,
+        TimeControl::SuddenDeath { base: Duration::from_secs(5 * 60) },
+        TimeControl::Fischer { base: Duration::from_secs(3 * 60), increment: Duration::from_secs(2) },
+        TimeControl::Fischer { base: Duration::from_secs(10 * 60), increment: Duration::from_secs(5) },
+        TimeControl::Delay { base: Duration::from_secs(5 * 60), delay: Duration::from_secs(3) },
+        TimeControl::MovesPerPeriod { base: Duration::from_secs(10 * 60), moves: 20 }
+    ];
+
+    pub fn base(&self) -> Option<Duration> {
+        match *self {
+            TimeControl::Unlimited => None,
+            TimeControl::SuddenDeath { base } => Some(base),
+            TimeControl::Fischer { base, .. } => Some(base),
+            TimeControl::Delay { base, .. } => Some(base),
+            TimeControl::MovesPerPeriod { base, .. } => Some(base)
+        }
+    }
+
+    // Cycle through the presets, used by the menu
+    pub fn next_preset(&self) -> TimeControl {
+        let idx = Self::PRESETS.iter().position(|tc| tc == self).unwrap_or(0);
+        return Self::PRESETS[(idx + 1) % Self::PRESETS.len()];
+    }
+
+    pub fn label(&self) -> String {
+        match *self {
+            TimeControl::Unlimited => String::from("No Clock"),
+            TimeControl::SuddenDeath { base } => format!("{} min", base.as_secs() / 60),
+            TimeControl::Fischer { base, increment } => format!("{} + {}", base.as_secs() / 60, increment.as_secs()),
+            TimeControl::Delay { base, delay } => format!("{} min delay {}", base.as_secs() / 60, delay.as_secs()),
+            TimeControl::MovesPerPeriod { base, moves } => format!("{} / {} min", moves, base.as_secs() / 60)
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PlayerClock {
+    pub remaining: Duration,
+    pub moves: u32,
+    pub turn_elapsed: Duration
+}
+
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct GameClock {
+    pub control: TimeControl,
+    pub black: PlayerClock,
+    pub red: PlayerClock,
+    pub running: Option<PieceColor>,
+    pub flagged: Option<PieceColor>
+}
+
+
+impl GameClock {
+    pub fn new(control: TimeControl) -> Self {
+        let player_clock = PlayerClock {
+            remaining: control.base().unwrap_or(Duration::ZERO),
+            moves: 0,
+            turn_elapsed: Duration::ZERO
+        };
+        return GameClock { control, black: player_clock, red: player_clock, running: None, flagged: None };
+    }
+
+    pub fn reset(&mut self) {
+        *self = GameClock::new(self.control);
+    }
+
+    pub fn is_timed(&self) -> bool {
+        return self.control != TimeControl::Unlimited;
+    }
+
+    pub fn clock(&self, player: PieceColor) -> &PlayerClock {
+        match player {
+            PieceColor::Black => &self.black,
+            PieceColor::Red => &self.red
+        }
+    }
+
+    fn clock_mut(&mut self, player: PieceColor) -> &mut PlayerClock {
+        match player {
+            PieceColor::Black => &mut self.black,
+            PieceColor::Red => &mut self.red
+        }
+    }
+
+    // Run the player's clock, returns true if the flag fell
+    pub fn tick(&mut self, player: PieceColor, delta: Duration) -> bool {
+        if !self.is_timed() || self.flagged.is_some() {
+            return false;
+        }
+        let delay = match self.control {
+            TimeControl::Delay { delay, .. } => delay,
+            _ => Duration::ZERO
+        };
+        let clock = self.clock_mut(player);
+        let previous_elapsed = clock.turn_elapsed;
+        clock.turn_elapsed += delta;
+
+        // only the part of this tick which is past the delay counts
+        let charged = clock.turn_elapsed.saturating_sub(delay.max(previous_elapsed));
+        clock.remaining = clock.remaining.saturating_sub(charged);
+        if clock.remaining.is_zero() {
+            self.flagged = Some(player);
+            return true;
+        }
+        return false;
+    }
+
+    // Called once the player has completed a move
+    pub fn complete_move(&mut self, player: PieceColor) {
+        let control = self.control;
+        let clock = self.clock_mut(player);
+        clock.moves += 1;
+        clock.turn_elapsed = Duration::ZERO;
+        match control {
+            TimeControl::Fischer { increment, .. } => {
+                clock.remaining += increment;
+            },
+            TimeControl::MovesPerPeriod { base, moves } => {
+                if clock.moves % moves == 0 {
+                    clock.remaining += base;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    // Time the AI is allowed to think for its next move, None if there is no clock
+    pub fn ai_budget(&self, player: PieceColor) -> Option<Duration> {
+        if !self.is_timed() {
+            return None;
+        }
+        let clock = self.clock(player);
+        let (moves_to_go, bonus) = match self.control {
+            TimeControl::Fischer { increment, .. } => (AI_EXPECTED_MOVES, increment.mul_f32(0.8)),
+            TimeControl::Delay { delay, .. } => (AI_EXPECTED_MOVES, delay),
+            TimeControl::MovesPerPeriod { moves, .. } => (moves - clock.moves % moves, Duration::ZERO),
+            _ => (AI_EXPECTED_MOVES, Duration::ZERO)
+        };
+        let budget = clock.remaining / moves_to_go + bonus;
+        return Some(budget.min(clock.remaining / 2));
+    }
+}
+
+
+#[derive(Component)]
+struct ClockDisplay;
+
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs < 10 {
+        return format!("{}.{}", secs, d.subsec_millis() / 100);
+    }
+    return format!("{:02}:{:02}", secs / 60, secs % 60);
+}
+
+
+// Loading a saved game replaces the clock after this runs
+fn reset_clock(mut clock: ResMut<GameClock>) {
+    clock.reset();
+}
+
+
+fn setup_clock(mut commands: Commands, clock: Res<GameClock>, asset_server: Res<AssetServer>, query: Query<Entity, With<ClockDisplay>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !clock.is_timed() {
+        return;
+    }
+
+    commands.spawn(
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/Pixeboy.ttf"),
+                font_size: 40.0,
+                color: Color::rgb(0.8, 0.8, 0.8)
+            }
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { top: Val::Px(20.), left: Val::Px(20.), ..default() },
+            ..default()
+        })
+    ).insert(ClockDisplay);
+}
+
+
+// Clocks only run while a player is thinking, so animations are not charged to anyone
+fn tick_clock(
+    time: Res<Time>,
+    mut game_state: ResMut<State<GameState>>,
+    checkers_state: Res<CheckersState>,
+    mut clock: ResMut<GameClock>,
+    mut game_over_writer: EventWriter<GameOverEvent>
+){
+    match game_state.current() {
+        GameState::Input | GameState::RestrictedInput | GameState::AIMove => {},
+        _ => return
+    }
+
+    if clock.running != Some(checkers_state.turn) {
+        if let Some(previous) = clock.running {
+            clock.complete_move(previous);
+        }
+        clock.running = Some(checkers_state.turn);
+    }
+
+    if clock.tick(checkers_state.turn, time.delta()) {
+        info!("{:?} flagged", checkers_state.turn);
+        game_over_writer.send(GameOverEvent { winner: Some(checkers_state.turn.opponent()), reason: GameOverReason::FlagFall });
+        game_state.overwrite_set(GameState::GameOver).unwrap();
+    }
+}
+
+
+fn update_clock_display(clock: Res<GameClock>, mut query: Query<&mut Text, With<ClockDisplay>>) {
+    if !clock.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Black {}   Red {}",
+            format_duration(clock.black.remaining),
+            format_duration(clock.red.remaining)
+        );
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        return Duration::from_secs(s);
+    }
+
+    #[test]
+    fn delay_is_not_charged() {
+        let mut clock = GameClock::new(TimeControl::Delay { base: secs(60), delay: secs(3) });
+        assert!(!clock.tick(PieceColor::Black, secs(2)));
+        assert_eq!(clock.black.remaining, secs(60));
+        // the tick crossing the delay is only charged for the part past it
+        clock.tick(PieceColor::Black, secs(2));
+        assert_eq!(clock.black.remaining, secs(59));
+        clock.tick(PieceColor::Black, secs(5));
+        assert_eq!(clock.black.remaining, secs(54));
+        // the delay starts again on the next turn
+        clock.complete_move(PieceColor::Black);
+        clock.tick(PieceColor::Black, secs(3));
+        assert_eq!(clock.black.remaining, secs(54));
+        assert_eq!(clock.red.remaining, secs(60));
+    }
+
+    #[test]
+    fn increment_after_every_move() {
+        let mut clock = GameClock::new(TimeControl::Fischer { base: secs(60), increment: secs(2) });
+        clock.tick(PieceColor::Red, secs(10));
+        clock.complete_move(PieceColor::Red);
+        assert_eq!(clock.red.remaining, secs(52));
+        assert_eq!(clock.red.moves, 1);
+        assert_eq!(clock.red.turn_elapsed, Duration::ZERO);
+    }
+
+    #[test]
+    fn base_time_again_each_period() {
+        let mut clock = GameClock::new(TimeControl::MovesPerPeriod { base: secs(60), moves: 3 });
+        for _ in 0..2 {
+            clock.tick(PieceColor::Black, secs(10));
+            clock.complete_move(PieceColor::Black);
+        }
+        assert_eq!(clock.black.remaining, secs(40));
+        clock.tick(PieceColor::Black, secs(10));
+        clock.complete_move(PieceColor::Black);
+        assert_eq!(clock.black.remaining, secs(90));
+    }
+
+    #[test]
+    fn flag_falls_once() {
+        let mut clock = GameClock::new(TimeControl::SuddenDeath { base: secs(5) });
+        assert!(!clock.tick(PieceColor::Black, secs(4)));
+        assert!(clock.tick(PieceColor::Black, secs(2)));
+        assert_eq!(clock.flagged, Some(PieceColor::Black));
+        assert_eq!(clock.black.remaining, Duration::ZERO);
+        // nothing runs after the flag has fallen
+        assert!(!clock.tick(PieceColor::Red, secs(10)));
+        assert_eq!(clock.red.remaining, secs(5));
+
+        let mut unlimited = GameClock::new(TimeControl::Unlimited);
+        assert!(!unlimited.tick(PieceColor::Black, secs(1000)));
+    }
+
+    #[test]
+    fn ai_budget_spreads_the_remaining_time() {
+        assert_eq!(GameClock::new(TimeControl::Unlimited).ai_budget(PieceColor::Red), None);
+        let sudden = GameClock::new(TimeControl::SuddenDeath { base: secs(250) });
+        assert_eq!(sudden.ai_budget(PieceColor::Red), Some(secs(10)));
+        let fischer = GameClock::new(TimeControl::Fischer { base: secs(250), increment: secs(5) });
+        assert_eq!(fischer.ai_budget(PieceColor::Red), Some(secs(14)));
+        let mut period = GameClock::new(TimeControl::MovesPerPeriod { base: secs(60), moves: 4 });
+        period.complete_move(PieceColor::Red);
+        assert_eq!(period.ai_budget(PieceColor::Red), Some(secs(20)));
+        // never more than half of what is left
+        let mut short = GameClock::new(TimeControl::Delay { base: secs(4), delay: secs(3) });
+        short.tick(PieceColor::Red, secs(3));
+        assert_eq!(short.ai_budget(PieceColor::Red), Some(secs(2)));
+    }
+}
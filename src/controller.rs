@@ -0,0 +1,302 @@
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{Arc, Mutex, mpsc::{self, Receiver, RecvTimeoutError}},
+    thread,
+    time::{Duration, Instant}
+};
+use crate::{
+    state::CheckersState,
+    logic::Move,
+    ai::{find_best_moves, find_best_moves_mcts, MCTS_PLAYOUTS},
+    hub::{parse_command, to_hub_position, resolve_hub_move},
+    notation::moves_to_string,
+    book::book_move,
+    dxp::DxpOpponent
+};
+
+
+// Time an external engine gets per move when the game is not on the clock
+const EXTERNAL_MOVE_TIME: Duration = Duration::from_secs(2);
+// How long an engine has to answer a command, and how far past its budget it can go for a move
+const ENGINE_REPLY_TIME: Duration = Duration::from_secs(5);
+
+
+// Something that picks the moves for one side, the built in search or another program
+pub trait PlayerController: Send + Sync {
+    fn name(&self) -> String;
+    // Blocks until a whole turn is chosen, with a budget if the game is on the clock
+    fn choose_moves(&self, state: &CheckersState, budget: Option<Duration>) -> Result<Vec<Move>, String>;
+    // Enough to set the same controller up again
+    fn settings(&self) -> ControllerSettings;
+    // How the built in search is set up, None for other programs
+    fn search_settings(&self) -> Option<SearchSettings> {
+        match self.settings() {
+            ControllerSettings::BuiltIn(settings) => return Some(settings),
+            _ => return None
+        }
+    }
+}
+
+
+// Choices the menu can change for the built in search
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SearchSettings {
+    // Monte Carlo tree search instead of alpha-beta
+    pub mcts: bool,
+    pub use_book: bool
+}
+
+
+// What a controller is, kept in saved games so a loaded game goes on against the same opponent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ControllerSettings {
+    BuiltIn(SearchSettings),
+    ExternalEngine { path: String, args: Vec<String> },
+    DxpOpponent { address: String }
+}
+
+
+// The controller the AI side uses, picked on the command line
+#[derive(Resource, Clone)]
+pub struct AIController(pub Arc<dyn PlayerController>);
+
+
+impl AIController {
+    // --external-engine <path> plays the AI side with a Hub engine instead of the built in search
+    pub fn from_args() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        if let Some(idx) = args.iter().position(|a| a == "--external-engine") {
+            if let Some(path) = args.get(idx + 1) {
+                match ExternalEngine::launch(path, &args[(idx + 2)..].iter().take_while(|a| !a.starts_with("--")).cloned().collect::<Vec<String>>()) {
+                    Ok(engine) => return AIController(Arc::new(engine)),
+                    Err(e) => info!("Could not start engine {}: {}", path, e)
+                }
+            }
+        }
+        // --dxp-opponent <host:port> plays the AI side with an engine at the other end of a DXP connection
+        if let Some(idx) = args.iter().position(|a| a == "--dxp-opponent") {
+            if let Some(address) = args.get(idx + 1) {
+                match DxpOpponent::connect(address) {
+                    Ok(opponent) => return AIController(Arc::new(opponent)),
+                    Err(e) => info!("Could not connect to {}: {}", address, e)
+                }
+            }
+        }
+        // --no-book makes the built in search think about every move, the opening book is used otherwise
+        let use_book = !args.iter().any(|a| a == "--no-book");
+        // --mcts plays with the Monte Carlo tree search instead of alpha-beta
+        let mcts = args.iter().any(|a| a == "--mcts");
+        return AIController::built_in(SearchSettings { mcts, use_book });
+    }
+
+    // Starts the engine or connects to the opponent again for a loaded game
+    pub fn from_settings(settings: &ControllerSettings) -> Result<Self, String> {
+        match settings {
+            ControllerSettings::BuiltIn(settings) => return Ok(AIController::built_in(*settings)),
+            ControllerSettings::ExternalEngine { path, args } => return Ok(AIController(Arc::new(ExternalEngine::launch(path, args)?))),
+            ControllerSettings::DxpOpponent { address } => return Ok(AIController(Arc::new(DxpOpponent::connect(address)?)))
+        }
+    }
+
+    pub fn built_in(settings: SearchSettings) -> Self {
+        if settings.mcts {
+            return AIController(Arc::new(MctsController { use_book: settings.use_book }));
+        }
+        return AIController(Arc::new(SearchController { use_book: settings.use_book }));
+    }
+}
+
+
+// Plays from the opening book when the position is in it, otherwise searches
+pub struct SearchController {
+    pub use_book: bool
+}
+
+
+fn opening_book_move(state: &CheckersState, use_book: bool) -> Option<Vec<Move>> {
+    if !use_book {
+        return None;
+    }
+    let moves = book_move(state)?;
+    info!("Playing {} from the opening book", moves_to_string(state, &moves));
+    return Some(moves);
+}
+
+
+impl PlayerController for SearchController {
+    fn name(&self) -> String {
+        return String::from("Computer");
+    }
+
+    fn choose_moves(&self, state: &CheckersState, budget: Option<Duration>) -> Result<Vec<Move>, String> {
+        if let Some(moves) = opening_book_move(state, self.use_book) {
+            return Ok(moves);
+        }
+        return Ok(find_best_moves(state, budget));
+    }
+
+    fn settings(&self) -> ControllerSettings {
+        return ControllerSettings::BuiltIn(SearchSettings { mcts: false, use_book: self.use_book });
+    }
+}
+
+
+pub struct MctsController {
+    pub use_book: bool
+}
+
+
+impl PlayerController for MctsController {
+    fn name(&self) -> String {
+        return String::from("Computer (MCTS)");
+    }
+
+    fn choose_moves(&self, state: &CheckersState, budget: Option<Duration>) -> Result<Vec<Move>, String> {
+        if let Some(moves) = opening_book_move(state, self.use_book) {
+            return Ok(moves);
+        }
+        return Ok(find_best_moves_mcts(state, budget, MCTS_PLAYOUTS));
+    }
+
+    fn settings(&self) -> ControllerSettings {
+        return ControllerSettings::BuiltIn(SearchSettings { mcts: true, use_book: self.use_book });
+    }
+}
+
+
+struct EngineProcess {
+    child: Child,
+    stdin: ChildStdin,
+    // lines the engine writes, read on their own thread so waiting for them can time out
+    lines: Receiver<String>
+}
+
+
+impl EngineProcess {
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        info!("engine <- {}", line);
+        writeln!(self.stdin, "{}", line).map_err(|e| e.to_string())?;
+        return self.stdin.flush().map_err(|e| e.to_string());
+    }
+
+    // A hung engine is told to stop and then shut down, the game goes on with the built in search
+    fn read_line(&mut self, deadline: Instant) -> Result<String, String> {
+        match self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => return Ok(line.trim().to_string()),
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = self.send("stop");
+                let _ = self.child.kill();
+                return Err(String::from("Engine did not answer in time"));
+            },
+            Err(RecvTimeoutError::Disconnected) => return Err(String::from("Engine closed its output"))
+        }
+    }
+
+    // Reads lines until one starts with the given command, returning the line
+    fn wait_for(&mut self, command: &str, timeout: Duration) -> Result<String, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = self.read_line(deadline)?;
+            info!("engine -> {}", line);
+            let (received, args) = parse_command(&line);
+            if received == command {
+                return Ok(line);
+            }
+            // errors about parameters the engine doesn't know are fine, a failed search is not
+            if received == "error" && command == "done" {
+                return Err(args.get("message").cloned().unwrap_or(line));
+            }
+        }
+    }
+}
+
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+
+// An engine program speaking the Hub protocol, started once and asked for every move
+pub struct ExternalEngine {
+    name: String,
+    path: String,
+    args: Vec<String>,
+    process: Mutex<EngineProcess>
+}
+
+
+impl ExternalEngine {
+    pub fn launch(path: &str, args: &[String]) -> Result<Self, String> {
+        let mut child = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        let stdin = child.stdin.take().ok_or("Engine has no input")?;
+        let stdout = BufReader::new(child.stdout.take().ok_or("Engine has no output")?);
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut process = EngineProcess { child, stdin, lines };
+
+        process.send("hub")?;
+        let mut name = String::from(path);
+        let deadline = Instant::now() + ENGINE_REPLY_TIME;
+        loop {
+            let line = process.read_line(deadline)?;
+            let (command, args) = parse_command(&line);
+            if command == "id" {
+                if let Some(id) = args.get("name") {
+                    name = id.clone();
+                }
+            } else if command == "wait" {
+                break;
+            }
+        }
+        process.send("init")?;
+        process.wait_for("ready", ENGINE_REPLY_TIME)?;
+        info!("Started engine {}", name);
+        return Ok(ExternalEngine { name, path: path.to_string(), args: args.to_vec(), process: Mutex::new(process) });
+    }
+}
+
+
+impl PlayerController for ExternalEngine {
+    fn name(&self) -> String {
+        return self.name.clone();
+    }
+
+    fn settings(&self) -> ControllerSettings {
+        return ControllerSettings::ExternalEngine { path: self.path.clone(), args: self.args.clone() };
+    }
+
+    fn choose_moves(&self, state: &CheckersState, budget: Option<Duration>) -> Result<Vec<Move>, String> {
+        let mut process = self.process.lock().map_err(|e| e.to_string())?;
+        process.send(&format!("set-param name=variant value={}", state.rules.variant.name().to_lowercase()))?;
+        // engines without the parameter answer with an error, which has to be read before the search starts
+        process.send("ping")?;
+        process.wait_for("pong", ENGINE_REPLY_TIME)?;
+        process.send(&format!("pos pos={}", to_hub_position(state)))?;
+        let move_time = budget.unwrap_or(EXTERNAL_MOVE_TIME);
+        process.send(&format!("level move-time={:.2}", move_time.as_secs_f32()))?;
+        process.send("go think")?;
+        let done = process.wait_for("done", move_time + ENGINE_REPLY_TIME)?;
+        let (_, args) = parse_command(&done);
+        let token = args.get("move").ok_or("Engine did not give a move")?;
+        let moves = resolve_hub_move(state, token)?;
+        info!("{} plays {}", self.name, moves_to_string(state, &moves));
+        return Ok(moves);
+    }
+}
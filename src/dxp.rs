@@ -0,0 +1,611 @@
+use bevy::prelude::*;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    time::Duration
+};
+use crate::{
+    state::{CheckersState, CheckersPiece, PieceColor, PieceType},
+    rules::{RulesConfig, Variant},
+    logic::Move,
+    notation::{square_number, square_position, moves_to_string, to_fen},
+    alphabeta::TwoPlayerGameState,
+    ai::find_best_moves,
+    controller::{PlayerController, ControllerSettings}
+};
+
+
+// DamExchange protocol for playing 10x10 games over TCP. Messages are plain text ended by a
+// zero byte, the first character says what the message is, fields have fixed widths.
+
+const DXP_VERSION: &str = "01";
+const NAME_WIDTH: usize = 32;
+const ENGINE_NAME: &str = "Checkers";
+pub const DEFAULT_PORT: &str = "27531";
+// Thinking time offered in a game request, for the whole game
+const DEFAULT_MINUTES: u32 = 10;
+const DEFAULT_MOVES: u32 = 75;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEndReason {
+    Unknown,
+    // from the point of view of the side sending the message
+    Loss,
+    Draw,
+    Win
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DxpMessage {
+    GameRequest { name: String, follower_color: PieceColor, minutes: u32, moves: u32, position: Option<(PieceColor, String)> },
+    // code 0 accepts, anything else declines
+    GameAccept { name: String, code: u8 },
+    Move { time: u32, from: usize, to: usize, captured: Vec<usize> },
+    // the stop code asks for no further games when it is 1
+    GameEnd { reason: GameEndReason, stop_code: u8 },
+    Chat(String),
+    BackRequest { move_number: u32, color: PieceColor },
+    // code 0 accepts, 1 means backing up is not supported, 2 declines
+    BackAccept { code: u8 }
+}
+
+
+fn color_char(color: PieceColor) -> char {
+    match color {
+        PieceColor::Red => 'W',
+        PieceColor::Black => 'Z'
+    }
+}
+
+
+fn parse_color(c: char) -> Result<PieceColor, String> {
+    match c {
+        'W' | 'w' => Ok(PieceColor::Red),
+        'Z' | 'z' => Ok(PieceColor::Black),
+        c => Err(format!("Invalid color {}", c))
+    }
+}
+
+
+fn number(text: &str, field: &str) -> Result<u32, String> {
+    return text.trim().parse().map_err(|_| format!("Invalid {} {}", field, text));
+}
+
+
+impl DxpMessage {
+    pub fn to_wire(&self) -> String {
+        match self {
+            DxpMessage::GameRequest { name, follower_color, minutes, moves, position } => {
+                let mut text = format!("R{}{:<width$.width$}{}{:03}{:03}", DXP_VERSION, name, color_char(*follower_color), minutes, moves, width = NAME_WIDTH);
+                match position {
+                    Some((turn, squares)) => text.push_str(&format!("B{}{}", color_char(*turn), squares)),
+                    None => text.push('A')
+                }
+                return text;
+            },
+            DxpMessage::GameAccept { name, code } => return format!("A{:<width$.width$}{}", name, code, width = NAME_WIDTH),
+            DxpMessage::Move { time, from, to, captured } => {
+                let squares: String = captured.iter().map(|s| format!("{:02}", s)).collect();
+                return format!("M{:04}{:02}{:02}{:02}{}", time, from, to, captured.len(), squares);
+            },
+            DxpMessage::GameEnd { reason, stop_code } => {
+                let reason = match reason {
+                    GameEndReason::Unknown => 0,
+                    GameEndReason::Loss => 1,
+                    GameEndReason::Draw => 2,
+                    GameEndReason::Win => 3
+                };
+                return format!("E{}{}", reason, stop_code);
+            },
+            DxpMessage::Chat(text) => return format!("C{}", text),
+            DxpMessage::BackRequest { move_number, color } => return format!("B{:03}{}", move_number, color_char(*color)),
+            DxpMessage::BackAccept { code } => return format!("K{}", code)
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<DxpMessage, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let field = |start: usize, len: usize| -> Result<String, String> {
+            if chars.len() < start + len {
+                return Err(format!("Message too short: {}", text));
+            }
+            return Ok(chars[start..(start + len)].iter().collect());
+        };
+        match chars.first() {
+            Some('R') => {
+                let name = field(3, NAME_WIDTH)?.trim_end().to_string();
+                let rest = 3 + NAME_WIDTH;
+                let follower_color = parse_color(field(rest, 1)?.chars().next().unwrap())?;
+                let minutes = number(&field(rest + 1, 3)?, "thinking time")?;
+                let moves = number(&field(rest + 4, 3)?, "number of moves")?;
+                let position = match field(rest + 7, 1)?.as_str() {
+                    "B" => {
+                        let turn = parse_color(field(rest + 8, 1)?.chars().next().unwrap())?;
+                        Some((turn, chars[(rest + 9)..].iter().collect()))
+                    },
+                    _ => None
+                };
+                return Ok(DxpMessage::GameRequest { name, follower_color, minutes, moves, position });
+            },
+            Some('A') => {
+                let name = field(1, NAME_WIDTH)?.trim_end().to_string();
+                let code = number(&field(1 + NAME_WIDTH, 1)?, "acceptance code")? as u8;
+                return Ok(DxpMessage::GameAccept { name, code });
+            },
+            Some('M') => {
+                let time = number(&field(1, 4)?, "time")?;
+                let from = number(&field(5, 2)?, "from square")? as usize;
+                let to = number(&field(7, 2)?, "to square")? as usize;
+                let count = number(&field(9, 2)?, "number of captures")? as usize;
+                let mut captured = Vec::new();
+                for idx in 0..count {
+                    captured.push(number(&field(11 + 2 * idx, 2)?, "captured square")? as usize);
+                }
+                return Ok(DxpMessage::Move { time, from, to, captured });
+            },
+            Some('E') => {
+                let reason = match field(1, 1)?.as_str() {
+                    "1" => GameEndReason::Loss,
+                    "2" => GameEndReason::Draw,
+                    "3" => GameEndReason::Win,
+                    _ => GameEndReason::Unknown
+                };
+                let stop_code = number(&field(2, 1)?, "stop code")? as u8;
+                return Ok(DxpMessage::GameEnd { reason, stop_code });
+            },
+            Some('C') => return Ok(DxpMessage::Chat(chars[1..].iter().collect())),
+            Some('B') => {
+                let move_number = number(&field(1, 3)?, "move number")?;
+                let color = parse_color(field(4, 1)?.chars().next().unwrap())?;
+                return Ok(DxpMessage::BackRequest { move_number, color });
+            },
+            Some('K') => return Ok(DxpMessage::BackAccept { code: number(&field(1, 1)?, "acceptance code")? as u8 }),
+            _ => return Err(format!("Unknown message {}", text))
+        }
+    }
+}
+
+
+// The 50 squares of a custom start, w and z for men, W and Z for kings, e for empty
+pub fn to_dxp_position(state: &CheckersState) -> (PieceColor, String) {
+    let squares = state.rules.squares_per_row() * state.rules.board_dim;
+    let mut text = String::new();
+    for num in 1..=squares {
+        let pos = square_position(num, &state.rules).unwrap();
+        text.push(match state.board[pos.row][pos.col] {
+            None => 'e',
+            Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::Man }) => 'w',
+            Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man }) => 'z',
+            Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::King }) => 'W',
+            Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::King }) => 'Z'
+        });
+    }
+    return (state.turn, text);
+}
+
+
+pub fn from_dxp_position(turn: PieceColor, squares: &str, rules: RulesConfig) -> Result<CheckersState, String> {
+    let mut state = CheckersState::new(rules);
+    let chars: Vec<char> = squares.chars().collect();
+    if chars.len() != rules.squares_per_row() * rules.board_dim {
+        return Err(format!("Position has {} squares", chars.len()));
+    }
+    state.turn = turn;
+    for (idx, c) in chars.iter().enumerate() {
+        let pos = square_position(idx + 1, &rules).unwrap();
+        state.board[pos.row][pos.col] = match c {
+            'e' => None,
+            'w' => Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::Man }),
+            'z' => Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man }),
+            'W' => Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::King }),
+            'Z' => Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::King }),
+            c => return Err(format!("Invalid square {} in position", c))
+        };
+    }
+    return Ok(state);
+}
+
+
+// Squares of the pieces a turn captures, in the order they are jumped
+pub fn captured_squares(state: &CheckersState, moves: &Vec<Move>) -> Vec<usize> {
+    let mut captured = Vec::new();
+    let mut current = state.clone();
+    for m in moves.iter() {
+        if let Some(pos) = current.captured_by(m) {
+            captured.push(square_number(&pos, &state.rules));
+        }
+        current.update_with_move(m);
+    }
+    return captured;
+}
+
+
+pub fn move_message(state: &CheckersState, moves: &Vec<Move>, time: u32) -> DxpMessage {
+    return DxpMessage::Move {
+        time,
+        from: square_number(&moves[0].from, &state.rules),
+        to: square_number(&moves[moves.len() - 1].to, &state.rules),
+        captured: captured_squares(state, moves)
+    };
+}
+
+
+// The legal turn a move message stands for, captured squares tell apart captures ending on the same square
+pub fn resolve_dxp_move(state: &CheckersState, from: usize, to: usize, captured: &Vec<usize>) -> Result<Vec<Move>, String> {
+    let mut wanted = captured.clone();
+    wanted.sort();
+    for sequence in state.get_possible_moves() {
+        if square_number(&sequence[0].from, &state.rules) != from || square_number(&sequence[sequence.len() - 1].to, &state.rules) != to {
+            continue;
+        }
+        let mut squares = captured_squares(state, &sequence);
+        squares.sort();
+        if squares == wanted {
+            return Ok(sequence);
+        }
+    }
+    return Err(format!("Illegal move {}-{} capturing {:?}", from, to, captured));
+}
+
+
+pub struct DxpConnection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>
+}
+
+
+impl DxpConnection {
+    pub fn new(stream: TcpStream) -> Result<Self, String> {
+        let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+        return Ok(DxpConnection { stream, reader });
+    }
+
+    pub fn connect(address: &str) -> Result<Self, String> {
+        return DxpConnection::new(TcpStream::connect(address).map_err(|e| e.to_string())?);
+    }
+
+    pub fn send(&mut self, message: &DxpMessage) -> Result<(), String> {
+        let mut bytes = message.to_wire().into_bytes();
+        bytes.push(0);
+        return self.stream.write_all(&bytes).map_err(|e| e.to_string());
+    }
+
+    pub fn receive(&mut self) -> Result<DxpMessage, String> {
+        let mut bytes = Vec::new();
+        if self.reader.read_until(0, &mut bytes).map_err(|e| e.to_string())? == 0 {
+            return Err(String::from("Connection closed"));
+        }
+        if bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        return DxpMessage::parse(&String::from_utf8_lossy(&bytes));
+    }
+}
+
+
+// Turns played so far, kept so the opponent can ask to take some back
+struct GameHistory {
+    states: Vec<CheckersState>
+}
+
+
+impl GameHistory {
+    // Move numbers count a move by each side as one, as in the notation
+    fn back_up(&mut self, move_number: u32, color: PieceColor) -> bool {
+        let first = self.states[0].turn;
+        let idx = (move_number.max(1) as usize - 1) * 2 + if color == first { 0 } else { 1 };
+        if idx >= self.states.len() || self.states[idx].turn != color {
+            return false;
+        }
+        self.states.truncate(idx + 1);
+        return true;
+    }
+
+    fn current(&self) -> &CheckersState {
+        return self.states.last().unwrap();
+    }
+}
+
+
+// Plays one game with the built in search until it ends or the connection drops, returns true
+// if the opponent wants to play on. Progress goes to report, a line at a time.
+fn play_game(conn: &mut DxpConnection, start: CheckersState, color: PieceColor, minutes: u32, moves: u32, report: &mut dyn FnMut(String)) -> Result<bool, String> {
+    let budget = Duration::from_secs(minutes as u64 * 60 / moves.max(1) as u64);
+    let mut history = GameHistory { states: vec![start] };
+    loop {
+        let state = history.current().clone();
+        if state.is_game_over() {
+            let reason = match state.get_winner() {
+                Some(winner) if winner == color => GameEndReason::Win,
+                Some(_) => GameEndReason::Loss,
+                None => GameEndReason::Unknown
+            };
+            report(format!("Game over, {:?}", reason));
+            conn.send(&DxpMessage::GameEnd { reason, stop_code: 0 })?;
+            // the opponent answers with its own game end
+            loop {
+                if let DxpMessage::GameEnd { stop_code, .. } = conn.receive()? {
+                    return Ok(stop_code == 0);
+                }
+            }
+        }
+        if state.turn == color {
+            let turn = find_best_moves(&state, Some(budget));
+            report(format!("Playing {}", moves_to_string(&state, &turn)));
+            conn.send(&move_message(&state, &turn, budget.as_secs() as u32))?;
+            history.states.push(state.next_state_with_move(&turn));
+            continue;
+        }
+        match conn.receive()? {
+            DxpMessage::Move { from, to, captured, .. } => {
+                let turn = resolve_dxp_move(&state, from, to, &captured)?;
+                report(format!("Opponent played {}", moves_to_string(&state, &turn)));
+                history.states.push(state.next_state_with_move(&turn));
+            },
+            DxpMessage::GameEnd { reason, stop_code } => {
+                report(format!("Opponent ended the game, {:?} for them", reason));
+                conn.send(&DxpMessage::GameEnd { reason: GameEndReason::Unknown, stop_code })?;
+                return Ok(stop_code == 0);
+            },
+            DxpMessage::Chat(text) => report(format!("Opponent says: {}", text)),
+            DxpMessage::BackRequest { move_number, color: to_move } => {
+                let code = if history.back_up(move_number, to_move) { 0 } else { 2 };
+                report(format!("Opponent asked to back up to move {}, answered {}", move_number, code));
+                conn.send(&DxpMessage::BackAccept { code })?;
+            },
+            message => report(format!("Ignoring {:?}", message))
+        }
+    }
+}
+
+
+// Waits for opponents on the port and plays whatever game they ask for, --dxp-server <port>
+pub fn run_server(port: &str, report: &mut dyn FnMut(String)) -> Result<(), String> {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).map_err(|e| e.to_string())?;
+    report(format!("Waiting for DXP games on port {}", port));
+    return serve(listener, report);
+}
+
+
+// Plays the games asked for by every connection the listener accepts, one connection at a time
+pub fn serve(listener: TcpListener, report: &mut dyn FnMut(String)) -> Result<(), String> {
+    for stream in listener.incoming() {
+        let mut conn = DxpConnection::new(stream.map_err(|e| e.to_string())?)?;
+        loop {
+            let (name, color, minutes, moves, position) = match conn.receive() {
+                Ok(DxpMessage::GameRequest { name, follower_color, minutes, moves, position }) => (name, follower_color, minutes, moves, position),
+                Ok(DxpMessage::Chat(text)) => {
+                    report(format!("Opponent says: {}", text));
+                    continue;
+                },
+                Ok(message) => {
+                    report(format!("Expected a game request, got {:?}", message));
+                    continue;
+                },
+                Err(e) => {
+                    report(e);
+                    break;
+                }
+            };
+            let rules = Variant::International.rules();
+            let start = match position {
+                Some((turn, squares)) => from_dxp_position(turn, &squares, rules),
+                None => Ok(CheckersState::new(rules))
+            };
+            let start = match start {
+                Ok(start) => start,
+                Err(e) => {
+                    report(format!("Declining game from {}: {}", name, e));
+                    conn.send(&DxpMessage::GameAccept { name: String::from(ENGINE_NAME), code: 3 })?;
+                    continue;
+                }
+            };
+            report(format!("Playing {:?} against {}", color, name));
+            conn.send(&DxpMessage::GameAccept { name: String::from(ENGINE_NAME), code: 0 })?;
+            match play_game(&mut conn, start, color, minutes, moves, report) {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(e) => {
+                    report(e);
+                    break;
+                }
+            }
+        }
+    }
+    return Ok(());
+}
+
+
+// Asks the engine at the address for a game, playing White, --dxp-client <host:port>
+pub fn run_client(address: &str, report: &mut dyn FnMut(String)) -> Result<(), String> {
+    let mut conn = DxpConnection::connect(address)?;
+    let rules = Variant::International.rules();
+    let color = PieceColor::Red;
+    conn.send(&DxpMessage::GameRequest {
+        name: String::from(ENGINE_NAME), follower_color: color.opponent(), minutes: DEFAULT_MINUTES, moves: DEFAULT_MOVES, position: None
+    })?;
+    match conn.receive()? {
+        DxpMessage::GameAccept { name, code: 0 } => report(format!("{} accepted the game", name)),
+        DxpMessage::GameAccept { name, code } => return Err(format!("{} declined the game with code {}", name, code)),
+        message => return Err(format!("Expected the game to be accepted, got {:?}", message))
+    }
+    play_game(&mut conn, CheckersState::new(rules), color, DEFAULT_MINUTES, DEFAULT_MOVES, report)?;
+    return Ok(());
+}
+
+
+struct OpponentConnection {
+    conn: DxpConnection,
+    // position after the opponent's last move, None until a game has been requested
+    last_state: Option<CheckersState>
+}
+
+
+// A DXP engine on the other end of a connection playing the AI side against a human, --dxp-opponent <host:port>
+pub struct DxpOpponent {
+    name: String,
+    address: String,
+    connection: Mutex<OpponentConnection>
+}
+
+
+impl DxpOpponent {
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let conn = DxpConnection::connect(address)?;
+        return Ok(DxpOpponent { name: String::from(address), address: String::from(address), connection: Mutex::new(OpponentConnection { conn, last_state: None }) });
+    }
+}
+
+
+impl PlayerController for DxpOpponent {
+    fn name(&self) -> String {
+        return self.name.clone();
+    }
+
+    fn settings(&self) -> ControllerSettings {
+        return ControllerSettings::DxpOpponent { address: self.address.clone() };
+    }
+
+    fn choose_moves(&self, state: &CheckersState, budget: Option<Duration>) -> Result<Vec<Move>, String> {
+        if state.rules.board_dim != 10 {
+            return Err(String::from("DXP games are played on a 10x10 board"));
+        }
+        let mut connection = self.connection.lock().map_err(|e| e.to_string())?;
+        let mut in_game = false;
+        if let Some(last) = connection.last_state.take() {
+            // pass on the human's move, the turn that leads from the last position to this one
+            match last.get_possible_moves().into_iter().find(|turn| to_fen(&last.next_state_with_move(turn)) == to_fen(state)) {
+                Some(played) => {
+                    let time = budget.map(|b| b.as_secs() as u32).unwrap_or(0);
+                    connection.conn.send(&move_message(&last, &played, time))?;
+                    in_game = true;
+                },
+                // no move leads here, so a new game has started and the old one is ended first
+                None => {
+                    info!("Ending the game with {} for a new one", self.name);
+                    connection.conn.send(&DxpMessage::GameEnd { reason: GameEndReason::Unknown, stop_code: 0 })?;
+                    while !matches!(connection.conn.receive()?, DxpMessage::GameEnd { .. }) {}
+                }
+            }
+        }
+        // the game is requested from the position the opponent first has to move in
+        if !in_game {
+            let position = match to_fen(state) == to_fen(&CheckersState::new(state.rules)) {
+                true => None,
+                false => Some(to_dxp_position(state))
+            };
+            connection.conn.send(&DxpMessage::GameRequest {
+                name: String::from(ENGINE_NAME), follower_color: state.turn, minutes: DEFAULT_MINUTES, moves: DEFAULT_MOVES, position
+            })?;
+            match connection.conn.receive()? {
+                DxpMessage::GameAccept { name, code: 0 } => info!("{} accepted the game", name),
+                DxpMessage::GameAccept { code, .. } => return Err(format!("Game declined with code {}", code)),
+                message => return Err(format!("Expected the game to be accepted, got {:?}", message))
+            }
+        }
+        loop {
+            match connection.conn.receive()? {
+                DxpMessage::Move { from, to, captured, .. } => {
+                    let turn = resolve_dxp_move(state, from, to, &captured)?;
+                    connection.last_state = Some(state.next_state_with_move(&turn));
+                    return Ok(turn);
+                },
+                DxpMessage::Chat(text) => info!("{} says: {}", self.name, text),
+                DxpMessage::BackRequest { .. } => connection.conn.send(&DxpMessage::BackAccept { code: 1 })?,
+                // the next move asks for a new game
+                DxpMessage::GameEnd { reason, stop_code } => {
+                    connection.conn.send(&DxpMessage::GameEnd { reason: GameEndReason::Unknown, stop_code })?;
+                    return Err(format!("{} ended the game, {:?}", self.name, reason));
+                },
+                message => info!("Ignoring {:?}", message)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn round_trip(message: DxpMessage) {
+        assert_eq!(DxpMessage::parse(&message.to_wire()), Ok(message));
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        round_trip(DxpMessage::GameRequest { name: String::from("Opponent"), follower_color: PieceColor::Black, minutes: 10, moves: 75, position: None });
+        let (turn, squares) = to_dxp_position(&CheckersState::new(Variant::International.rules()));
+        round_trip(DxpMessage::GameRequest { name: String::from("Opponent"), follower_color: PieceColor::Red, minutes: 5, moves: 50, position: Some((turn, squares)) });
+        round_trip(DxpMessage::GameAccept { name: String::from("Opponent"), code: 0 });
+        round_trip(DxpMessage::Move { time: 12, from: 32, to: 21, captured: vec![] });
+        round_trip(DxpMessage::Move { time: 3, from: 46, to: 19, captured: vec![41, 32, 23] });
+        round_trip(DxpMessage::GameEnd { reason: GameEndReason::Win, stop_code: 1 });
+        round_trip(DxpMessage::Chat(String::from("good game")));
+        round_trip(DxpMessage::BackRequest { move_number: 12, color: PieceColor::Black });
+        round_trip(DxpMessage::BackAccept { code: 2 });
+    }
+
+    #[test]
+    fn message_headers() {
+        let request = DxpMessage::GameRequest { name: String::from("Opponent"), follower_color: PieceColor::Black, minutes: 10, moves: 75, position: None };
+        assert_eq!(request.to_wire(), format!("R01{:<32}Z010075A", "Opponent"));
+        assert_eq!(DxpMessage::Chat(String::from("hi")).to_wire(), "Chi");
+        assert_eq!(DxpMessage::Move { time: 1, from: 32, to: 21, captured: vec![27] }.to_wire(), "M000132210127");
+    }
+
+    // White man on 32 that has to take the black man on 27, a man each further back so the game goes on
+    fn capture_position() -> (PieceColor, String) {
+        return (PieceColor::Red, String::from("eeezzeeeeeeeeeeeeeeeeeeeeezeeeeweeeeeeeeeeeeeweeee"));
+    }
+
+    #[test]
+    fn loopback_game() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, &mut |_| {}));
+
+        let mut conn = DxpConnection::connect(&address.to_string()).unwrap();
+        let (turn, squares) = capture_position();
+        // a minute for 999 moves leaves the server no time to think past the first depth
+        conn.send(&DxpMessage::GameRequest { name: String::from("Test"), follower_color: PieceColor::Black, minutes: 1, moves: 999, position: Some((turn, squares.clone())) }).unwrap();
+        assert_eq!(conn.receive().unwrap(), DxpMessage::GameAccept { name: String::from(ENGINE_NAME), code: 0 });
+
+        let mut state = from_dxp_position(turn, &squares, Variant::International.rules()).unwrap();
+        let capture = state.get_possible_moves().remove(0);
+        let message = move_message(&state, &capture, 0);
+        assert_eq!(message, DxpMessage::Move { time: 0, from: 32, to: 21, captured: vec![27] });
+        conn.send(&message).unwrap();
+        state = state.next_state_with_move(&capture);
+
+        for _ in 0..2 {
+            match conn.receive().unwrap() {
+                DxpMessage::Move { from, to, captured, .. } => {
+                    let reply = resolve_dxp_move(&state, from, to, &captured).unwrap();
+                    state = state.next_state_with_move(&reply);
+                },
+                message => panic!("Expected a move, got {:?}", message)
+            }
+            let turn = state.get_possible_moves().remove(0);
+            conn.send(&move_message(&state, &turn, 0)).unwrap();
+            state = state.next_state_with_move(&turn);
+        }
+
+        conn.send(&DxpMessage::GameEnd { reason: GameEndReason::Unknown, stop_code: 1 }).unwrap();
+        loop {
+            match conn.receive().unwrap() {
+                DxpMessage::GameEnd { stop_code, .. } => {
+                    assert_eq!(stop_code, 1);
+                    break;
+                },
+                // the server may have moved before it read the game end
+                DxpMessage::Move { .. } => continue,
+                message => panic!("Expected the game to end, got {:?}", message)
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+use crate::{
+    state::{GameState, CheckersState, CheckersPiece, PieceColor, PieceType},
+    logic::PossibleMoves,
+    ai::AIStatus,
+    clock::{GameClock, TimeControl},
+    analysis::AnalysisMode,
+    checkers_events::{ButtonSelectEvent, EditSquareEvent, RedrawPiecesEvent},
+    ui::{spawn_button, ButtonLayout, BUTTON_BACKGROUND, BUTTON_SELECTED, BUTTON_FONT_SIZE}
+};
+
+
+pub struct CheckersEditorPlugin;
+
+
+impl Plugin for CheckersEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(Editor { tool: EditorTool::Piece(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man }) })
+        .add_system_set(SystemSet::on_enter(GameState::EditorSetup).with_system(setup_editor_controls))
+        .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(cleanup_editor_controls))
+        .add_system_set(SystemSet::on_update(GameState::Editor).with_system(handle_edit_square))
+        .add_system_set(SystemSet::on_update(GameState::Editor).with_system(editor_button_system))
+        .add_system(update_editor_labels);
+    }
+}
+
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditorTool {
+    Piece(CheckersPiece),
+    Erase
+}
+
+
+#[derive(Resource)]
+pub struct Editor {
+    pub tool: EditorTool
+}
+
+
+#[derive(Component)]
+struct EditorControls;
+
+
+#[derive(Component)]
+struct EditorMessage;
+
+
+#[derive(Component)]
+struct ToolButton(EditorTool);
+
+
+fn setup_editor_controls(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("fonts/MunichRegular.ttf");
+    let tools = [
+        ("Black Man", EditorTool::Piece(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man })),
+        ("Black King", EditorTool::Piece(CheckersPiece { col: PieceColor::Black, typ: PieceType::King })),
+        ("Red Man", EditorTool::Piece(CheckersPiece { col: PieceColor::Red, typ: PieceType::Man })),
+        ("Red King", EditorTool::Piece(CheckersPiece { col: PieceColor::Red, typ: PieceType::King })),
+        ("Erase", EditorTool::Erase)
+    ];
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect { top: Val::Px(20.), right: Val::Px(20.), ..default() },
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        ..default()
+    })
+    .insert(EditorControls)
+    .with_children(|parent| {
+        let layout = ButtonLayout::new(Size::new(Val::Px(200.), Val::Px(40.)), UiRect{top: Val::Px(8.), ..default()});
+        for (label, tool) in tools {
+            spawn_button(parent, &font, label, "TOOL", layout).insert(ToolButton(tool));
+        }
+        spawn_button(parent, &font, "", "TURN", layout);
+        spawn_button(parent, &font, "Clear Board", "CLEAR", layout);
+        spawn_button(parent, &font, "Initial Position", "INITIAL", layout);
+        spawn_button(parent, &font, "Play vs AI", "PLAY_AI", layout);
+        spawn_button(parent, &font, "Two Players", "PLAY_HUMAN", layout);
+        spawn_button(parent, &font, "Analysis", "ANALYSIS", layout);
+        parent.spawn(TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: BUTTON_FONT_SIZE,
+                color: Color::rgb(0.9, 0.4, 0.4),
+            },
+        ).with_style(Style { max_size: Size::new(Val::Px(200.), Val::Undefined), margin: UiRect{top: Val::Px(8.), ..default()}, ..default() }))
+        .insert(EditorMessage);
+    });
+}
+
+
+fn cleanup_editor_controls(mut commands: Commands, query: Query<Entity, With<EditorControls>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+
+// Clicking a square places the selected piece, clicking the same piece again removes it
+fn handle_edit_square(
+    mut events: EventReader<EditSquareEvent>,
+    editor: Res<Editor>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut redraw_writer: EventWriter<RedrawPiecesEvent>,
+    mut message_query: Query<&mut Text, With<EditorMessage>>
+){
+    for ev in events.iter() {
+        let current = checkers_state.board[ev.pos.row][ev.pos.col];
+        checkers_state.board[ev.pos.row][ev.pos.col] = match editor.tool {
+            EditorTool::Piece(piece) => {
+                match current {
+                    Some(p) if p.col == piece.col && p.typ == piece.typ => None,
+                    _ => Some(piece)
+                }
+            },
+            EditorTool::Erase => None
+        };
+        redraw_writer.send(RedrawPiecesEvent);
+        for mut text in message_query.iter_mut() {
+            text.sections[0].value = String::new();
+        }
+    }
+}
+
+
+fn editor_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &Children, Option<&ToolButton>),
+        (Changed<Interaction>, With<Button>)
+    >,
+    text_query: Query<&Name>,
+    mut message_query: Query<&mut Text, With<EditorMessage>>,
+    mut editor: ResMut<Editor>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut ai_status: ResMut<AIStatus>,
+    mut analysis: ResMut<AnalysisMode>,
+    mut clock: ResMut<GameClock>,
+    mut possible_moves: ResMut<PossibleMoves>,
+    mut redraw_writer: EventWriter<RedrawPiecesEvent>,
+    mut select_event: EventWriter<ButtonSelectEvent>,
+    mut game_state: ResMut<State<GameState>>
+){
+    for (interaction, children, tool_button) in &mut interaction_query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let name = match text_query.get(children[0]) {
+            Ok(name) => name,
+            Err(_) => continue
+        };
+        select_event.send(ButtonSelectEvent);
+        let mut start_game = false;
+        match name.as_str() {
+            "TOOL" => {
+                if let Some(tool_button) = tool_button {
+                    editor.tool = tool_button.0;
+                }
+            },
+            "TURN" => {
+                checkers_state.turn = checkers_state.turn.opponent();
+            },
+            "CLEAR" => {
+                for row in checkers_state.board.iter_mut() {
+                    for square in row.iter_mut() {
+                        *square = None;
+                    }
+                }
+                redraw_writer.send(RedrawPiecesEvent);
+            },
+            "INITIAL" => {
+                *checkers_state = CheckersState::new(checkers_state.rules);
+                redraw_writer.send(RedrawPiecesEvent);
+            },
+            "PLAY_AI" => {
+                ai_status.enabled = true;
+                analysis.enabled = false;
+                start_game = true;
+            },
+            "PLAY_HUMAN" => {
+                ai_status.enabled = false;
+                analysis.enabled = false;
+                start_game = true;
+            },
+            "ANALYSIS" => {
+                ai_status.enabled = false;
+                analysis.enabled = true;
+                clock.control = TimeControl::Unlimited;
+                start_game = true;
+            },
+            _ => {}
+        }
+
+        if start_game {
+            match checkers_state.validate_setup() {
+                Ok(_) => {
+                    possible_moves.moves = None;
+                    game_state.set(GameState::BoardSetup).unwrap();
+                },
+                Err(e) => {
+                    info!("Invalid position: {}", e);
+                    for mut text in message_query.iter_mut() {
+                        text.sections[0].value = e.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+fn update_editor_labels(
+    editor: Res<Editor>,
+    checkers_state: Res<CheckersState>,
+    game_state: Res<State<GameState>>,
+    mut button_query: Query<(&mut BackgroundColor, &Children, Option<&ToolButton>), With<Button>>,
+    mut text_query: Query<(&Name, &mut Text)>
+){
+    if *game_state.current() != GameState::Editor {
+        return;
+    }
+    for (mut color, children, tool_button) in button_query.iter_mut() {
+        if let Some(tool_button) = tool_button {
+            *color = if tool_button.0 == editor.tool { BUTTON_SELECTED.into() } else { BUTTON_BACKGROUND.into() };
+        }
+        if let Ok((name, mut text)) = text_query.get_mut(children[0]) {
+            if name.as_str() == "TURN" {
+                text.sections[0].value = format!("{:?} to move", checkers_state.turn);
+            }
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant}
+};
+use crate::{
+    state::{CheckersState, CheckersPiece, PieceColor, PieceType},
+    rules::{RulesConfig, Variant},
+    logic::Move,
+    notation::{square_number, square_position, resolve_move},
+    alphabeta::{iterative_deepening_with, minimax_alpha_beta, SearchLimit, TwoPlayerGameState},
+    dxp::{captured_squares, resolve_dxp_move}
+};
+
+
+// Engine mode speaking the Hub protocol on stdin and stdout, started with --engine.
+// The GUI sends one command per line, e.g. `pos pos=Wbbb...eww moves="32-28 19-23"`
+// followed by `go think`, and the engine answers with `done move=28x19x23`. Captures are
+// written as the start, the end and then the captured squares, so captures that only differ
+// in the pieces they take can be told apart.
+
+const ENGINE_NAME: &str = "Checkers";
+// Depth used when the level is infinite or only limited by time
+const MAX_SEARCH_DEPTH: u32 = 64;
+// Moves the engine assumes are left when the time control does not say
+const EXPECTED_MOVES: u32 = 30;
+// Shallow search for the reply to suggest pondering on
+const PONDER_DEPTH: u32 = 4;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Depth(u32),
+    MoveTime(Duration),
+    // time left for the game, increment per move and moves left until the next control
+    Time { time: Duration, inc: Duration, moves: Option<u32> },
+    Infinite
+}
+
+
+impl Level {
+    // How long a search may take and how deep it may go, None runs until stopped
+    fn budget(&self) -> (Option<Duration>, u32) {
+        match self {
+            Level::Depth(depth) => return (None, *depth),
+            Level::MoveTime(time) => return (Some(*time), MAX_SEARCH_DEPTH),
+            Level::Time { time, inc, moves } => {
+                let share = *time / moves.unwrap_or(EXPECTED_MOVES).max(1) + *inc;
+                // keep a little in hand so the flag can't fall on a slow reply
+                return (Some(share.min(*time / 2)), MAX_SEARCH_DEPTH);
+            },
+            Level::Infinite => return (None, MAX_SEARCH_DEPTH)
+        }
+    }
+}
+
+
+// Splits `pos pos=W... moves="32-28 19-23"` into the command and its arguments
+pub fn parse_command(line: &str) -> (String, HashMap<String, String>) {
+    let mut args = HashMap::new();
+    let line = line.trim();
+    let (command, mut rest) = match line.find(' ') {
+        Some(idx) => (&line[..idx], line[idx..].trim_start()),
+        None => (line, "")
+    };
+    while rest.len() > 0 {
+        let end = rest.find(|c: char| c == '=' || c == ' ').unwrap_or(rest.len());
+        let name = &rest[..end];
+        rest = &rest[end..];
+        let value = if rest.starts_with('=') {
+            rest = &rest[1..];
+            if rest.starts_with('"') {
+                let close = rest[1..].find('"').map(|i| i + 1).unwrap_or(rest.len());
+                let value = &rest[1..close];
+                rest = &rest[(close + 1).min(rest.len())..];
+                value
+            } else {
+                let end = rest.find(' ').unwrap_or(rest.len());
+                let value = &rest[..end];
+                rest = &rest[end..];
+                value
+            }
+        } else {
+            ""
+        };
+        args.insert(name.to_string(), value.to_string());
+        rest = rest.trim_start();
+    }
+    return (command.to_string(), args);
+}
+
+
+// Side to move followed by every square in order, w and b for men, W and B for kings, e for empty
+pub fn to_hub_position(state: &CheckersState) -> String {
+    let mut text = String::from(match state.turn {
+        PieceColor::Red => "W",
+        PieceColor::Black => "B"
+    });
+    let squares = state.rules.squares_per_row() * state.rules.board_dim;
+    for num in 1..=squares {
+        let pos = square_position(num, &state.rules).unwrap();
+        text.push(match state.board[pos.row][pos.col] {
+            None => 'e',
+            Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::Man }) => 'w',
+            Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man }) => 'b',
+            Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::King }) => 'W',
+            Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::King }) => 'B'
+        });
+    }
+    return text;
+}
+
+
+pub fn from_hub_position(text: &str, rules: RulesConfig) -> Result<CheckersState, String> {
+    let mut state = CheckersState::new(rules);
+    let squares = rules.squares_per_row() * rules.board_dim;
+    let chars: Vec<char> = text.trim().chars().collect();
+    if chars.len() != squares + 1 {
+        return Err(format!("Position has {} squares, expected {}", chars.len().saturating_sub(1), squares));
+    }
+    state.turn = match chars[0] {
+        'W' | 'w' => PieceColor::Red,
+        'B' | 'b' => PieceColor::Black,
+        c => return Err(format!("Invalid side to move {}", c))
+    };
+    for row in state.board.iter_mut() {
+        for square in row.iter_mut() {
+            *square = None;
+        }
+    }
+    for (idx, c) in chars[1..].iter().enumerate() {
+        let pos = square_position(idx + 1, &rules).unwrap();
+        state.board[pos.row][pos.col] = match c {
+            'e' => None,
+            'w' => Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::Man }),
+            'b' => Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man }),
+            'W' => Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::King }),
+            'B' => Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::King }),
+            c => return Err(format!("Invalid square {} in position", c))
+        };
+    }
+    return Ok(state);
+}
+
+
+// Scores in hundredths of a man, wins are reported as +-10000
+pub fn to_hub_move(state: &CheckersState, turn: &Vec<Move>) -> String {
+    let from = square_number(&turn[0].from, &state.rules);
+    let to = square_number(&turn[turn.len() - 1].to, &state.rules);
+    let captured = captured_squares(state, turn);
+    if captured.len() == 0 {
+        return format!("{}-{}", from, to);
+    }
+    let mut squares = vec![from, to];
+    squares.extend(captured);
+    return squares.iter().map(|s| s.to_string()).collect::<Vec<String>>().join("x");
+}
+
+
+// A move as the Hub protocol writes it, a capture given only by its start and end is fine
+// when no other capture shares them
+pub fn resolve_hub_move(state: &CheckersState, token: &str) -> Result<Vec<Move>, String> {
+    let squares = match token.split('x').map(|s| s.parse::<usize>()).collect::<Result<Vec<usize>, _>>() {
+        Ok(squares) if squares.len() > 2 => squares,
+        _ => return resolve_move(state, token)
+    };
+    return resolve_dxp_move(state, squares[0], squares[1], &squares[2..].to_vec());
+}
+
+
+fn hub_score(score: f32) -> i32 {
+    if score >= f32::MAX {
+        return 10000;
+    } else if score <= f32::MIN {
+        return -10000;
+    }
+    return (score * 2.) as i32;
+}
+
+
+fn send(line: &str) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+
+// A search running on its own thread, replies with done when it finishes or is stopped
+struct Search {
+    handle: JoinHandle<()>,
+    stop: Arc<AtomicBool>,
+    // pondering searches don't finish on their own until the opponent's move comes in
+    pondering: bool
+}
+
+
+struct Engine {
+    rules: RulesConfig,
+    state: CheckersState,
+    level: Level,
+    search: Option<Search>
+}
+
+
+impl Engine {
+    fn new() -> Self {
+        // draughts GUIs expect the international game unless told otherwise
+        let rules = Variant::International.rules();
+        return Engine { rules, state: CheckersState::new(rules), level: Level::MoveTime(Duration::from_secs(1)), search: None };
+    }
+
+    fn hub(&self) {
+        send(&format!("id name={} version={}", ENGINE_NAME, env!("CARGO_PKG_VERSION")));
+        let variants: Vec<String> = Variant::ALL.iter().map(|v| v.name().to_lowercase()).collect();
+        send(&format!(
+            "param name=variant value={} type=enum values=\"{}\"",
+            self.rules.variant.name().to_lowercase(), variants.join(" ")
+        ));
+        send("wait");
+    }
+
+    fn set_param(&mut self, args: &HashMap<String, String>) -> Result<(), String> {
+        let name = args.get("name").ok_or("set-param needs a name")?;
+        let value = args.get("value").ok_or("set-param needs a value")?;
+        match name.as_str() {
+            "variant" => {
+                let variant = Variant::ALL.iter().find(|v| v.name().eq_ignore_ascii_case(value)).ok_or(format!("Unknown variant {}", value))?;
+                self.rules = variant.rules();
+                self.state = CheckersState::new(self.rules);
+                return Ok(());
+            },
+            _ => return Err(format!("Unknown parameter {}", name))
+        }
+    }
+
+    fn pos(&mut self, args: &HashMap<String, String>) -> Result<(), String> {
+        let mut state = match args.get("pos") {
+            Some(pos) => from_hub_position(pos, self.rules)?,
+            None => CheckersState::new(self.rules)
+        };
+        if let Some(moves) = args.get("moves") {
+            for token in moves.split_whitespace() {
+                let turn = resolve_hub_move(&state, token)?;
+                state = state.next_state_with_move(&turn);
+            }
+        }
+        self.state = state;
+        return Ok(());
+    }
+
+    fn level(&mut self, args: &HashMap<String, String>) -> Result<(), String> {
+        let seconds = |name: &str| -> Result<Option<Duration>, String> {
+            match args.get(name) {
+                Some(v) => v.parse::<f32>().map(|s| Some(Duration::from_secs_f32(s.max(0.)))).map_err(|_| format!("Invalid {} {}", name, v)),
+                None => Ok(None)
+            }
+        };
+        if args.contains_key("infinite") {
+            self.level = Level::Infinite;
+        } else if let Some(depth) = args.get("depth") {
+            self.level = Level::Depth(depth.parse().map_err(|_| format!("Invalid depth {}", depth))?);
+        } else if let Some(time) = seconds("move-time")? {
+            self.level = Level::MoveTime(time);
+        } else if let Some(time) = seconds("time")? {
+            let moves = match args.get("moves") {
+                Some(m) => Some(m.parse().map_err(|_| format!("Invalid moves {}", m))?),
+                None => None
+            };
+            self.level = Level::Time { time, inc: seconds("inc")?.unwrap_or(Duration::ZERO), moves };
+        } else {
+            return Err(String::from("Unknown level"));
+        }
+        return Ok(());
+    }
+
+    fn go(&mut self, args: &HashMap<String, String>) {
+        self.finish_search();
+        let pondering = args.contains_key("ponder");
+        let analysing = args.contains_key("analyze");
+        let state = self.state.clone();
+        let (budget, depth) = match analysing || pondering {
+            true => Level::Infinite.budget(),
+            false => self.level.budget()
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let search_stop = stop.clone();
+        let start = Instant::now();
+        let handle = thread::spawn(move || {
+            let limit = SearchLimit { deadline: budget.map(|b| start + b), stop: Some(&search_stop) };
+            let (_, best) = iterative_deepening_with(&state, depth, &limit, &state.turn, |depth, score, best: &Option<Vec<Move>>| {
+                let pv = best.as_ref().map(|m| to_hub_move(&state, m)).unwrap_or_default();
+                send(&format!(
+                    "info depth={} score={} time={:.2} pv=\"{}\"",
+                    depth, hub_score(score), start.elapsed().as_secs_f32(), pv
+                ));
+            });
+            match best {
+                Some(best) => {
+                    // suggest the reply the engine expects so the GUI can let it ponder
+                    let next = state.next_state_with_move(&best);
+                    let ponder = match next.is_game_over() {
+                        true => None,
+                        false => minimax_alpha_beta(&next, PONDER_DEPTH, f32::NEG_INFINITY, f32::INFINITY, true, &next.turn).1.map(|m| to_hub_move(&next, &m))
+                    };
+                    match ponder {
+                        Some(ponder) => send(&format!("done move={} ponder={}", to_hub_move(&state, &best), ponder)),
+                        None => send(&format!("done move={}", to_hub_move(&state, &best)))
+                    }
+                },
+                None => send("done")
+            }
+        });
+        self.search = Some(Search { handle, stop, pondering });
+    }
+
+    fn stop(&mut self) {
+        if let Some(ref search) = self.search {
+            search.stop.store(true, Ordering::Relaxed);
+        }
+        self.finish_search();
+    }
+
+    // The move the engine pondered on was played, keep searching but now on the clock
+    fn ponder_hit(&mut self) {
+        let (budget, _) = self.level.budget();
+        if let Some(ref mut search) = self.search {
+            if !search.pondering {
+                return;
+            }
+            search.pondering = false;
+            if let Some(budget) = budget {
+                let stop = search.stop.clone();
+                thread::spawn(move || {
+                    thread::sleep(budget);
+                    stop.store(true, Ordering::Relaxed);
+                });
+            }
+        }
+    }
+
+    // Waits for the search thread so only one search ever runs
+    fn finish_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::Relaxed);
+            let _ = search.handle.join();
+        }
+    }
+}
+
+
+pub fn run() {
+    let mut engine = Engine::new();
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break
+        };
+        let (command, args) = parse_command(&line);
+        let result = match command.as_str() {
+            "" => Ok(()),
+            "hub" => {
+                engine.hub();
+                Ok(())
+            },
+            "init" => {
+                send("ready");
+                Ok(())
+            },
+            "ping" => {
+                send("pong");
+                Ok(())
+            },
+            "set-param" => engine.set_param(&args),
+            "new-game" => {
+                engine.stop();
+                engine.state = CheckersState::new(engine.rules);
+                Ok(())
+            },
+            "pos" => {
+                engine.stop();
+                engine.pos(&args)
+            },
+            "level" => engine.level(&args),
+            "go" => {
+                engine.go(&args);
+                Ok(())
+            },
+            "stop" => {
+                engine.stop();
+                Ok(())
+            },
+            "ponder-hit" => {
+                engine.ponder_hit();
+                Ok(())
+            },
+            "quit" => {
+                engine.stop();
+                break;
+            },
+            _ => Err(format!("Unknown command {}", command))
+        };
+        if let Err(e) = result {
+            send(&format!("error message=\"{}\"", e));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::from_fen;
+
+    #[test]
+    fn captures_name_the_captured_squares() {
+        // Black's man on 9 takes 14 and then 23
+        let state = from_fen("B:W14,23,32:B9,1", Variant::American.rules()).unwrap();
+        let turn = state.get_possible_moves().remove(0);
+        assert_eq!(to_hub_move(&state, &turn), "9x27x14x23");
+        assert_eq!(resolve_hub_move(&state, "9x27x14x23"), Ok(turn.clone()));
+        assert_eq!(resolve_hub_move(&state, "9x27x23x14"), Ok(turn));
+        assert!(resolve_hub_move(&state, "9x27x14x32").is_err());
+    }
+
+    #[test]
+    fn simple_moves_and_bare_captures() {
+        let state = CheckersState::new(Variant::American.rules());
+        let turn = resolve_hub_move(&state, "11-15").unwrap();
+        assert_eq!(to_hub_move(&state, &turn), "11-15");
+        let state = from_fen("B:W14,23,32:B9,1", Variant::American.rules()).unwrap();
+        assert_eq!(resolve_hub_move(&state, "9x27"), Ok(state.get_possible_moves().remove(0)));
+    }
+}
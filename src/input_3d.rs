@@ -18,6 +18,8 @@ impl Plugin for CheckersInput3dPlugin {
         .add_system_set(SystemSet::on_exit(GameState::Input).with_system(unmark_pickable_pieces))
         .add_system_set(SystemSet::on_enter(GameState::RestrictedInput).with_system(mark_pickable_pieces))
         .add_system_set(SystemSet::on_exit(GameState::RestrictedInput).with_system(unmark_pickable_pieces))
+        .add_system_set(SystemSet::on_enter(GameState::Editor).with_system(mark_pickable_squares))
+        .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(unmark_pickable_pieces))
         .add_system(handle_picking_events.after(mark_pickable_pieces).after(unmark_pickable_pieces))
         .add_system(bevy::window::close_on_esc);
     }
@@ -33,6 +35,7 @@ fn handle_picking_events(
         mut highlight_writer: EventWriter<HighlightEntityEvent>,
         mut remove_highlight_writer: EventWriter<RemoveHighlightEntityEvent>,
         mut trymove_writer: EventWriter<TryMoveEvent>,
+        mut edit_writer: EventWriter<EditSquareEvent>,
         mut input_move: ResMut<InputMove>,
         mut game_state: ResMut<State<GameState>>
     ){
@@ -55,6 +58,7 @@ fn handle_picking_events(
                     }
                 }
             },
+            PickingEvent::Selection(_) if *game_state.current() == GameState::Editor => {},
             PickingEvent::Selection(selection_event) => {
                 match selection_event {
                     SelectionEvent::JustSelected(entity) => {
@@ -88,8 +92,12 @@ fn handle_picking_events(
                     }
                 }
             }
-            PickingEvent::Clicked(_) => {
-                ();
+            PickingEvent::Clicked(entity) => {
+                if *game_state.current() == GameState::Editor {
+                    if let Ok(board_sq_comp) = bsc_query.get(*entity) {
+                        edit_writer.send(EditSquareEvent{pos: board_sq_comp.pos});
+                    }
+                }
             },
         }
     }
@@ -152,6 +160,14 @@ fn mark_pickable_pieces(
 }
 
 
+// In the editor every square can be clicked, pieces included
+fn mark_pickable_squares(mut commands: Commands, sq_query: Query<Entity, With<BoardSquareComponent>>){
+    for entity in sq_query.iter() {
+        commands.entity(entity).insert(PickableBundle::default());
+    }
+}
+
+
 // Function to unmark pickable pieces
 fn unmark_pickable_pieces(
     mut commands: Commands,
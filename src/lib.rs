@@ -0,0 +1,30 @@
+pub mod rendering_3d;
+pub mod input_3d;
+pub mod config;
+pub mod state;
+pub mod rules;
+pub mod logic;
+pub mod checkers_events;
+pub mod ai;
+pub mod alphabeta;
+pub mod mcts;
+pub mod network;
+pub mod training;
+pub mod sound;
+pub mod menu;
+pub mod clock;
+pub mod record;
+pub mod actions;
+pub mod save;
+pub mod notation;
+pub mod replay;
+pub mod editor;
+pub mod analysis;
+pub mod ballot;
+pub mod start;
+pub mod hub;
+pub mod controller;
+pub mod dxp;
+pub mod tablebase;
+pub mod book;
+pub mod ui;
@@ -1,8 +1,10 @@
 use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
 use crate::{
     state::{GameState, CheckersState, PieceColor},
     checkers_events::*,
-    ai::AIStatus
+    ai::AIStatus,
+    record::GameRecord
 };
 
 
@@ -40,7 +42,7 @@ pub struct PossibleMoves {
     pub moves: Option<Vec<Move>>
 }
 
-#[derive(PartialEq, Clone, Copy, Debug)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Position {
     pub row: usize,
     pub col: usize
@@ -53,44 +55,39 @@ impl Position {
 }
 
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Move {
     pub from: Position,
     pub to: Position
 }
 
-impl Move {
-    pub fn is_jump(&self) -> bool {
-        if (self.from.row as i32 - self.to.row as i32).abs() != 2 {
-            return false;
-        }
-        if (self.from.col as i32 - self.to.col as i32).abs() != 2 {
-            return false;
+
+fn handle_game_over(mut game_over_reader: EventReader<GameOverEvent>){
+
+    for ev in game_over_reader.iter() {
+        match ev.winner {
+            Some(PieceColor::Black) => info!{"Black Won! ({:?})", ev.reason},
+            Some(PieceColor::Red) => info!{"Red Won! ({:?})", ev.reason},
+            None => info!{"Draw! ({:?})", ev.reason}
         }
-        true
     }
+}
 
-    pub fn middle_pos(&self) -> Option<Position> {
-        if !self.is_jump() {
-            return None;
-        }
 
-        let row_delta: i32 = ((self.from.row as i32) - (self.to.row as i32)).signum();
-        let col_delta: i32 = ((self.from.col as i32) - (self.to.col as i32)).signum();
-        
-        return Some(Position::new((self.from.row as i32 - row_delta) as usize, (self.from.col as i32 - col_delta) as usize));
+// State to continue the game in once the board is ready for the player to move
+pub fn next_turn_state(checkers_state: &CheckersState, ai_status: &AIStatus, possible_moves: &PossibleMoves) -> GameState {
+    if ai_status.enabled && checkers_state.turn == ai_status.color {
+        return GameState::AIMove;
+    }
+    if possible_moves.moves.is_some() {
+        return GameState::RestrictedInput;
     }
+    return GameState::Input;
 }
 
 
-fn handle_game_over(mut win_reader: EventReader<VictoryEvent>){
-
-    for ev in win_reader.iter() {
-        match ev.winner {
-            PieceColor::Black => info!{"Black Won!"},
-            PieceColor::Red => info!{"Red Won!"}
-        }
-    }
+fn format_moves(moves: &Vec<Move>) -> String {
+    return moves.iter().map(|m|-> String { format!("{:?} to {:?}", m.from, m.to) }).collect::<Vec<String>>().join(", ");
 }
 
 
@@ -101,20 +98,18 @@ fn is_valid_move(m: &Move, checkers_state: &CheckersState, move_from: Option<Pos
             return false;
         }
     }
-    let possible_jumps = checkers_state.possible_captures();
-    if possible_jumps.len() > 0 {
-        if possible_jumps.contains(m){
-            return true;
-        } else {
-            info!{
-                "Invalid move! Capture is mandatory, can only move {}",
-                possible_jumps.iter().map(|m|-> String { format!("{:?} to {:?}", m.from, m.to) }).collect::<Vec<String>>().join(", ")
-            };
-            return false;
-        }
-    } else if checkers_state.valid_steps(&m.from, checkers_state.turn).contains(m){
+    let legal_moves = checkers_state.legal_moves(move_from);
+    if legal_moves.contains(m) {
         return true;
     }
+    if let Some(reason) = checkers_state.rule_violation(m, move_from) {
+        info!{
+            "Invalid move! Under {} rules you {}, can only move {}",
+            checkers_state.rules.name(), reason, format_moves(&legal_moves)
+        };
+    } else if checkers_state.possible_captures().len() > 0 {
+        info!{"Invalid move! Capture is mandatory, can only move {}", format_moves(&legal_moves)};
+    }
     return false;
 }
 
@@ -146,7 +141,8 @@ fn handle_try_move(
         } else {
             info!("Valid move {:?}", ev.game_move);
             move_writer.send(PieceMoveEvent{
-                game_move: ev.game_move
+                game_move: ev.game_move,
+                is_capture: checkers_state.captured_by(&ev.game_move).is_some()
             });
             game_state.set(GameState::Move).unwrap();
         }
@@ -162,13 +158,23 @@ pub fn handle_move(
     mut upgrade_writer: EventWriter<UpgradePieceEvent>,
     ai_status: Res<AIStatus>,
     mut possible_moves: ResMut<PossibleMoves>,
-    mut win_writer: EventWriter<VictoryEvent>,
-    mut post_animation_state: ResMut<PostAnimationState>
+    mut game_over_writer: EventWriter<GameOverEvent>,
+    mut post_animation_state: ResMut<PostAnimationState>,
+    mut game_record: ResMut<GameRecord>
 ){
     for ev in move_event.iter(){
+        let player = checkers_state.turn;
+        let before = checkers_state.board.clone();
         let (capture_pos, is_upgrade, next_capture_moves) = checkers_state.update_with_move(&ev.game_move);
-        if let Some(mid_pos) = capture_pos {
-            kill_writer.send(KillPieceEvent { pos: mid_pos });
+        game_record.push_move(player, ev.game_move, next_capture_moves.len() == 0);
+        // some rules leave captured pieces on the board until the whole sequence is played
+        for row in 0..before.len() {
+            for col in 0..before.len() {
+                let pos = Position::new(row, col);
+                if before[row][col].is_some() && checkers_state.board[row][col].is_none() && pos != ev.game_move.from {
+                    kill_writer.send(KillPieceEvent { pos });
+                }
+            }
         }
 
         if is_upgrade {
@@ -177,30 +183,23 @@ pub fn handle_move(
         }
 
 
-        let mut next_input_state: GameState = GameState::Input;
-        if capture_pos.is_some() &&  !is_upgrade && next_capture_moves.len() > 0 {
-            possible_moves.moves = Some(next_capture_moves);
-            next_input_state = GameState::RestrictedInput;
+        if capture_pos.is_some() && next_capture_moves.len() > 0 {
+            // the capture rule may rule out some of the ways to go on
+            possible_moves.moves = Some(checkers_state.legal_moves(Some(ev.game_move.to)));
         } else {
             possible_moves.moves = None;
         }
 
-        if checkers_state.is_loser(checkers_state.turn){
-            let winner = match checkers_state.turn {
-                PieceColor::Black => PieceColor::Red,
-                PieceColor::Red => PieceColor::Black
+        if checkers_state.is_out_of_play(checkers_state.turn){
+            // in giveaway the side that can't move has reached its goal
+            let winner = match checkers_state.rules.giveaway {
+                true => checkers_state.turn,
+                false => checkers_state.turn.opponent()
             };
-            win_writer.send(VictoryEvent { winner });
+            game_over_writer.send(GameOverEvent { winner: Some(winner), reason: GameOverReason::NoMoves });
             post_animation_state.state = GameState::GameOver;
         } else {
-            if ai_status.enabled{
-                match checkers_state.turn {
-                    PieceColor::Red => {post_animation_state.state = GameState::AIMove},
-                    PieceColor::Black => {post_animation_state.state = next_input_state}
-                }
-            } else {
-                post_animation_state.state = next_input_state
-            }
+            post_animation_state.state = next_turn_state(&checkers_state, &ai_status, &possible_moves);
         }
         game_state.set(GameState::Animating).unwrap();
     }
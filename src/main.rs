@@ -1,33 +1,53 @@
 use bevy::prelude::*;
 use std::f32::consts::PI;
 use bevy_mod_picking::{PickingCameraBundle};
-use rendering_3d::CheckersRendering3dPlugin;
-use input_3d::CheckersInput3dPlugin;
-use config::*;
-use state::CheckersState;
-use logic::CheckersGameLogicPlugin;
-use checkers_events::CheckersEventsPlugin;
-use ai::CheckersAIPlugin;
-use sound::CheckersSoundPlugin;
-use menu::CheckersMenuPlugin;
+use checkers::rendering_3d::CheckersRendering3dPlugin;
+use checkers::input_3d::CheckersInput3dPlugin;
+use checkers::config::*;
+use checkers::state::CheckersState;
+use checkers::rules::RulesConfig;
+use checkers::logic::CheckersGameLogicPlugin;
+use checkers::checkers_events::CheckersEventsPlugin;
+use checkers::ai::CheckersAIPlugin;
+use checkers::sound::CheckersSoundPlugin;
+use checkers::menu::CheckersMenuPlugin;
+use checkers::clock::CheckersClockPlugin;
+use checkers::record::CheckersRecordPlugin;
+use checkers::actions::CheckersActionsPlugin;
+use checkers::save::CheckersSavePlugin;
+use checkers::replay::CheckersReplayPlugin;
+use checkers::editor::CheckersEditorPlugin;
+use checkers::analysis::CheckersAnalysisPlugin;
+use checkers::ballot::CheckersBallotPlugin;
+use checkers::start::CheckersStartPlugin;
+use checkers::{hub, dxp};
 use bevy::log::LogPlugin;
 
-mod rendering_3d;
-mod input_3d;
-mod config;
-mod state;
-mod logic;
-mod checkers_events;
-mod ai;
-mod alphabeta;
-mod sound;
-mod menu;
-
-
 
 fn main() {
-    let board_config = BoardConfig::default();
-    let checkers_state = CheckersState::new(board_config.board_dim);
+    // engine mode runs the search on stdin and stdout without opening a window
+    if std::env::args().any(|a| a == "--engine") {
+        hub::run();
+        return;
+    }
+    // DXP games between engines run without a window as well
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(idx) = args.iter().position(|a| a == "--dxp-server") {
+        if let Err(e) = dxp::run_server(args.get(idx + 1).map(|a| a.as_str()).unwrap_or(dxp::DEFAULT_PORT), &mut |line| println!("{}", line)) {
+            println!("{}", e);
+        }
+        return;
+    }
+    if let Some(idx) = args.iter().position(|a| a == "--dxp-client") {
+        let address = args.get(idx + 1).cloned().unwrap_or(format!("127.0.0.1:{}", dxp::DEFAULT_PORT));
+        if let Err(e) = dxp::run_client(&address, &mut |line| println!("{}", line)) {
+            println!("{}", e);
+        }
+        return;
+    }
+    let rules = RulesConfig::default();
+    let board_config = BoardConfig { board_dim: rules.board_dim, ..default() };
+    let checkers_state = CheckersState::new(rules);
     let mut app = App::new();
     app
     .add_plugins(DefaultPlugins
@@ -51,7 +71,16 @@ fn main() {
     .add_plugin(CheckersEventsPlugin)
     .add_plugin(CheckersAIPlugin)
     .add_plugin(CheckersSoundPlugin)
-    .add_plugin(CheckersMenuPlugin);
+    .add_plugin(CheckersMenuPlugin)
+    .add_plugin(CheckersClockPlugin)
+    .add_plugin(CheckersRecordPlugin)
+    .add_plugin(CheckersActionsPlugin)
+    .add_plugin(CheckersSavePlugin)
+    .add_plugin(CheckersReplayPlugin)
+    .add_plugin(CheckersEditorPlugin)
+    .add_plugin(CheckersAnalysisPlugin)
+    .add_plugin(CheckersBallotPlugin)
+    .add_plugin(CheckersStartPlugin);
     // bevy_mod_debugdump::print_schedule(&mut app);
     app.run();
 }
@@ -0,0 +1,192 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::alphabeta::{TwoPlayerGameState, SearchLimit};
+
+
+// Monte Carlo tree search with UCT selection. Playouts are a few random moves followed by the
+// evaluation, turned into a win chance, so they work for any game the alpha-beta search can play.
+
+// Exploration constant of UCT, larger values try the less promising moves more often
+const EXPLORATION: f32 = 1.4;
+const PLAYOUT_MOVES: u32 = 8;
+// Evaluation difference that makes a side about 73% likely to win, one man with the default weights
+const SCORE_SCALE: f32 = 50.;
+
+
+struct Node<S: TwoPlayerGameState> {
+    state: S,
+    // move that led here from the parent
+    game_move: Option<S::GameMove>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<S::GameMove>,
+    // whether the player searched for is to move here
+    players_turn: bool,
+    visits: u32,
+    // sum of the playout results for the player searched for, 1 is a win
+    value: f32
+}
+
+
+pub struct RootMove<M> {
+    pub game_move: M,
+    pub visits: u32,
+    // average playout result for the player searched for
+    pub value: f32
+}
+
+
+pub struct MctsResult<M> {
+    pub best_move: Option<M>,
+    // every root move that was tried, most visited first
+    pub root_moves: Vec<RootMove<M>>,
+    pub playouts: u32
+}
+
+
+// Small xorshift generator, playouts need speed more than good randomness
+pub struct Random(u64);
+
+
+impl Random {
+    pub fn new() -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(1);
+        return Random(seed | 1);
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return (self.0 % n as u64) as usize;
+    }
+}
+
+
+fn win_chance(score: f32) -> f32 {
+    return 1. / (1. + (-score / SCORE_SCALE).exp());
+}
+
+
+// Plays random moves from the state then guesses the result from the evaluation
+fn playout<S>(state: &S, player: &S::Player, random: &mut Random) -> f32
+    where S: TwoPlayerGameState<GameState=S>
+{
+    let mut current: Option<S> = None;
+    for _ in 0..PLAYOUT_MOVES {
+        let state_ref = current.as_ref().unwrap_or(state);
+        if state_ref.is_game_over() {
+            break;
+        }
+        let mut moves = state_ref.get_possible_moves();
+        if moves.len() == 0 {
+            break;
+        }
+        let m = moves.swap_remove(random.below(moves.len()));
+        current = Some(state_ref.next_state_with_move(&m));
+    }
+    return win_chance(current.as_ref().unwrap_or(state).score_state(player));
+}
+
+
+// Searches until the limit is reached or the number of playouts is done, at least one playout runs per root move
+pub fn monte_carlo_tree_search<S>(state: &S, limit: &SearchLimit, max_playouts: Option<u32>, player: &S::Player) -> MctsResult<S::GameMove>
+    where S: TwoPlayerGameState<GameState=S> + Clone, S::GameMove: Clone, S::Player: PartialEq
+{
+    let mut random = Random::new();
+    let mut nodes = vec![Node { state: state.clone(), game_move: None, parent: None, children: Vec::new(), untried: state.get_possible_moves(), players_turn: state.player_to_move() == *player, visits: 0, value: 0. }];
+    let root_moves = nodes[0].untried.len() as u32;
+    let mut playouts = 0;
+    loop {
+        if playouts >= root_moves && (limit.reached() || max_playouts.map_or(false, |max| playouts >= max)) {
+            break;
+        }
+        if nodes[0].untried.len() == 0 && nodes[0].children.len() == 0 {
+            break;
+        }
+
+        // selection, following the best UCT score down to a node with moves left to try
+        let mut idx = 0;
+        while nodes[idx].untried.len() == 0 && nodes[idx].children.len() > 0 {
+            let parent_visits = (nodes[idx].visits as f32).ln();
+            let players_turn = nodes[idx].players_turn;
+            idx = *nodes[idx].children.iter().max_by(|a, b| {
+                let uct = |child: &Node<S>| {
+                    let mean = child.value / child.visits as f32;
+                    let mean = if players_turn { mean } else { 1. - mean };
+                    return mean + EXPLORATION * (parent_visits / child.visits as f32).sqrt();
+                };
+                return uct(&nodes[**a]).total_cmp(&uct(&nodes[**b]));
+            }).unwrap();
+        }
+
+        // expansion of one untried move
+        if nodes[idx].untried.len() > 0 {
+            let pick = random.below(nodes[idx].untried.len());
+            let m = nodes[idx].untried.swap_remove(pick);
+            let next_state = nodes[idx].state.next_state_with_move(&m);
+            let untried = if next_state.is_game_over() { Vec::new() } else { next_state.get_possible_moves() };
+            let players_turn = next_state.player_to_move() == *player;
+            nodes.push(Node { state: next_state, game_move: Some(m), parent: Some(idx), children: Vec::new(), untried, players_turn, visits: 0, value: 0. });
+            let child = nodes.len() - 1;
+            nodes[idx].children.push(child);
+            idx = child;
+        }
+
+        // simulation and backpropagation
+        let result = playout(&nodes[idx].state, player, &mut random);
+        let mut current = Some(idx);
+        while let Some(node) = current {
+            nodes[node].visits += 1;
+            nodes[node].value += result;
+            current = nodes[node].parent;
+        }
+        playouts += 1;
+    }
+
+    let mut root_moves: Vec<RootMove<S::GameMove>> = nodes[0].children.iter().map(|&child| RootMove {
+        game_move: nodes[child].game_move.clone().unwrap(),
+        visits: nodes[child].visits,
+        value: nodes[child].value / nodes[child].visits.max(1) as f32
+    }).collect();
+    root_moves.sort_by(|a, b| b.visits.cmp(&a.visits));
+    // the most visited move is the most robust choice
+    let best_move = root_moves.first().map(|r| r.game_move.clone());
+    return MctsResult { best_move, root_moves, playouts };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{state::{CheckersState, CheckersPiece, PieceColor, PieceType}, rules::Variant, logic::{Move, Position}};
+
+    fn step(from: (usize, usize), to: (usize, usize)) -> Move {
+        return Move { from: Position::new(from.0, from.1), to: Position::new(to.0, to.1) };
+    }
+
+    #[test]
+    fn takes_the_winning_double_jump() {
+        // either Red man can capture, only the double jump takes both of Black's men
+        let mut state = CheckersState::new(Variant::American.rules());
+        for square in state.board.iter_mut().flatten() {
+            *square = None;
+        }
+        state.turn = PieceColor::Red;
+        for (row, col, color) in [(2, 3, PieceColor::Red), (2, 5, PieceColor::Red), (3, 4, PieceColor::Black), (5, 6, PieceColor::Black)] {
+            state.board[row][col] = Some(CheckersPiece { col: color, typ: PieceType::Man });
+        }
+        assert_eq!(state.get_possible_moves().len(), 2);
+        let result = monte_carlo_tree_search(&state, &SearchLimit::default(), Some(2000), &PieceColor::Red);
+        assert_eq!(result.best_move, Some(vec![step((2, 3), (4, 5)), step((4, 5), (6, 7))]));
+    }
+
+    #[test]
+    fn root_visits_add_up_to_the_playouts() {
+        let state = CheckersState::new(Variant::American.rules());
+        let result = monte_carlo_tree_search(&state, &SearchLimit::default(), Some(500), &state.turn);
+        assert_eq!(result.playouts, 500);
+        assert_eq!(result.root_moves.len(), state.get_possible_moves().len());
+        assert_eq!(result.root_moves.iter().map(|r| r.visits).sum::<u32>(), 500);
+        assert!(result.root_moves.windows(2).all(|pair| pair[0].visits >= pair[1].visits));
+    }
+}
@@ -2,7 +2,19 @@
 
 use bevy::prelude::*;
 
-use crate::{state::GameState, ai::AIStatus, checkers_events::ButtonSelectEvent};
+use crate::{
+    config::BoardConfig,
+    state::{GameState, CheckersState, PieceColor},
+    rules::RulesConfig, ai::AIStatus, checkers_events::ButtonSelectEvent, clock::GameClock,
+    save::{LoadRequest, can_continue, autosave_path, save_path},
+    replay::{ReplayRequest, replay_source},
+    analysis::AnalysisMode,
+    ballot::{Ballot, load_deck, opening_label},
+    start::{StartSettings, Handicap},
+    record::GameStats,
+    controller::{AIController, SearchSettings},
+    ui::{spawn_button, ButtonLayout, BUTTON_BACKGROUND, BUTTON_HOVERED}
+};
 
 
 pub struct CheckersMenuPlugin;
@@ -29,6 +41,8 @@ struct ButtonColor(Color);
 #[derive(Component)]
 struct Menu;
 
+
+
 fn cleanup(mut commands: Commands, query: Query<Entity, With<Menu>>, asset_server: Res<AssetServer>){
     for menu in query.iter() {
         commands.entity(menu).despawn_recursive();
@@ -46,10 +60,21 @@ fn button_system(
     hovered_color: Res<HoveredButtonColor>,
     button_color: Res<ButtonColor>,
     text_query: Query<&Name>,
+    mut label_query: Query<(&Name, &mut Text)>,
     mut game_state: ResMut<State<GameState>>,
     mut ai_status: ResMut<AIStatus>,
+    mut analysis: ResMut<AnalysisMode>,
+    mut clock: ResMut<GameClock>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut board_config: ResMut<BoardConfig>,
+    requests: (ResMut<LoadRequest>, ResMut<ReplayRequest>),
+    mut ballot: ResMut<Ballot>,
+    mut start: ResMut<StartSettings>,
+    mut controller: ResMut<AIController>,
     mut select_event: EventWriter<ButtonSelectEvent>
 ) {
+    // grouped to stay within the number of parameters a system can take
+    let (mut load_request, mut replay_request) = requests;
     for (interaction, mut color, children) in &mut interaction_query {
         let text = text_query.get(children[0]).unwrap();
         match *interaction {
@@ -64,12 +89,123 @@ fn button_system(
                 match text.as_str() {
                     "HUMAN" => {
                         ai_status.enabled = false;
+                        analysis.enabled = false;
+                        new_pairing(&mut ai_status, &mut ballot, &mut start);
+                        start.pending = true;
                         game_state.set(GameState::BoardSetup).unwrap();
                     },
                     "CPU" => {
                         ai_status.enabled = true;
+                        analysis.enabled = false;
+                        new_pairing(&mut ai_status, &mut ballot, &mut start);
+                        start.pending = true;
                         game_state.set(GameState::BoardSetup).unwrap();
                     },
+                    "CONTINUE" => {
+                        load_request.path = Some(autosave_path());
+                        game_state.set(GameState::Loading).unwrap();
+                    },
+                    "LOAD" => {
+                        load_request.path = Some(save_path());
+                        game_state.set(GameState::Loading).unwrap();
+                    },
+                    "REPLAY" => {
+                        replay_request.path = replay_source();
+                        game_state.set(GameState::Loading).unwrap();
+                    },
+                    "EDITOR" => {
+                        game_state.set(GameState::EditorSetup).unwrap();
+                    },
+                    "VARIANT" => {
+                        let rules = RulesConfig { giveaway: checkers_state.rules.giveaway, ..checkers_state.rules.variant.next().rules() };
+                        *checkers_state = CheckersState::new(rules);
+                        board_config.board_dim = rules.board_dim;
+                        for (name, mut label) in label_query.iter_mut() {
+                            match name.as_str() {
+                                "VARIANT" => label.sections[0].value = variant_label(&rules),
+                                // the other rules have a different deck
+                                "OPENING" => label.sections[0].value = opening_label(ballot.mode, &checkers_state),
+                                _ => {}
+                            }
+                        }
+                    },
+                    "GIVEAWAY" => {
+                        let rules = RulesConfig { giveaway: !checkers_state.rules.giveaway, ..checkers_state.rules };
+                        *checkers_state = CheckersState::new(rules);
+                        for (name, mut label) in label_query.iter_mut() {
+                            match name.as_str() {
+                                "GIVEAWAY" => label.sections[0].value = giveaway_label(&rules),
+                                "OPENING" => label.sections[0].value = opening_label(ballot.mode, &checkers_state),
+                                _ => {}
+                            }
+                        }
+                    },
+                    "BALLOT" | "OPENING" => {
+                        ballot.mode = match text.as_str() {
+                            "BALLOT" => ballot.mode.next(),
+                            _ => ballot.mode.next_opening(load_deck(&checkers_state).len())
+                        };
+                        for (name, mut label) in label_query.iter_mut() {
+                            match name.as_str() {
+                                "BALLOT" => label.sections[0].value = ballot.mode.label(),
+                                "OPENING" => label.sections[0].value = opening_label(ballot.mode, &checkers_state),
+                                _ => {}
+                            }
+                        }
+                    },
+                    "ELEVEN" => {
+                        start.eleven_man = !start.eleven_man;
+                        for (name, mut label) in label_query.iter_mut() {
+                            if name.as_str() == "ELEVEN" {
+                                label.sections[0].value = eleven_man_label(&start);
+                            }
+                        }
+                    },
+                    "HANDICAP" => {
+                        start.handicap = Handicap::next(start.handicap);
+                        for (name, mut label) in label_query.iter_mut() {
+                            if name.as_str() == "HANDICAP" {
+                                label.sections[0].value = Handicap::label(start.handicap);
+                            }
+                        }
+                    },
+                    "TIME" => {
+                        clock.control = clock.control.next_preset();
+                        for (name, mut label) in label_query.iter_mut() {
+                            if name.as_str() == "TIME" {
+                                label.sections[0].value = clock.control.label();
+                            }
+                        }
+                    },
+                    // an engine given on the command line keeps playing whatever it is set to
+                    "SEARCH" => {
+                        if let Some(settings) = controller.0.search_settings() {
+                            *controller = AIController::built_in(SearchSettings { mcts: !settings.mcts, ..settings });
+                        }
+                        for (name, mut label) in label_query.iter_mut() {
+                            if name.as_str() == "SEARCH" {
+                                label.sections[0].value = search_label(&controller);
+                            }
+                        }
+                    },
+                    "CONTEMPT" => {
+                        ai_status.contempt = ai_status.next_contempt();
+                        for (name, mut label) in label_query.iter_mut() {
+                            if name.as_str() == "CONTEMPT" {
+                                label.sections[0].value = ai_status.contempt_label();
+                            }
+                        }
+                    },
+                    "BOOK" => {
+                        if let Some(settings) = controller.0.search_settings() {
+                            *controller = AIController::built_in(SearchSettings { use_book: !settings.use_book, ..settings });
+                        }
+                        for (name, mut label) in label_query.iter_mut() {
+                            if name.as_str() == "BOOK" {
+                                label.sections[0].value = book_label(&controller);
+                            }
+                        }
+                    },
                     _ => {}
                 }
             }
@@ -78,14 +214,70 @@ fn button_system(
 }
 
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    const BUTTON_BACKGROUND: Color = Color::rgb(0.1, 0.1, 0.1);
-    const BUTTON_FONT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
-    const BUTTON_FONT_SIZE: f32 = 20.;
-    const BUTTON_HEIGHT: f32 = 65.;
-    const BUTTON_WIDTH_RELATIVE: f32 = 50.;
+// A game started from the menu begins a new pair, only a rematch keeps the opening, the
+// men removed by the 11-man ballot and swaps sides
+fn new_pairing(ai_status: &mut AIStatus, ballot: &mut Ballot, start: &mut StartSettings) {
+    ai_status.color = PieceColor::Red;
+    ballot.opening = None;
+    start.removed = None;
+}
+
+
+const MENU_FONT_SIZE: f32 = 20.;
+const BUTTON_HEIGHT: f32 = 45.;
+const BUTTON_WIDTH_RELATIVE: f32 = 50.;
+
+
+fn variant_label(rules: &RulesConfig) -> String {
+    return format!("Rules: {}", rules.variant.name());
+}
+
+
+fn eleven_man_label(start: &StartSettings) -> String {
+    return match start.eleven_man {
+        true => String::from("11-Man Ballot: On"),
+        false => String::from("11-Man Ballot: Off")
+    };
+}
+
+
+fn search_label(controller: &AIController) -> String {
+    return match controller.0.search_settings() {
+        Some(settings) if settings.mcts => String::from("AI: Tree Search"),
+        Some(_) => String::from("AI: Alpha-Beta"),
+        None => format!("AI: {}", controller.0.name())
+    };
+}
+
+
+fn book_label(controller: &AIController) -> String {
+    return match controller.0.search_settings() {
+        Some(settings) if settings.use_book => String::from("Opening Book: On"),
+        _ => String::from("Opening Book: Off")
+    };
+}
+
+
+fn giveaway_label(rules: &RulesConfig) -> String {
+    return match rules.giveaway {
+        true => String::from("Giveaway: On"),
+        false => String::from("Giveaway: Off")
+    };
+}
+
+
+fn spawn_menu_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, name: &str, margin_top: f32) {
+    let layout = ButtonLayout {
+        font_size: MENU_FONT_SIZE,
+        ..ButtonLayout::new(Size::new(Val::Percent(BUTTON_WIDTH_RELATIVE), Val::Px(BUTTON_HEIGHT)), UiRect{top: Val::Px(margin_top), ..default()})
+    };
+    spawn_button(parent, font, label, name, layout);
+}
+
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, clock: Res<GameClock>, checkers_state: Res<CheckersState>, ballot: Res<Ballot>, start: Res<StartSettings>, stats: Res<GameStats>, controller: Res<AIController>, ai_status: Res<AIStatus>) {
     let button_font: Handle<Font> = asset_server.load("fonts/MunichRegular.ttf");
-    commands.insert_resource(HoveredButtonColor(Color::rgb(0.2, 0.2, 0.2)));
+    commands.insert_resource(HoveredButtonColor(BUTTON_HOVERED));
     commands.insert_resource(ButtonColor(BUTTON_BACKGROUND));
 
 
@@ -160,52 +352,41 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             )
                         );
                     });
-                    
-                    // first button
-                    parent.spawn(ButtonBundle {
-                        style: Style {
-                            size: Size::new(Val::Percent(BUTTON_WIDTH_RELATIVE), Val::Px(BUTTON_HEIGHT)),
-                            margin: UiRect{top: Val::Px(50.), ..default()},
-                            justify_content: JustifyContent::Center,
-                            align_items: AlignItems::Center,
-                            ..default()
-                        },
-                        background_color: BUTTON_BACKGROUND.into(),
-                        ..default()
-                    })
-                    .with_children(|parent| {
-                        parent.spawn(TextBundle::from_section(
-                            "Two Players",
-                            TextStyle {
-                                font: button_font.clone(),
-                                font_size: BUTTON_FONT_SIZE,
-                                color: BUTTON_FONT_COLOR.into(),
-                            },
-                        )).insert(Name::new("HUMAN"));
-                    });
 
-                    // second button
-                    parent.spawn(ButtonBundle {
-                        style: Style {
-                            size: Size::new(Val::Percent(BUTTON_WIDTH_RELATIVE), Val::Px(BUTTON_HEIGHT)),
-                            margin: UiRect{top: Val::Px(20.), ..default()},
-                            justify_content: JustifyContent::Center,
-                            align_items: AlignItems::Center,
-                            ..default()
-                        },
-                        background_color: BUTTON_BACKGROUND.into(),
-                        ..default()
-                    })
-                    .with_children(|parent| {
+                    // results of every game played so far
+                    if stats.games() > 0 {
                         parent.spawn(TextBundle::from_section(
-                            "Against AI",
+                            stats.label(),
                             TextStyle {
                                 font: button_font.clone(),
-                                font_size: BUTTON_FONT_SIZE,
-                                color: BUTTON_FONT_COLOR.into(),
+                                font_size: MENU_FONT_SIZE,
+                                color: Color::rgb(0.8, 0.8, 0.8),
                             },
-                        )).insert(Name::new("CPU"));
-                    });
+                        ).with_style(Style { margin: UiRect{top: Val::Px(10.), ..default()}, ..default() }));
+                    }
+                    
+                    if can_continue() {
+                        spawn_menu_button(parent, &button_font, "Continue", "CONTINUE", 50.);
+                    }
+                    spawn_menu_button(parent, &button_font, "Two Players", "HUMAN", 20.);
+                    spawn_menu_button(parent, &button_font, "Against AI", "CPU", 20.);
+                    if save_path().exists() {
+                        spawn_menu_button(parent, &button_font, "Load Game", "LOAD", 20.);
+                    }
+                    if replay_source().is_some() {
+                        spawn_menu_button(parent, &button_font, "Replay", "REPLAY", 20.);
+                    }
+                    spawn_menu_button(parent, &button_font, "Position Editor", "EDITOR", 20.);
+                    spawn_menu_button(parent, &button_font, &variant_label(&checkers_state.rules), "VARIANT", 10.);
+                    spawn_menu_button(parent, &button_font, &giveaway_label(&checkers_state.rules), "GIVEAWAY", 10.);
+                    spawn_menu_button(parent, &button_font, &ballot.mode.label(), "BALLOT", 10.);
+                    spawn_menu_button(parent, &button_font, &opening_label(ballot.mode, &checkers_state), "OPENING", 10.);
+                    spawn_menu_button(parent, &button_font, &eleven_man_label(&start), "ELEVEN", 10.);
+                    spawn_menu_button(parent, &button_font, &Handicap::label(start.handicap), "HANDICAP", 10.);
+                    spawn_menu_button(parent, &button_font, &clock.control.label(), "TIME", 10.);
+                    spawn_menu_button(parent, &button_font, &search_label(&controller), "SEARCH", 10.);
+                    spawn_menu_button(parent, &button_font, &book_label(&controller), "BOOK", 10.);
+                    spawn_menu_button(parent, &button_font, &ai_status.contempt_label(), "CONTEMPT", 10.);
                 });
                 
         });
@@ -0,0 +1,227 @@
+use std::{fs, path::{Path, PathBuf}};
+use serde::{Serialize, Deserialize};
+use crate::{
+    state::{CheckersState, PieceColor, PieceType},
+    logic::Position,
+    ai::{Evaluator, EvalWeights},
+    notation::square_number,
+    save::save_dir
+};
+
+
+// A small network evaluating positions, one hidden layer over the playable squares of the board.
+// Each square has four inputs, a man or a king of the side to move or of the opponent, and the
+// board is turned around for the second player so both sides are seen the same way.
+
+const NETWORK_FILE: &str = "network.json";
+const PLANES: usize = 4;
+// The network gives the log odds of winning, scaled so a man is worth about 50 as in the handcrafted weights
+const NETWORK_SCORE_SCALE: f32 = 50.;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Network {
+    // playable squares of the board the network was trained for, 32 for 8x8
+    pub squares: usize,
+    pub hidden: usize,
+    // weights from each input to every hidden unit, input major
+    pub input_weights: Vec<f32>,
+    pub hidden_bias: Vec<f32>,
+    pub output_weights: Vec<f32>,
+    pub output_bias: f32
+}
+
+
+pub fn network_path() -> PathBuf {
+    return save_dir().join(NETWORK_FILE);
+}
+
+
+fn sigmoid(x: f32) -> f32 {
+    return 1. / (1. + (-x).exp());
+}
+
+
+impl Network {
+    // Small random weights, the seed makes training runs repeatable
+    pub fn new(squares: usize, hidden: usize, seed: u64) -> Self {
+        let mut random = seed.max(1);
+        let mut next = |range: f32| {
+            random ^= random << 13;
+            random ^= random >> 7;
+            random ^= random << 17;
+            return ((random % 20001) as f32 / 10000. - 1.) * range;
+        };
+        let input_range = (1. / (squares * PLANES) as f32).sqrt();
+        let hidden_range = (1. / hidden as f32).sqrt();
+        let input_weights = (0..(squares * PLANES * hidden)).map(|_| next(input_range)).collect();
+        let output_weights = (0..hidden).map(|_| next(hidden_range)).collect();
+        return Network { squares, hidden, input_weights, hidden_bias: vec![0.; hidden], output_weights, output_bias: 0. };
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let network: Network = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        if network.input_weights.len() != network.squares * PLANES * network.hidden || network.output_weights.len() != network.hidden {
+            return Err(String::from("Network weights don't match its size"));
+        }
+        return Ok(network);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let text = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        return fs::write(path, text).map_err(|e| e.to_string());
+    }
+
+    // Indices of the inputs that are on, None if the board has a different number of squares
+    pub fn inputs(&self, state: &CheckersState, turn: PieceColor) -> Option<Vec<usize>> {
+        let squares = state.rules.squares_per_row() * state.rules.board_dim;
+        if squares != self.squares {
+            return None;
+        }
+        let mut inputs = Vec::new();
+        for row in 0..state.board.len() {
+            for col in 0..state.board.len() {
+                if let Some(piece) = state.board[row][col] {
+                    let mut square = square_number(&Position::new(row, col), &state.rules) - 1;
+                    if turn == PieceColor::Red {
+                        square = squares - 1 - square;
+                    }
+                    let plane = match (piece.col == turn, piece.typ) {
+                        (true, PieceType::Man) => 0,
+                        (true, PieceType::King) => 1,
+                        (false, PieceType::Man) => 2,
+                        (false, PieceType::King) => 3
+                    };
+                    inputs.push(plane * squares + square);
+                }
+            }
+        }
+        return Some(inputs);
+    }
+
+    fn hidden_layer(&self, inputs: &[usize]) -> Vec<f32> {
+        let mut hidden = self.hidden_bias.clone();
+        for &input in inputs.iter() {
+            let weights = &self.input_weights[(input * self.hidden)..((input + 1) * self.hidden)];
+            for (h, w) in hidden.iter_mut().zip(weights.iter()) {
+                *h += w;
+            }
+        }
+        for h in hidden.iter_mut() {
+            *h = h.max(0.);
+        }
+        return hidden;
+    }
+
+    // Log odds of the side to move winning
+    pub fn forward(&self, inputs: &[usize]) -> f32 {
+        let hidden = self.hidden_layer(inputs);
+        return self.output_bias + hidden.iter().zip(self.output_weights.iter()).map(|(h, w)| h * w).sum::<f32>();
+    }
+
+    // One step of gradient descent on the cross entropy with the result, 1 for a win, returns the loss
+    pub fn train(&mut self, inputs: &[usize], result: f32, rate: f32) -> f32 {
+        let hidden = self.hidden_layer(inputs);
+        let predicted = sigmoid(self.output_bias + hidden.iter().zip(self.output_weights.iter()).map(|(h, w)| h * w).sum::<f32>());
+        let error = predicted - result;
+        for j in 0..self.hidden {
+            if hidden[j] <= 0. {
+                continue;
+            }
+            let hidden_error = error * self.output_weights[j];
+            self.hidden_bias[j] -= rate * hidden_error;
+            for &input in inputs.iter() {
+                self.input_weights[input * self.hidden + j] -= rate * hidden_error;
+            }
+        }
+        for j in 0..self.hidden {
+            self.output_weights[j] -= rate * error * hidden[j];
+        }
+        self.output_bias -= rate * error;
+        let predicted = predicted.clamp(1e-6, 1. - 1e-6);
+        return -(result * predicted.ln() + (1. - result) * (1. - predicted).ln());
+    }
+}
+
+
+impl Evaluator for Network {
+    fn name(&self) -> String {
+        return String::from("network");
+    }
+
+    // Giveaway and boards the network wasn't trained for fall back to the handcrafted evaluation
+    fn evaluate(&self, state: &CheckersState, turn: PieceColor) -> f32 {
+        if !state.rules.giveaway {
+            if let Some(inputs) = self.inputs(state, turn) {
+                return self.forward(&inputs) * NETWORK_SCORE_SCALE;
+            }
+        }
+        return EvalWeights::loaded().evaluate(state, turn);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{state::CheckersPiece, rules::Variant, notation::from_fen};
+
+    // The same position with the board turned around and the colours swapped
+    fn flipped(state: &CheckersState) -> CheckersState {
+        let mut flipped = state.clone();
+        let dim = state.board.len();
+        for row in 0..dim {
+            for col in 0..dim {
+                flipped.board[row][col] = state.board[dim - 1 - row][dim - 1 - col].map(|piece| {
+                    return CheckersPiece { col: piece.col.opponent(), typ: piece.typ };
+                });
+            }
+        }
+        flipped.turn = state.turn.opponent();
+        return flipped;
+    }
+
+    #[test]
+    fn both_sides_see_the_same_inputs() {
+        let network = Network::new(32, 8, 1);
+        let state = from_fen("B:W18,22,K30:B1,K9,14", Variant::American.rules()).unwrap();
+        let mut black = network.inputs(&state, PieceColor::Black).unwrap();
+        let mut red = network.inputs(&flipped(&state), PieceColor::Red).unwrap();
+        black.sort();
+        red.sort();
+        assert_eq!(black, red);
+        assert_eq!(network.forward(&black), network.forward(&red));
+        // a board of another size isn't evaluated by the network
+        assert_eq!(network.inputs(&CheckersState::new(Variant::International.rules()), PieceColor::Black), None);
+    }
+
+    #[test]
+    fn load_rejects_mismatched_sizes() {
+        let path = std::env::temp_dir().join(format!("checkers_network_{}.json", std::process::id()));
+        let mut network = Network::new(32, 8, 1);
+        network.save(&path).unwrap();
+        assert!(Network::load(&path).is_ok());
+        network.hidden = 9;
+        network.save(&path).unwrap();
+        let result = Network::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap_err(), "Network weights don't match its size");
+    }
+
+    #[test]
+    fn training_lowers_the_loss() {
+        let mut network = Network::new(32, 8, 7);
+        let state = CheckersState::new(Variant::American.rules());
+        let inputs = network.inputs(&state, PieceColor::Black).unwrap();
+        let first = network.train(&inputs, 1., 0.01);
+        let mut last = first;
+        for _ in 0..20 {
+            last = network.train(&inputs, 1., 0.01);
+        }
+        assert!(last < first, "{} {}", first, last);
+    }
+}
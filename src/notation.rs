@@ -0,0 +1,353 @@
+use crate::{
+    logic::{Move, Position},
+    state::{CheckersState, CheckersPiece, PieceColor, PieceType},
+    rules::{RulesConfig, Variant},
+    alphabeta::TwoPlayerGameState,
+    start::{Handicap, HandicapKind}
+};
+
+
+// Squares are numbered over the dark squares only, starting from Black's back row, so Black
+// starts on 1-12 and Red on 21-32 on an 8x8 board, and on 1-30 and 43-72 on a 12x12 board.
+pub fn square_number(pos: &Position, rules: &RulesConfig) -> usize {
+    let dim = rules.board_dim;
+    let per_row = rules.squares_per_row();
+    let rank_index = dim - 1 - pos.row;
+    let file_index = (dim - 1 - pos.col) * per_row / dim;
+    return rank_index * per_row + file_index + 1;
+}
+
+
+pub fn square_position(num: usize, rules: &RulesConfig) -> Option<Position> {
+    let dim = rules.board_dim;
+    let per_row = rules.squares_per_row();
+    if num == 0 || num > per_row * dim {
+        return None;
+    }
+    let idx = num - 1;
+    let row = dim - 1 - idx / per_row;
+    let step = dim / per_row;
+    let offset = if rules.is_playable(row, dim - 1) { 0 } else { 1 };
+    let col = dim - 1 - step * (idx % per_row) - offset;
+    return Some(Position::new(row, col));
+}
+
+
+// Single steps are written as 11-15, jumps list every landing square as 15x24x31.
+// The state is the position the moves are played from.
+pub fn moves_to_string(state: &CheckersState, moves: &[Move]) -> String {
+    if moves.len() == 0 {
+        return String::new();
+    }
+    let is_capture = state.captured_by(&moves[0]).is_some();
+    let separator = if is_capture { "x" } else { "-" };
+    let mut squares = vec![square_number(&moves[0].from, &state.rules).to_string()];
+    for m in moves.iter() {
+        squares.push(square_number(&m.to, &state.rules).to_string());
+    }
+    return squares.join(separator);
+}
+
+
+// Find the legal move sequence a token like 11-15, 15x24 or 15x24x31 refers to
+pub fn resolve_move(state: &CheckersState, token: &str) -> Result<Vec<Move>, String> {
+    let mut squares = Vec::<Position>::new();
+    for part in token.split(|c| c == '-' || c == 'x' || c == ':') {
+        let num: usize = part.parse().map_err(|_| format!("Invalid move {}", token))?;
+        squares.push(square_position(num, &state.rules).ok_or(format!("Invalid square {} in {}", num, token))?);
+    }
+    if squares.len() < 2 {
+        return Err(format!("Invalid move {}", token));
+    }
+
+    for sequence in state.get_possible_moves() {
+        if sequence[0].from != squares[0] || sequence[sequence.len() - 1].to != squares[squares.len() - 1] {
+            continue;
+        }
+        // intermediate squares are optional, but have to match if given
+        if squares.len() > 2 {
+            let landings: Vec<Position> = sequence.iter().map(|m| m.to).collect();
+            if landings != squares[1..] {
+                continue;
+            }
+        }
+        return Ok(sequence);
+    }
+    return Err(format!("Illegal move {}", token));
+}
+
+
+fn color_letter(color: PieceColor) -> &'static str {
+    match color {
+        PieceColor::Black => "B",
+        PieceColor::Red => "W"
+    }
+}
+
+
+// FEN as used in PDN files, e.g. B:W21,22,K23:B1,2,3
+pub fn to_fen(state: &CheckersState) -> String {
+    let dim = state.board.len();
+    let mut fen = String::from(color_letter(state.turn));
+    for color in [PieceColor::Red, PieceColor::Black] {
+        let mut squares = Vec::<(usize, PieceType)>::new();
+        for row in 0..dim {
+            for col in 0..dim {
+                if let Some(piece) = state.board[row][col] {
+                    if piece.col == color {
+                        squares.push((square_number(&Position::new(row, col), &state.rules), piece.typ));
+                    }
+                }
+            }
+        }
+        squares.sort_by_key(|(num, _)| *num);
+        let list: Vec<String> = squares.iter().map(|(num, typ)| {
+            match typ {
+                PieceType::King => format!("K{}", num),
+                PieceType::Man => num.to_string()
+            }
+        }).collect();
+        fen.push_str(&format!(":{}{}", color_letter(color), list.join(",")));
+    }
+    return fen;
+}
+
+
+pub fn from_fen(fen: &str, rules: RulesConfig) -> Result<CheckersState, String> {
+    let mut state = CheckersState::new(rules);
+    for row in state.board.iter_mut() {
+        for square in row.iter_mut() {
+            *square = None;
+        }
+    }
+
+    let fen = fen.trim().trim_end_matches('.');
+    let mut parts = fen.split(':');
+    state.turn = match parts.next().map(|p| p.trim()) {
+        Some("B") => PieceColor::Black,
+        Some("W") => PieceColor::Red,
+        _ => return Err(format!("Invalid side to move in FEN {}", fen))
+    };
+
+    for part in parts {
+        let part = part.trim();
+        if part.len() == 0 {
+            continue;
+        }
+        let col = match &part[..1] {
+            "B" => PieceColor::Black,
+            "W" => PieceColor::Red,
+            _ => return Err(format!("Invalid colour in FEN {}", fen))
+        };
+        for item in part[1..].split(',') {
+            let item = item.trim();
+            if item.len() == 0 {
+                continue;
+            }
+            let (typ, num) = match item.strip_prefix('K') {
+                Some(num) => (PieceType::King, num),
+                None => (PieceType::Man, item)
+            };
+            let num: usize = num.parse().map_err(|_| format!("Invalid square {} in FEN", item))?;
+            let pos = square_position(num, &rules).ok_or(format!("Invalid square {} in FEN", item))?;
+            state.board[pos.row][pos.col] = Some(CheckersPiece { col, typ });
+        }
+    }
+    return Ok(state);
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct PdnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: Option<String>
+}
+
+
+impl PdnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        return self.tags.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(k, _)| k == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    // Rules named by the GameType tag, American checkers if there is none
+    pub fn rules(&self) -> Result<RulesConfig, String> {
+        // PDN has no game type for giveaway, it is kept in a tag of its own
+        let giveaway = self.tag("Variant") == Some("Giveaway");
+        let game_type = match self.tag("GameType") {
+            Some(tag) => tag,
+            None => return Ok(RulesConfig { giveaway, ..RulesConfig::default() })
+        };
+        // the tag may carry board details after the number, e.g. 20,W,10,10,N2,0
+        let number = game_type.split(',').next().unwrap_or("").trim();
+        let variant = number.parse().ok().and_then(Variant::from_pdn_game_type);
+        return variant.map(|v| RulesConfig { giveaway, ..v.rules() }).ok_or(format!("Unsupported game type {}", game_type));
+    }
+
+    // Position the game starts from, taken from the FEN tag if there is one
+    pub fn start_state(&self) -> Result<CheckersState, String> {
+        let rules = self.rules()?;
+        let mut state = match self.tag("FEN") {
+            Some(fen) => from_fen(fen, rules)?,
+            None => CheckersState::new(rules)
+        };
+        // a move given by the second player can't be seen in the position
+        if let Some(handicap) = self.tag("Handicap").and_then(Handicap::from_tag) {
+            if handicap.kind == HandicapKind::Move && handicap.giver != rules.first_player {
+                state.passes = Some(handicap.giver);
+            }
+        }
+        return Ok(state);
+    }
+
+    // Play through the game returning every turn as a sequence of steps
+    pub fn resolve_turns(&self, start: &CheckersState) -> Result<Vec<Vec<Move>>, String> {
+        let mut state = start.clone();
+        let mut turns = Vec::new();
+        for token in self.moves.iter() {
+            let moves = resolve_move(&state, token)?;
+            state = state.next_state_with_move(&moves);
+            turns.push(moves);
+        }
+        return Ok(turns);
+    }
+
+    pub fn to_pdn(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in self.tags.iter() {
+            out.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        let mut line = String::new();
+        for (i, m) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                line.push_str(&format!("{}. ", i / 2 + 1));
+            }
+            line.push_str(m);
+            line.push(' ');
+            if line.len() > 70 {
+                out.push_str(line.trim_end());
+                out.push('\n');
+                line.clear();
+            }
+        }
+        line.push_str(self.result.as_deref().unwrap_or("*"));
+        out.push_str(&line);
+        out.push_str("\n\n");
+        return out;
+    }
+}
+
+
+fn is_result_token(token: &str) -> bool {
+    return ["1-0", "0-1", "2-0", "0-2", "1-1", "1/2-1/2", "*"].contains(&token);
+}
+
+
+pub fn parse_pdn(text: &str) -> Result<Vec<PdnGame>, String> {
+    let mut games = Vec::<PdnGame>::new();
+    let mut game = PdnGame::default();
+    let mut in_movetext = false;
+    let mut comment_depth = 0;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if comment_depth == 0 && line.starts_with('[') {
+            // tags after movetext start a new game
+            if in_movetext {
+                games.push(game);
+                game = PdnGame::default();
+                in_movetext = false;
+            }
+            let inner = line.trim_start_matches('[').trim_end_matches(']');
+            if let Some((name, value)) = inner.split_once(' ') {
+                game.tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+            }
+            continue;
+        }
+
+        let mut token = String::new();
+        for c in line.chars().chain(std::iter::once(' ')) {
+            match c {
+                '{' | '(' => comment_depth += 1,
+                '}' | ')' => comment_depth -= 1,
+                _ if comment_depth > 0 => {},
+                c if c.is_whitespace() => {
+                    if token.len() > 0 {
+                        in_movetext = true;
+                        if is_result_token(&token) {
+                            game.result = Some(token.clone());
+                            games.push(game);
+                            game = PdnGame::default();
+                            in_movetext = false;
+                        } else {
+                            // drop move numbers and annotations like 1. or 15-19!
+                            let mv = token.trim_end_matches(|c| c == '!' || c == '?');
+                            let mv = mv.rsplit('.').next().unwrap_or("");
+                            if mv.len() > 0 && mv.chars().any(|c| c == '-' || c == 'x' || c == ':') {
+                                game.moves.push(mv.to_string());
+                            }
+                        }
+                        token.clear();
+                    }
+                },
+                c => token.push(c)
+            }
+        }
+    }
+    if in_movetext || game.tags.len() > 0 {
+        games.push(game);
+    }
+    if games.len() == 0 {
+        return Err(String::from("No games found"));
+    }
+    return Ok(games);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rules::Variant, alphabeta::TwoPlayerGameState};
+
+    #[test]
+    fn canadian_squares_round_trip() {
+        let rules = Variant::Canadian.rules();
+        assert_eq!(rules.board_dim, 12);
+        for num in 1..=72 {
+            let pos = square_position(num, &rules).unwrap();
+            assert!(rules.is_playable(pos.row, pos.col));
+            assert_eq!(square_number(&pos, &rules), num);
+        }
+        assert_eq!(square_position(0, &rules), None);
+        assert_eq!(square_position(73, &rules), None);
+    }
+
+    #[test]
+    fn canadian_fen_round_trip() {
+        let rules = Variant::Canadian.rules();
+        let start = CheckersState::new(rules);
+        let fen = to_fen(&start);
+        let parsed = from_fen(&fen, rules).unwrap();
+        assert_eq!(parsed.board, start.board);
+        assert_eq!(parsed.turn, start.turn);
+        assert_eq!(to_fen(&parsed), fen);
+
+        let kings = from_fen("W:WK1,40,72:B5,K33,66", rules).unwrap();
+        assert_eq!(to_fen(&kings), "W:WK1,40,72:B5,K33,66");
+    }
+
+    #[test]
+    fn canadian_start_moves() {
+        let start = CheckersState::new(Variant::Canadian.rules());
+        assert_eq!(start.board.iter().flatten().flatten().count(), 60);
+        // six men on the front row, the one on the edge has a single square to go to
+        assert_eq!(start.get_possible_moves().len(), 11);
+    }
+}
@@ -0,0 +1,224 @@
+use bevy::prelude::*;
+use std::{collections::HashMap, fs};
+use serde::{Serialize, Deserialize};
+use crate::{
+    logic::Move,
+    state::{GameState, CheckersState, PieceColor},
+    checkers_events::{GameOverEvent, GameOverReason},
+    notation::{PdnGame, moves_to_string, to_fen},
+    alphabeta::TwoPlayerGameState,
+    save::{save_dir, stats_path}
+};
+
+
+pub struct CheckersRecordPlugin;
+
+
+impl Plugin for CheckersRecordPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(GameRecord::default())
+        .insert_resource(GameStats::load())
+        .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(reset_record))
+        .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(reset_record))
+        .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(reset_record))
+        .add_system(record_game_over);
+    }
+}
+
+
+// All the steps one player made in a single turn, several for multi jumps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedTurn {
+    pub player: PieceColor,
+    pub moves: Vec<Move>,
+    pub complete: bool
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GameResult {
+    pub winner: Option<PieceColor>,
+    pub reason: GameOverReason
+}
+
+
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    // position the game started from
+    pub start: Option<CheckersState>,
+    pub turns: Vec<RecordedTurn>,
+    pub result: Option<GameResult>,
+    // how the start was set up, e.g. the ballot the game was played from
+    #[serde(default)]
+    pub tags: Vec<(String, String)>
+}
+
+
+impl GameRecord {
+    pub fn push_move(&mut self, player: PieceColor, m: Move, turn_complete: bool) {
+        match self.turns.last_mut() {
+            Some(turn) if turn.player == player && !turn.complete => {
+                turn.moves.push(m);
+                turn.complete = turn_complete;
+            },
+            _ => {
+                self.turns.push(RecordedTurn { player, moves: vec![m], complete: turn_complete });
+            }
+        }
+    }
+
+    // Sets the result and counts it in the stats, a game can only end once
+    pub fn finish(&mut self, result: GameResult, stats: &mut GameStats) -> bool {
+        if self.result.is_some() {
+            return false;
+        }
+        self.result = Some(result);
+        stats.add_result(&result);
+        return true;
+    }
+
+    pub fn to_pdn_game(&self, black_name: &str, red_name: &str) -> PdnGame {
+        let mut game = PdnGame::default();
+        game.set_tag("Event", "Casual game");
+        game.set_tag("Black", black_name);
+        game.set_tag("White", red_name);
+        if let Some(ref start) = self.start {
+            game.set_tag("GameType", &start.rules.variant.pdn_game_type().to_string());
+            if start.rules.giveaway {
+                game.set_tag("Variant", "Giveaway");
+            }
+            for (name, value) in self.tags.iter() {
+                game.set_tag(name, value);
+            }
+            if to_fen(start) != to_fen(&CheckersState::new(start.rules)) {
+                game.set_tag("FEN", &to_fen(start));
+            }
+            let mut state = start.clone();
+            for turn in self.turns.iter() {
+                game.moves.push(moves_to_string(&state, &turn.moves));
+                state = state.next_state_with_move(&turn.moves);
+            }
+        }
+        // scores are given from the point of view of the side that moves first
+        let first_player = self.start.as_ref().map(|s| s.rules.first_player).unwrap_or(PieceColor::Black);
+        let result = match self.result {
+            Some(GameResult { winner: Some(winner), .. }) if winner == first_player => "2-0",
+            Some(GameResult { winner: Some(_), .. }) => "0-2",
+            Some(GameResult { winner: None, .. }) => "1-1",
+            None => "*"
+        };
+        game.set_tag("Result", result);
+        if let Some(result) = self.result {
+            game.set_tag("Termination", &format!("{:?}", result.reason));
+        }
+        game.result = Some(result.to_string());
+        return game;
+    }
+}
+
+
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+pub struct GameStats {
+    pub black_wins: u32,
+    pub red_wins: u32,
+    pub draws: u32,
+    pub by_reason: HashMap<GameOverReason, u32>
+}
+
+
+impl GameStats {
+    pub fn add_result(&mut self, result: &GameResult) {
+        match result.winner {
+            Some(PieceColor::Black) => self.black_wins += 1,
+            Some(PieceColor::Red) => self.red_wins += 1,
+            None => self.draws += 1
+        }
+        *self.by_reason.entry(result.reason).or_insert(0) += 1;
+    }
+
+    pub fn games(&self) -> u32 {
+        return self.black_wins + self.red_wins + self.draws;
+    }
+
+    pub fn label(&self) -> String {
+        return format!("Black {}   Red {}   Draws {}", self.black_wins, self.red_wins, self.draws);
+    }
+
+    // Starts over when there are no stats yet or they can't be read
+    pub fn load() -> GameStats {
+        let data = match fs::read_to_string(stats_path()) {
+            Ok(data) => data,
+            Err(_) => return GameStats::default()
+        };
+        match serde_json::from_str(&data) {
+            Ok(stats) => return stats,
+            Err(e) => {
+                info!("Could not read {:?}: {}", stats_path(), e);
+                return GameStats::default();
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        fs::create_dir_all(save_dir()).map_err(|e| e.to_string())?;
+        let data = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        return fs::write(stats_path(), data).map_err(|e| e.to_string());
+    }
+}
+
+
+fn reset_record(mut game_record: ResMut<GameRecord>, checkers_state: Res<CheckersState>) {
+    *game_record = GameRecord::default();
+    game_record.start = Some(checkers_state.clone());
+}
+
+
+fn record_game_over(mut events: EventReader<GameOverEvent>, mut game_record: ResMut<GameRecord>, mut stats: ResMut<GameStats>) {
+    for ev in events.iter() {
+        let result = GameResult { winner: ev.winner, reason: ev.reason };
+        if !game_record.finish(result, &mut stats) {
+            continue;
+        }
+        info!("Result recorded: {:?}, stats: {} - {} - {}", result, stats.black_wins, stats.red_wins, stats.draws);
+        if let Err(e) = stats.save() {
+            info!("Saving stats failed: {}", e);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Variant;
+
+    fn new_record() -> GameRecord {
+        return GameRecord { start: Some(CheckersState::new(Variant::American.rules())), ..default() };
+    }
+
+    #[test]
+    fn resignation_counts_for_the_opponent() {
+        let mut record = new_record();
+        let mut stats = GameStats::default();
+        assert!(record.finish(GameResult { winner: Some(PieceColor::Red), reason: GameOverReason::Resignation }, &mut stats));
+        assert_eq!((stats.black_wins, stats.red_wins, stats.draws), (0, 1, 0));
+        assert_eq!(stats.by_reason.get(&GameOverReason::Resignation), Some(&1));
+        let pdn = record.to_pdn_game("Black", "Red");
+        assert_eq!(pdn.tag("Result"), Some("0-2"));
+        assert_eq!(pdn.tag("Termination"), Some("Resignation"));
+    }
+
+    #[test]
+    fn agreed_draw_is_recorded_once() {
+        let mut record = new_record();
+        let mut stats = GameStats::default();
+        assert!(record.finish(GameResult { winner: None, reason: GameOverReason::AgreedDraw }, &mut stats));
+        // a flag falling after the draw was agreed changes nothing
+        assert!(!record.finish(GameResult { winner: Some(PieceColor::Black), reason: GameOverReason::FlagFall }, &mut stats));
+        assert_eq!(record.result, Some(GameResult { winner: None, reason: GameOverReason::AgreedDraw }));
+        assert_eq!((stats.black_wins, stats.red_wins, stats.draws), (0, 0, 1));
+        assert_eq!(stats.games(), 1);
+        assert_eq!(record.to_pdn_game("Black", "Red").tag("Result"), Some("1-1"));
+    }
+}
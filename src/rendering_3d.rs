@@ -2,7 +2,9 @@ use bevy::{prelude::*};
 use crate::{
     config::BoardConfig,
     state::*,
-    checkers_events::*, logic::{Position, PostAnimationState}
+    checkers_events::*, logic::{Move, Position, PostAnimationState, PossibleMoves, next_turn_state},
+    ai::AIStatus,
+    rules::RulesConfig
 };
 use std::f32::consts::PI;
 
@@ -24,6 +26,13 @@ impl Plugin for CheckersRendering3dPlugin {
         .add_system_set(SystemSet::on_exit(GameState::Move).with_system(handle_kill))
         .add_system_set(SystemSet::on_exit(GameState::Move).with_system(handle_upgrade.after(handle_move)))
         .add_system_set(SystemSet::on_update(GameState::Animating).with_system(cleanup_players_clips))
+        .add_system_set(SystemSet::on_update(GameState::ReplaySetup).with_system(setup_view_board))
+        .add_system_set(SystemSet::on_update(GameState::EditorSetup).with_system(setup_view_board))
+        .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(cleanup_board))
+        .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(cleanup_board))
+        .add_system_set(SystemSet::on_exit(GameState::Replay).with_system(handle_replay_step))
+        .add_system_set(SystemSet::on_update(GameState::ReplayAnimating).with_system(cleanup_players_clips))
+        .add_system(handle_redraw_pieces)
         ;
     }
 }
@@ -118,36 +127,45 @@ fn handle_upgrade(
 ){
 
     for event in upgrade_event.iter(){
-        let sq_dim: f32 = (board_config.world_dim - (board_config.border_size * 2.0)) / board_config.board_dim as f32;
-        let scaled_sq_dim: f32 = board_config.piece_scale * sq_dim;
-
         for (entity, mut piece_component) in query.iter_mut(){
             if piece_component.pos == event.pos {
                 piece_component.typ = PieceType::King;
-                let color = match piece_component.color {
-                    PieceColor::Black => Color::rgb(0.25, 0.25, 0.25),
-                    PieceColor::Red => Color::RED,
-                };
-                let child = commands.spawn(PbrBundle {
-                    mesh: meshes.add(Mesh::from(shape::Box{
-                                                                min_x: - scaled_sq_dim / 2.0,
-                                                                max_x: scaled_sq_dim / 2.0,
-                                                                min_y: - board_config.piece_height / 2.0,
-                                                                max_y: (board_config.piece_height / 2.0) * 1.75,
-                                                                min_z: - scaled_sq_dim / 2.0,
-                                                                max_z: scaled_sq_dim / 2.0
-                                                        })),
-                    material: materials.add(color.into()),
-                    transform: Transform::from_scale(Vec3{x: 0.8, y: 1.0, z: 0.8}),
-                    ..default()
-                }).id();
-                commands.entity(entity).push_children(&[child]);
+                add_king_crown(&mut commands, &mut meshes, &mut materials, &board_config, entity, piece_component.color);
             }
         }
     }
 }
 
 
+fn piece_color(color: PieceColor) -> Color {
+    match color {
+        PieceColor::Black => Color::rgb(0.25, 0.25, 0.25),
+        PieceColor::Red => Color::RED,
+    }
+}
+
+
+// Kings are marked by a taller box stacked on the piece
+fn add_king_crown(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materials: &mut ResMut<Assets<StandardMaterial>>, board_config: &Res<BoardConfig>, entity: Entity, color: PieceColor){
+    let sq_dim: f32 = (board_config.world_dim - (board_config.border_size * 2.0)) / board_config.board_dim as f32;
+    let scaled_sq_dim: f32 = board_config.piece_scale * sq_dim;
+    let child = commands.spawn(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Box{
+                                                    min_x: - scaled_sq_dim / 2.0,
+                                                    max_x: scaled_sq_dim / 2.0,
+                                                    min_y: - board_config.piece_height / 2.0,
+                                                    max_y: (board_config.piece_height / 2.0) * 1.75,
+                                                    min_z: - scaled_sq_dim / 2.0,
+                                                    max_z: scaled_sq_dim / 2.0
+                                            })),
+        material: materials.add(piece_color(color).into()),
+        transform: Transform::from_scale(Vec3{x: 0.8, y: 1.0, z: 0.8}),
+        ..default()
+    }).id();
+    commands.entity(entity).push_children(&[child]);
+}
+
+
 pub fn compute_piece_center(row: usize, col: usize, board_config: &BoardConfig) -> Vec3{
     let sq_dim: f32 = (board_config.world_dim - (board_config.border_size * 2.0)) / board_config.board_dim as f32;
     let scaled_sq_dim: f32 = board_config.piece_scale * sq_dim;
@@ -172,68 +190,101 @@ fn handle_piece_deselection(mut query: Query<(&mut Transform, &mut PieceComponen
 
 
 // System to add board and pieces
-fn setup_board(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>, board_config: Res<BoardConfig>, checkers_state: Res<CheckersState>, mut game_state: ResMut<State<GameState>>){
-    add_board(&mut commands, &mut meshes, &mut materials, &board_config);
+fn setup_board(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    board_config: Res<BoardConfig>,
+    checkers_state: Res<CheckersState>,
+    ai_status: Res<AIStatus>,
+    possible_moves: Res<PossibleMoves>,
+    mut game_state: ResMut<State<GameState>>
+){
+    add_board(&mut commands, &mut meshes, &mut materials, &board_config, &checkers_state.rules);
     add_pieces(&mut commands, &mut meshes, &mut materials, &board_config, &checkers_state);
-    game_state.set(GameState::Input).unwrap();
+    // a resumed game may continue with the AI or in the middle of a jump
+    game_state.set(next_turn_state(&checkers_state, &ai_status, &possible_moves)).unwrap();
 }
 
 
-// Function to add pieces using basic shapes
-fn add_pieces(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materials: &mut ResMut<Assets<StandardMaterial>>, board_config: &Res<BoardConfig>, checkers_state: &Res<CheckersState>){
-    let sq_dim: f32 = (board_config.world_dim - (board_config.border_size * 2.0)) / board_config.board_dim as f32;
-    let scaled_sq_dim: f32 = board_config.piece_scale * sq_dim;
+// Board for the replay viewer and the position editor, nobody is to move yet
+fn setup_view_board(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut materials: ResMut<Assets<StandardMaterial>>, board_config: Res<BoardConfig>, checkers_state: Res<CheckersState>, mut game_state: ResMut<State<GameState>>){
+    add_board(&mut commands, &mut meshes, &mut materials, &board_config, &checkers_state.rules);
+    add_pieces(&mut commands, &mut meshes, &mut materials, &board_config, &checkers_state);
+    let next_state = match game_state.current() {
+        GameState::EditorSetup => GameState::Editor,
+        _ => GameState::Replay
+    };
+    game_state.set(next_state).unwrap();
+}
+
 
+// The editor and rematches leave through BoardSetup, which builds the board again
+fn cleanup_board(mut commands: Commands, query: Query<Entity, Or<(With<BoardComponent>, With<PieceComponent>)>>){
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+
+// Replaces all pieces with the current state, used when jumping around in a replay
+fn handle_redraw_pieces(
+    mut commands: Commands,
+    mut events: EventReader<RedrawPiecesEvent>,
+    query: Query<Entity, With<PieceComponent>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    board_config: Res<BoardConfig>,
+    checkers_state: Res<CheckersState>
+){
+    if events.iter().count() == 0 {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    add_pieces(&mut commands, &mut meshes, &mut materials, &board_config, &checkers_state);
+}
+
+
+// Function to add pieces using basic shapes
+fn add_pieces(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materials: &mut ResMut<Assets<StandardMaterial>>, board_config: &Res<BoardConfig>, checkers_state: &CheckersState){
     for row in 0..board_config.board_dim {
         for col in 0..board_config.board_dim {
-            match checkers_state.board[row][col] {    
-                None => (),
-                Some(piece) => {
-                    let color = match piece.col {
-                        PieceColor::Black => Color::rgb(0.25, 0.25, 0.25),
-                        PieceColor::Red => Color::RED,
-                    };
-
-                    let position = compute_piece_center(row, col, board_config);
-
-                    let parent = commands.spawn(PbrBundle {
-                        mesh: meshes.add(Mesh::from(shape::Box{
-                                                                min_x: - scaled_sq_dim / 2.0,
-                                                                max_x: scaled_sq_dim / 2.0,
-                                                                min_y: - board_config.piece_height / 2.0,
-                                                                max_y: board_config.piece_height / 2.0,
-                                                                min_z: - scaled_sq_dim / 2.0,
-                                                                max_z: scaled_sq_dim / 2.0
-                                        })),
-                        material: materials.add(color.into()),
-                        transform: Transform::from_xyz(position.x, position.y, position.z),
-                        ..default()
-                    }).insert(PieceComponent{pos: Position::new(row, col), color: piece.col, typ: piece.typ}).id();
-                    if piece.typ == PieceType::King {
-                        let child = commands.spawn(PbrBundle {
-                            mesh: meshes.add(Mesh::from(shape::Box{
-                                                                        min_x: - scaled_sq_dim / 2.0,
-                                                                        max_x: scaled_sq_dim / 2.0,
-                                                                        min_y: - board_config.piece_height / 2.0,
-                                                                        max_y: (board_config.piece_height / 2.0) * 1.75,
-                                                                        min_z: - scaled_sq_dim / 2.0,
-                                                                        max_z: scaled_sq_dim / 2.0
-                                                                })),
-                            material: materials.add(color.into()),
-                            transform: Transform::from_scale(Vec3{x: 0.8, y: 1.0, z: 0.8}),
-                            ..default()
-                        }).id();
-                        commands.entity(parent).push_children(&[child]);
-                    }
-                }
+            if let Some(piece) = checkers_state.board[row][col] {
+                let position = compute_piece_center(row, col, board_config);
+                spawn_piece(commands, meshes, materials, board_config, Position::new(row, col), piece, position);
             }
-            
         }
     }
 }
 
+
+fn spawn_piece(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materials: &mut ResMut<Assets<StandardMaterial>>, board_config: &Res<BoardConfig>, pos: Position, piece: CheckersPiece, position: Vec3) -> Entity {
+    let sq_dim: f32 = (board_config.world_dim - (board_config.border_size * 2.0)) / board_config.board_dim as f32;
+    let scaled_sq_dim: f32 = board_config.piece_scale * sq_dim;
+
+    let parent = commands.spawn(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Box{
+                                                min_x: - scaled_sq_dim / 2.0,
+                                                max_x: scaled_sq_dim / 2.0,
+                                                min_y: - board_config.piece_height / 2.0,
+                                                max_y: board_config.piece_height / 2.0,
+                                                min_z: - scaled_sq_dim / 2.0,
+                                                max_z: scaled_sq_dim / 2.0
+                        })),
+        material: materials.add(piece_color(piece.col).into()),
+        transform: Transform::from_xyz(position.x, position.y, position.z),
+        ..default()
+    }).insert(PieceComponent{pos, color: piece.col, typ: piece.typ}).id();
+    if piece.typ == PieceType::King {
+        add_king_crown(commands, meshes, materials, board_config, parent, piece.col);
+    }
+    return parent;
+}
+
 // Function to add board using basic shapes
-fn add_board(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materials: &mut ResMut<Assets<StandardMaterial>>, board_config: &Res<BoardConfig>){
+fn add_board(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materials: &mut ResMut<Assets<StandardMaterial>>, board_config: &Res<BoardConfig>, rules: &RulesConfig){
     let sq_dim: f32 = (board_config.world_dim - (board_config.border_size * 2.0)) / board_config.board_dim as f32;
 
     // draw top border
@@ -300,7 +351,12 @@ fn add_board(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materia
     // draw the squares
     for z in 0..board_config.board_dim {
         for x in 0..board_config.board_dim {
-            if (x + (z % 2)) % 2 == 1 {
+            if rules.is_playable(z, x) {
+                // when every square is used the light ones are shaded to keep the grid visible
+                let square_color = match (z + x) % 2 == rules.dark_square_parity {
+                    true => Color::rgb(1.0, 1.0, 1.0),
+                    false => Color::rgb(0.7, 0.7, 0.7)
+                };
                 let sq_offset_x = board_config.offset_x + board_config.border_size + x as f32 * sq_dim;
                 let sq_offset_z = board_config.offset_z + board_config.border_size + z as f32 * sq_dim;
                 let child = commands.spawn(PbrBundle {
@@ -312,7 +368,7 @@ fn add_board(commands: &mut Commands, meshes: &mut ResMut<Assets<Mesh>>, materia
                                                             min_z: sq_offset_z,
                                                             max_z: sq_offset_z + sq_dim
                                     })),
-                    material: materials.add(Color::rgb(1.0, 1.0, 1.0).into()),
+                    material: materials.add(square_color.into()),
                     ..default()
                 }).insert(BoardSquareComponent{pos: Position::new(z, x)}).id();
                 commands.entity(board).push_children(&[child]);
@@ -418,6 +474,74 @@ fn handle_piece_selection(
 
 
 
+// Builds the animation for a piece moving, jumps arc over the captured square
+fn move_clip(entity: &Entity, game_move: &Move, is_jump: bool, translation: Vec3, board_config: &BoardConfig) -> (AnimationClip, f32) {
+    let center = compute_piece_center(game_move.to.row, game_move.to.col, board_config);
+    let duration: f32 = match is_jump {
+        true => {0.6},
+        false => {0.35}
+    };
+
+    let clip = match is_jump{
+        true => {
+            let source_center = compute_piece_center(game_move.from.row, game_move.from.col, board_config);
+            let mid_center = (source_center + center) / 2.0;
+
+            let mut points = Vec::<Vec3>::new();
+            const NUM_STEPS: i32 = 20;
+            let mut t = Transform::from_translation(source_center);
+            t.look_at(mid_center, Vec3::Y);
+             for _ in 0..NUM_STEPS {
+                 t.translate_around(mid_center, Quat::from_axis_angle(-t.local_x(), PI / NUM_STEPS as f32));
+                 points.push(t.transform_point(Vec3::ZERO));
+            }
+
+            create_translation_clip(
+                entity,
+                duration,
+                &points
+            )
+        },
+        false => {
+            create_translation_clip(
+                entity,
+                duration,
+                &vec![translation, center]
+            )
+        },
+    };
+    return (clip, duration);
+}
+
+
+fn sunk_translation(translation: Vec3, board_config: &BoardConfig) -> Vec3 {
+    return Vec3{  x: translation.x,
+                  y: translation.y - (board_config.piece_height + 0.02),
+                  z: translation.z };
+}
+
+
+// Captured pieces sink into the board, in reverse they rise back out of it
+fn kill_clip(entity: &Entity, translation: Vec3, board_config: &BoardConfig, reverse: bool) -> (AnimationClip, f32) {
+    let duration: f32 = 0.3;
+    let sunk = sunk_translation(translation, board_config);
+    let points = match reverse {
+        true => vec![sunk, translation],
+        false => vec![translation, sunk]
+    };
+    return (create_translation_clip(entity, duration, &points), duration);
+}
+
+
+fn play_clip(commands: &mut Commands, entity: Entity, clip: AnimationClip, duration: f32, despawn: bool, animation_assets: &mut ResMut<Assets<AnimationClip>>) {
+    let handle = animation_assets.add(clip);
+    let  mut player = AnimationPlayer::default();
+    player.play(handle.clone());
+    commands.entity(entity).insert(PlayerData{duration: duration, clip: handle.clone(), despawn});
+    commands.entity(entity).insert(player);
+}
+
+
 fn handle_move(
     mut commands: Commands,
     mut move_event: EventReader<PieceMoveEvent>,
@@ -430,51 +554,8 @@ fn handle_move(
         for (entity, transform, mut piece_component) in query.iter_mut(){
             if piece_component.pos == event.game_move.from {
                 piece_component.pos = event.game_move.to;
-                let center = compute_piece_center(event.game_move.to.row, event.game_move.to.col, &board_config);
-                let translation = transform.translation;
-                let is_jump = event.game_move.is_jump();
-                let duration: f32 = match is_jump {
-                    true => {0.6},
-                    false => {0.35}
-                };
-
-                
-                let clip = match is_jump{
-                    true => {
-                        let mid_pos = event.game_move.middle_pos().unwrap();
-                        let source_center = compute_piece_center(event.game_move.from.row, event.game_move.from.col, &board_config);
-                        let mid_center =  compute_piece_center(mid_pos.row, mid_pos.col, &board_config);
-
-                        let mut points = Vec::<Vec3>::new();
-                        const NUM_STEPS: i32 = 20;
-                        let mut t = Transform::from_translation(source_center);
-                        t.look_at(mid_center, Vec3::Y);
-                         for _ in 0..NUM_STEPS {
-                             t.translate_around(mid_center, Quat::from_axis_angle(-t.local_x(), PI / NUM_STEPS as f32));
-                             points.push(t.transform_point(Vec3::ZERO));
-                        }
-
-                        
-                        create_translation_clip(
-                            &entity,
-                            duration, 
-                            &points
-                        )
-                    },
-                    false => {
-                        create_translation_clip(
-                            &entity,
-                            duration, 
-                            &vec![translation, center]
-                        )
-                    },
-                };
-
-                let handle = animation_assets.add(clip);
-                let  mut player = AnimationPlayer::default();
-                player.play(handle.clone());
-                commands.entity(entity).insert(PlayerData{duration: duration, clip: handle.clone(), despawn: false});
-                commands.entity(entity).insert(player);
+                let (clip, duration) = move_clip(&entity, &event.game_move, event.is_capture, transform.translation, &board_config);
+                play_clip(&mut commands, entity, clip, duration, false, &mut animation_assets);
             }
         }
     }
@@ -490,22 +571,65 @@ fn handle_kill(
 ){
     for event in kill_event.iter(){
         for (entity, transform, piece_component) in query.iter(){
-            if piece_component.pos == event.pos {          
-                let translation = transform.translation;
-                let duration: f32 = 0.3;
-                let clip = create_translation_clip(
-                    &entity,
-                    duration, 
-                    &vec![translation, Vec3{  x: translation.x,
-                                                            y: translation.y - (board_config.piece_height + 0.02),
-                                                            z: translation.z }]
-                );
-                let handle = animation_assets.add(clip);
-                let  mut player = AnimationPlayer::default();
-                player.play(handle.clone());
-                commands.entity(entity).insert(PlayerData{duration: duration, clip: handle.clone(), despawn: true});
-                commands.entity(entity).insert(player);
+            if piece_component.pos == event.pos {
+                let (clip, duration) = kill_clip(&entity, transform.translation, &board_config, false);
+                play_clip(&mut commands, entity, clip, duration, true, &mut animation_assets);
+            }
+        }
+    }
+}
+
+
+// Plays a replay step with the same animations as the game, or undoes it when reversed
+fn handle_replay_step(
+    mut commands: Commands,
+    mut step_event: EventReader<ReplayStepEvent>,
+    mut query: Query<(Entity, &Transform, &mut PieceComponent, Option<&Children>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    board_config: Res<BoardConfig>,
+    mut animation_assets: ResMut<Assets<AnimationClip>>
+){
+    for event in step_event.iter() {
+        if !event.reverse {
+            for (entity, transform, mut piece_component, _) in query.iter_mut() {
+                if piece_component.pos == event.game_move.from {
+                    piece_component.pos = event.game_move.to;
+                    let (clip, duration) = move_clip(&entity, &event.game_move, event.captured.is_some(), transform.translation, &board_config);
+                    play_clip(&mut commands, entity, clip, duration, false, &mut animation_assets);
+                    if event.upgraded {
+                        piece_component.typ = PieceType::King;
+                        add_king_crown(&mut commands, &mut meshes, &mut materials, &board_config, entity, piece_component.color);
+                    }
+                } else if event.captured.map(|(pos, _)| pos) == Some(piece_component.pos) {
+                    let (clip, duration) = kill_clip(&entity, transform.translation, &board_config, false);
+                    play_clip(&mut commands, entity, clip, duration, true, &mut animation_assets);
+                }
+            }
+        } else {
+            let reversed = Move { from: event.game_move.to, to: event.game_move.from };
+            for (entity, transform, mut piece_component, children) in query.iter_mut() {
+                if piece_component.pos == reversed.from {
+                    piece_component.pos = reversed.to;
+                    let (clip, duration) = move_clip(&entity, &reversed, event.captured.is_some(), transform.translation, &board_config);
+                    play_clip(&mut commands, entity, clip, duration, false, &mut animation_assets);
+                    if event.upgraded {
+                        piece_component.typ = PieceType::Man;
+                        if let Some(children) = children {
+                            for child in children.iter() {
+                                commands.entity(*child).despawn_recursive();
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some((pos, piece)) = event.captured {
+                let translation = compute_piece_center(pos.row, pos.col, &board_config);
+                let sunk = sunk_translation(translation, &board_config);
+                let entity = spawn_piece(&mut commands, &mut meshes, &mut materials, &board_config, pos, piece, sunk);
+                let (clip, duration) = kill_clip(&entity, translation, &board_config, true);
+                play_clip(&mut commands, entity, clip, duration, false, &mut animation_assets);
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,471 @@
+use bevy::prelude::*;
+use std::{fs, path::PathBuf};
+use crate::{
+    config::BoardConfig,
+    state::{GameState, CheckersState, CheckersPiece},
+    logic::{Move, Position, PostAnimationState},
+    checkers_events::{ButtonSelectEvent, ReplayStepEvent, RedrawPiecesEvent},
+    notation::parse_pdn,
+    save::{read_session, archive_path},
+    ui::{spawn_button, ButtonLayout, BUTTON_BACKGROUND, BUTTON_HOVERED, BUTTON_FONT_COLOR, BUTTON_FONT_SIZE}
+};
+
+
+pub struct CheckersReplayPlugin;
+
+
+impl Plugin for CheckersReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(ReplayRequest { path: None })
+        .insert_resource(Replay::default())
+        .add_system_set(SystemSet::on_update(GameState::Loading).with_system(load_replay))
+        .add_system_set(SystemSet::on_enter(GameState::ReplaySetup).with_system(setup_replay_controls))
+        .add_system_set(SystemSet::on_update(GameState::Replay).with_system(autoplay))
+        .add_system(replay_button_system)
+        .add_system(update_replay_controls);
+    }
+}
+
+
+// Seconds between steps when playing at normal speed
+const STEP_INTERVAL: f32 = 0.8;
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+
+// Game file the menu asked to replay, picked up in GameState::Loading
+#[derive(Resource)]
+pub struct ReplayRequest {
+    pub path: Option<PathBuf>
+}
+
+
+// A single step of a turn together with what it changed, so it can be undone
+#[derive(Clone)]
+pub struct ReplayStep {
+    pub game_move: Move,
+    pub before: CheckersState,
+    pub captured: Option<(Position, CheckersPiece)>,
+    pub upgraded: bool
+}
+
+
+#[derive(Resource, Default)]
+pub struct Replay {
+    pub steps: Vec<ReplayStep>,
+    pub final_state: Option<CheckersState>,
+    // number of steps played so far
+    pub cursor: usize,
+    pub playing: bool,
+    pub speed_idx: usize,
+    timer: f32
+}
+
+
+impl Replay {
+    pub fn new(start: &CheckersState, turns: &Vec<Vec<Move>>) -> Self {
+        let mut state = start.clone();
+        let mut steps = Vec::new();
+        for turn in turns.iter() {
+            for m in turn.iter() {
+                let before = state.clone();
+                let captured = before.captured_by(m).map(|pos| (pos, before.board[pos.row][pos.col].unwrap()));
+                let (_, upgraded, _) = state.update_with_move(m);
+                steps.push(ReplayStep { game_move: *m, before, captured, upgraded });
+            }
+        }
+        return Replay { steps, final_state: Some(state), cursor: 0, playing: false, speed_idx: 2, timer: 0. };
+    }
+
+    pub fn speed(&self) -> f32 {
+        return SPEEDS[self.speed_idx];
+    }
+
+    // State of the board after `cursor` steps
+    pub fn state_at(&self, cursor: usize) -> CheckersState {
+        if cursor < self.steps.len() {
+            return self.steps[cursor].before.clone();
+        }
+        return self.final_state.clone().unwrap();
+    }
+
+    // Plays the next step, None at the end of the game
+    pub fn step_forward(&mut self) -> Option<ReplayStep> {
+        let step = self.steps.get(self.cursor)?.clone();
+        self.cursor += 1;
+        return Some(step);
+    }
+
+    // Takes back the last step played, None at the start of the game
+    pub fn step_back(&mut self) -> Option<ReplayStep> {
+        if self.cursor == 0 {
+            return None;
+        }
+        self.cursor -= 1;
+        return Some(self.steps[self.cursor].clone());
+    }
+
+    pub fn jump_to(&mut self, cursor: usize) {
+        self.cursor = cursor.min(self.steps.len());
+    }
+}
+
+
+// Replays the game given with --replay, or else the last finished game
+pub fn replay_source() -> Option<PathBuf> {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(idx) = args.iter().position(|a| a == "--replay") {
+        if let Some(path) = args.get(idx + 1) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    if archive_path().exists() {
+        return Some(archive_path());
+    }
+    return None;
+}
+
+
+// Reads the last game of a PDN file or a saved game
+fn read_replay(path: &PathBuf) -> Result<(CheckersState, Vec<Vec<Move>>), String> {
+    let is_pdn = path.extension().map(|e| e == "pdn").unwrap_or(false);
+    if is_pdn {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let games = parse_pdn(&text)?;
+        let game = games.last().unwrap();
+        let start = game.start_state()?;
+        let turns = game.resolve_turns(&start)?;
+        return Ok((start, turns));
+    }
+    let session = read_session(path)?;
+    let start = session.record.start.ok_or(String::from("Saved game has no start position"))?;
+    let turns = session.record.turns.iter().map(|t| t.moves.clone()).collect();
+    return Ok((start, turns));
+}
+
+
+fn load_replay(
+    mut replay_request: ResMut<ReplayRequest>,
+    mut replay: ResMut<Replay>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut board_config: ResMut<BoardConfig>,
+    mut game_state: ResMut<State<GameState>>
+){
+    if let Some(path) = replay_request.path.take() {
+        match read_replay(&path) {
+            Ok((start, turns)) => {
+                info!("Replaying {:?}, {} turns", path, turns.len());
+                board_config.board_dim = start.rules.board_dim;
+                *replay = Replay::new(&start, &turns);
+                *checkers_state = start;
+                game_state.set(GameState::ReplaySetup).unwrap();
+            },
+            Err(e) => {
+                info!("Could not replay {:?}: {}", path, e);
+                game_state.set(GameState::Menu).unwrap();
+            }
+        }
+    }
+}
+
+
+fn step_forward(replay: &mut Replay, checkers_state: &mut CheckersState, step_writer: &mut EventWriter<ReplayStepEvent>) -> bool {
+    let step = match replay.step_forward() {
+        Some(step) => step,
+        None => return false
+    };
+    step_writer.send(ReplayStepEvent { game_move: step.game_move, captured: step.captured, upgraded: step.upgraded, reverse: false });
+    *checkers_state = replay.state_at(replay.cursor);
+    return true;
+}
+
+
+fn step_back(replay: &mut Replay, checkers_state: &mut CheckersState, step_writer: &mut EventWriter<ReplayStepEvent>) -> bool {
+    let step = match replay.step_back() {
+        Some(step) => step,
+        None => return false
+    };
+    step_writer.send(ReplayStepEvent { game_move: step.game_move, captured: step.captured, upgraded: step.upgraded, reverse: true });
+    *checkers_state = step.before;
+    return true;
+}
+
+
+// Jumps straight to a step without animating, as used by the scrub bar
+fn jump_to(replay: &mut Replay, cursor: usize, checkers_state: &mut CheckersState, redraw_writer: &mut EventWriter<RedrawPiecesEvent>) {
+    replay.jump_to(cursor);
+    *checkers_state = replay.state_at(replay.cursor);
+    redraw_writer.send(RedrawPiecesEvent);
+}
+
+
+fn autoplay(
+    time: Res<Time>,
+    mut replay: ResMut<Replay>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut step_writer: EventWriter<ReplayStepEvent>,
+    mut game_state: ResMut<State<GameState>>,
+    mut post_animation_state: ResMut<PostAnimationState>
+){
+    if !replay.playing {
+        return;
+    }
+    replay.timer += time.delta_seconds() * replay.speed();
+    if replay.timer < STEP_INTERVAL {
+        return;
+    }
+    replay.timer = 0.;
+    if step_forward(&mut replay, &mut checkers_state, &mut step_writer) {
+        post_animation_state.state = GameState::Replay;
+        game_state.set(GameState::ReplayAnimating).unwrap();
+    } else {
+        replay.playing = false;
+    }
+}
+
+
+#[derive(Component)]
+struct ReplayControls;
+
+
+#[derive(Component)]
+struct ReplayProgress;
+
+
+#[derive(Component)]
+struct ReplayLabel;
+
+
+fn setup_replay_controls(mut commands: Commands, asset_server: Res<AssetServer>, query: Query<Entity, With<ReplayControls>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let font: Handle<Font> = asset_server.load("fonts/MunichRegular.ttf");
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.), Val::Auto),
+            position_type: PositionType::Absolute,
+            position: UiRect { bottom: Val::Px(20.), left: Val::Px(0.), ..default() },
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        ..default()
+    })
+    .insert(ReplayControls)
+    .with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: BUTTON_FONT_SIZE,
+                color: BUTTON_FONT_COLOR,
+            },
+        )).insert(ReplayLabel);
+
+        // scrub bar, clicking on it jumps to that point of the game
+        parent.spawn(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Percent(60.), Val::Px(16.)),
+                margin: UiRect::all(Val::Px(10.)),
+                ..default()
+            },
+            background_color: BUTTON_BACKGROUND.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                    ..default()
+                },
+                background_color: Color::rgb(0.6, 0.6, 0.6).into(),
+                ..default()
+            }).insert(Name::new("SCRUB")).insert(ReplayProgress);
+        });
+
+        parent.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            let layout = ButtonLayout::new(Size::new(Val::Px(90.), Val::Px(45.)), UiRect::all(Val::Px(5.)));
+            spawn_button(parent, &font, "|<", "START", layout);
+            spawn_button(parent, &font, "<", "BACK", layout);
+            spawn_button(parent, &font, "Play", "PLAY", layout);
+            spawn_button(parent, &font, ">", "FORWARD", layout);
+            spawn_button(parent, &font, ">|", "END", layout);
+            spawn_button(parent, &font, "Slower", "SLOWER", layout);
+            spawn_button(parent, &font, "Faster", "FASTER", layout);
+        });
+    });
+}
+
+
+fn replay_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &Children, &Node, &GlobalTransform),
+        (Changed<Interaction>, With<Button>)
+    >,
+    text_query: Query<&Name>,
+    windows: Res<Windows>,
+    mut replay: ResMut<Replay>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut step_writer: EventWriter<ReplayStepEvent>,
+    mut redraw_writer: EventWriter<RedrawPiecesEvent>,
+    mut select_event: EventWriter<ButtonSelectEvent>,
+    mut game_state: ResMut<State<GameState>>,
+    mut post_animation_state: ResMut<PostAnimationState>
+){
+    let current = game_state.current().clone();
+    if current != GameState::Replay && current != GameState::ReplayAnimating {
+        return;
+    }
+
+    for (interaction, mut color, children, node, transform) in &mut interaction_query {
+        let name = match text_query.get(children[0]) {
+            Ok(name) => name,
+            Err(_) => continue
+        };
+        match *interaction {
+            Interaction::Hovered => {
+                if name.as_str() != "SCRUB" {
+                    *color = BUTTON_HOVERED.into();
+                }
+            },
+            Interaction::None => {
+                *color = BUTTON_BACKGROUND.into();
+            },
+            Interaction::Clicked => {
+                select_event.send(ButtonSelectEvent);
+                match name.as_str() {
+                    "PLAY" => {
+                        replay.playing = !replay.playing;
+                        continue;
+                    },
+                    "SLOWER" => {
+                        replay.speed_idx = replay.speed_idx.saturating_sub(1);
+                        continue;
+                    },
+                    "FASTER" => {
+                        replay.speed_idx = (replay.speed_idx + 1).min(SPEEDS.len() - 1);
+                        continue;
+                    },
+                    _ => {}
+                }
+
+                // moving around has to wait for the current animation
+                if current != GameState::Replay {
+                    continue;
+                }
+                replay.playing = false;
+                let animated = match name.as_str() {
+                    "FORWARD" => step_forward(&mut replay, &mut checkers_state, &mut step_writer),
+                    "BACK" => step_back(&mut replay, &mut checkers_state, &mut step_writer),
+                    "START" => {
+                        jump_to(&mut replay, 0, &mut checkers_state, &mut redraw_writer);
+                        false
+                    },
+                    "END" => {
+                        let end = replay.steps.len();
+                        jump_to(&mut replay, end, &mut checkers_state, &mut redraw_writer);
+                        false
+                    },
+                    "SCRUB" => {
+                        if let Some(cursor_pos) = windows.get_primary().and_then(|w| w.cursor_position()) {
+                            let width = node.size().x;
+                            let left = transform.translation().x - width / 2.;
+                            let fraction = ((cursor_pos.x - left) / width).clamp(0., 1.);
+                            let target = (fraction * replay.steps.len() as f32).round() as usize;
+                            jump_to(&mut replay, target, &mut checkers_state, &mut redraw_writer);
+                        }
+                        false
+                    },
+                    _ => false
+                };
+                if animated {
+                    post_animation_state.state = GameState::Replay;
+                    game_state.set(GameState::ReplayAnimating).unwrap();
+                }
+            }
+        }
+    }
+}
+
+
+fn update_replay_controls(
+    replay: Res<Replay>,
+    mut progress_query: Query<&mut Style, With<ReplayProgress>>,
+    mut label_query: Query<&mut Text, With<ReplayLabel>>,
+    mut button_text_query: Query<(&Name, &mut Text), Without<ReplayLabel>>
+){
+    if !replay.is_changed() {
+        return;
+    }
+    let total = replay.steps.len().max(1);
+    for mut style in progress_query.iter_mut() {
+        style.size.width = Val::Percent(100. * replay.cursor as f32 / total as f32);
+    }
+    for mut text in label_query.iter_mut() {
+        text.sections[0].value = format!("Step {} / {}   Speed x{}", replay.cursor, replay.steps.len(), replay.speed());
+    }
+    for (name, mut text) in button_text_query.iter_mut() {
+        if name.as_str() == "PLAY" {
+            text.sections[0].value = String::from(if replay.playing { "Pause" } else { "Play" });
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rules::Variant, notation::{resolve_move, to_fen}};
+
+    // 11-15 22-18 15x22 25x18, the last two turns capture
+    fn known_game() -> (CheckersState, Vec<Vec<Move>>) {
+        let start = CheckersState::new(Variant::American.rules());
+        let mut state = start.clone();
+        let mut turns = Vec::new();
+        for token in ["11-15", "22-18", "15x22", "25x18"] {
+            let moves = resolve_move(&state, token).unwrap();
+            for m in moves.iter() {
+                state.update_with_move(m);
+            }
+            turns.push(moves);
+        }
+        return (start, turns);
+    }
+
+    #[test]
+    fn steps_through_a_game() {
+        let (start, turns) = known_game();
+        let mut replay = Replay::new(&start, &turns);
+        assert_eq!(replay.steps.len(), 4);
+        assert_eq!(to_fen(&replay.state_at(0)), to_fen(&start));
+
+        let mut played = 0;
+        while let Some(step) = replay.step_forward() {
+            played += 1;
+            assert_eq!(step.captured.is_some(), played >= 3);
+        }
+        assert_eq!(replay.cursor, 4);
+        let end = to_fen(&replay.state_at(4));
+        assert_eq!(end, to_fen(replay.final_state.as_ref().unwrap()));
+
+        let step = replay.step_back().unwrap();
+        assert_eq!(step.game_move, turns[3][0]);
+        assert_eq!(replay.cursor, 3);
+        assert_eq!(to_fen(&replay.state_at(3)), to_fen(&step.before));
+
+        replay.jump_to(0);
+        assert!(replay.step_back().is_none());
+        replay.jump_to(100);
+        assert_eq!(replay.cursor, 4);
+        assert_eq!(to_fen(&replay.state_at(replay.cursor)), end);
+    }
+}
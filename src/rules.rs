@@ -0,0 +1,294 @@
+use serde::{Serialize, Deserialize};
+use std::ops::Range;
+use crate::state::PieceColor;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Variant {
+    American,
+    International,
+    Russian,
+    Italian,
+    Brazilian,
+    Pool,
+    Canadian,
+    Turkish,
+    Frisian
+}
+
+
+impl Variant {
+    pub const ALL: [Variant; 9] = [
+        Variant::American, Variant::International, Variant::Russian, Variant::Italian, Variant::Brazilian, Variant::Pool,
+        Variant::Canadian, Variant::Turkish, Variant::Frisian
+    ];
+
+    pub fn rules(&self) -> RulesConfig {
+        match self {
+            Variant::American => RulesConfig {
+                variant: *self,
+                board_dim: 8,
+                flying_kings: false,
+                men_capture_backwards: false,
+                men_capture_kings: true,
+                capture_rule: CaptureRule::Free,
+                crowning: Crowning::EndsTurn,
+                capture_removal: CaptureRemoval::Immediate,
+                first_player: PieceColor::Black,
+                dark_square_parity: 1,
+                orthogonal: false,
+                orthogonal_captures: false,
+                king_move_limit: None,
+                giveaway: false
+            },
+            // white moves first, which is red here
+            Variant::International => RulesConfig {
+                variant: *self,
+                board_dim: 10,
+                flying_kings: true,
+                men_capture_backwards: true,
+                men_capture_kings: true,
+                capture_rule: CaptureRule::Majority,
+                crowning: Crowning::PassesThrough,
+                capture_removal: CaptureRemoval::AfterSequence,
+                first_player: PieceColor::Red,
+                dark_square_parity: 1,
+                orthogonal: false,
+                orthogonal_captures: false,
+                king_move_limit: None,
+                giveaway: false
+            },
+            Variant::Russian => RulesConfig {
+                variant: *self,
+                board_dim: 8,
+                flying_kings: true,
+                men_capture_backwards: true,
+                men_capture_kings: true,
+                capture_rule: CaptureRule::Free,
+                crowning: Crowning::ContinuesAsKing,
+                capture_removal: CaptureRemoval::AfterSequence,
+                first_player: PieceColor::Red,
+                dark_square_parity: 1,
+                orthogonal: false,
+                orthogonal_captures: false,
+                king_move_limit: None,
+                giveaway: false
+            },
+            // the board is turned so the corner on each player's left is light
+            Variant::Italian => RulesConfig {
+                variant: *self,
+                board_dim: 8,
+                flying_kings: false,
+                men_capture_backwards: false,
+                men_capture_kings: false,
+                capture_rule: CaptureRule::Italian,
+                crowning: Crowning::EndsTurn,
+                capture_removal: CaptureRemoval::AfterSequence,
+                first_player: PieceColor::Red,
+                dark_square_parity: 0,
+                orthogonal: false,
+                orthogonal_captures: false,
+                king_move_limit: None,
+                giveaway: false
+            },
+            // international rules on the small board
+            Variant::Brazilian => RulesConfig {
+                board_dim: 8,
+                ..Variant::International.rules()
+            }.with_variant(*self),
+            Variant::Pool => RulesConfig {
+                variant: *self,
+                board_dim: 8,
+                flying_kings: true,
+                men_capture_backwards: true,
+                men_capture_kings: true,
+                capture_rule: CaptureRule::Free,
+                crowning: Crowning::PassesThrough,
+                capture_removal: CaptureRemoval::AfterSequence,
+                first_player: PieceColor::Black,
+                dark_square_parity: 1,
+                orthogonal: false,
+                orthogonal_captures: false,
+                king_move_limit: None,
+                giveaway: false
+            },
+            // international rules on a 12x12 board with 30 pieces a side
+            Variant::Canadian => RulesConfig {
+                board_dim: 12,
+                ..Variant::International.rules()
+            }.with_variant(*self),
+            // pieces move along rows and columns and use every square
+            Variant::Turkish => RulesConfig {
+                variant: *self,
+                board_dim: 8,
+                flying_kings: true,
+                men_capture_backwards: false,
+                men_capture_kings: true,
+                capture_rule: CaptureRule::Majority,
+                crowning: Crowning::ContinuesAsKing,
+                capture_removal: CaptureRemoval::Immediate,
+                first_player: PieceColor::Red,
+                dark_square_parity: 1,
+                orthogonal: true,
+                orthogonal_captures: false,
+                king_move_limit: None,
+                giveaway: false
+            },
+            // captures go straight along rows and columns as well, a king is worth almost two men
+            Variant::Frisian => RulesConfig {
+                capture_rule: CaptureRule::Frisian,
+                orthogonal_captures: true,
+                king_move_limit: Some(3),
+                ..Variant::International.rules()
+            }.with_variant(*self)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::American => "American",
+            Variant::International => "International",
+            Variant::Russian => "Russian",
+            Variant::Italian => "Italian",
+            Variant::Brazilian => "Brazilian",
+            Variant::Pool => "Pool",
+            Variant::Canadian => "Canadian",
+            Variant::Turkish => "Turkish",
+            Variant::Frisian => "Frisian"
+        }
+    }
+
+    // Game type numbers from the PDN standard
+    pub fn pdn_game_type(&self) -> u32 {
+        match self {
+            Variant::American => 21,
+            Variant::International => 20,
+            Variant::Russian => 25,
+            Variant::Italian => 22,
+            Variant::Brazilian => 26,
+            Variant::Pool => 23,
+            Variant::Canadian => 27,
+            Variant::Turkish => 30,
+            Variant::Frisian => 40
+        }
+    }
+
+    pub fn from_pdn_game_type(game_type: u32) -> Option<Variant> {
+        return Variant::ALL.iter().find(|v| v.pdn_game_type() == game_type).copied();
+    }
+
+    pub fn next(&self) -> Variant {
+        let idx = Variant::ALL.iter().position(|v| v == self).unwrap();
+        return Variant::ALL[(idx + 1) % Variant::ALL.len()];
+    }
+}
+
+
+// Which of the available capture sequences a player may choose from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaptureRule {
+    // any capture sequence, as long as it is played to the end
+    Free,
+    // only the sequences capturing the most pieces
+    Majority,
+    // the most pieces, then capturing with a king, then the most kings, then the earliest king
+    Italian,
+    // the highest value with a king counting a little less than two men, then capturing with a king
+    Frisian
+}
+
+
+// What happens when a man reaches the last row in the middle of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Crowning {
+    // the man is crowned and the turn is over
+    EndsTurn,
+    // the man is crowned and keeps capturing as a king
+    ContinuesAsKing,
+    // the man keeps capturing as a man if it can, and is only crowned if it ends there
+    PassesThrough
+}
+
+
+// When captured pieces leave the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CaptureRemoval {
+    #[default]
+    Immediate,
+    // captured pieces stay until the sequence is over, they can't be jumped twice (Turkish strike)
+    AfterSequence
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RulesConfig {
+    pub variant: Variant,
+    pub board_dim: usize,
+    // kings move and capture any distance along a diagonal
+    pub flying_kings: bool,
+    pub men_capture_backwards: bool,
+    pub men_capture_kings: bool,
+    pub capture_rule: CaptureRule,
+    pub crowning: Crowning,
+    #[serde(default)]
+    pub capture_removal: CaptureRemoval,
+    pub first_player: PieceColor,
+    // dark squares are the ones where row + col has this parity
+    pub dark_square_parity: usize,
+    // pieces move along rows and columns instead of diagonals
+    #[serde(default)]
+    pub orthogonal: bool,
+    // pieces on a diagonal board may also capture along rows and columns
+    #[serde(default)]
+    pub orthogonal_captures: bool,
+    // how many moves in a row the same king may make while its side still has men
+    #[serde(default)]
+    pub king_move_limit: Option<u32>,
+    // the first side to lose all its pieces or get blocked wins
+    #[serde(default)]
+    pub giveaway: bool
+}
+
+
+impl RulesConfig {
+    fn with_variant(self, variant: Variant) -> Self {
+        return RulesConfig { variant, ..self };
+    }
+
+    pub fn name(&self) -> String {
+        if self.giveaway {
+            return format!("{} giveaway", self.variant.name());
+        }
+        return self.variant.name().to_string();
+    }
+
+    // Pieces only ever stand on the dark squares, unless they move orthogonally
+    pub fn is_playable(&self, row: usize, col: usize) -> bool {
+        return self.orthogonal || (row + col) % 2 == self.dark_square_parity;
+    }
+
+    pub fn squares_per_row(&self) -> usize {
+        if self.orthogonal {
+            return self.board_dim;
+        }
+        return self.board_dim / 2;
+    }
+
+    // Rows the men of each side start on, orthogonal boards leave the back rows empty
+    pub fn starting_rows(&self, col: PieceColor) -> Range<usize> {
+        let dim = self.board_dim;
+        match (col, self.orthogonal) {
+            (PieceColor::Red, false) => 0..(dim / 2 - 1),
+            (PieceColor::Black, false) => (dim / 2 + 1)..dim,
+            (PieceColor::Red, true) => 1..3,
+            (PieceColor::Black, true) => (dim - 3)..(dim - 1)
+        }
+    }
+}
+
+
+impl Default for RulesConfig {
+    fn default() -> Self {
+        return Variant::American.rules();
+    }
+}
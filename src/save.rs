@@ -0,0 +1,353 @@
+use bevy::{prelude::*, app::AppExit, window::WindowCloseRequested};
+use serde::{Serialize, Deserialize};
+use std::{fs, io::Write, path::PathBuf};
+use crate::{
+    config::BoardConfig,
+    state::{GameState, CheckersState, PieceColor},
+    logic::{Move, PossibleMoves},
+    record::GameRecord,
+    clock::GameClock,
+    ai::AIStatus,
+    controller::{AIController, ControllerSettings},
+    checkers_events::SaveGameEvent
+};
+
+
+pub struct CheckersSavePlugin;
+
+
+impl Plugin for CheckersSavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(LoadRequest { path: None })
+        .add_system_set(SystemSet::on_update(GameState::Loading).with_system(load_game))
+        .add_system(autosave)
+        .add_system(autosave_on_exit)
+        .add_system(handle_save_event);
+    }
+}
+
+
+// Bump whenever the layout of SavedSession changes
+pub const SAVE_VERSION: u32 = 5;
+const AUTOSAVE_FILE: &str = "autosave.json";
+const SAVE_FILE: &str = "saved_game.json";
+const ARCHIVE_FILE: &str = "games.pdn";
+const STATS_FILE: &str = "stats.json";
+
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedSession {
+    pub version: u32,
+    pub state: CheckersState,
+    pub record: GameRecord,
+    pub ai_enabled: bool,
+    pub ai_contempt: f32,
+    pub ai_color: PieceColor,
+    // the search settings, or the engine or DXP opponent the AI side was played by
+    pub controller: ControllerSettings,
+    pub clock: GameClock,
+    // steps allowed while a multi jump is in progress
+    pub possible_moves: Option<Vec<Move>>
+}
+
+
+// File the menu asked to load, picked up in GameState::Loading
+#[derive(Resource)]
+pub struct LoadRequest {
+    pub path: Option<PathBuf>
+}
+
+
+pub fn save_dir() -> PathBuf {
+    let base = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
+    return base.join("checkers");
+}
+
+
+pub fn autosave_path() -> PathBuf {
+    return save_dir().join(AUTOSAVE_FILE);
+}
+
+
+pub fn save_path() -> PathBuf {
+    return save_dir().join(SAVE_FILE);
+}
+
+
+// Results of every game played, kept next to the autosave
+pub fn stats_path() -> PathBuf {
+    return save_dir().join(STATS_FILE);
+}
+
+
+// Every finished game is appended here in PDN
+pub fn archive_path() -> PathBuf {
+    return save_dir().join(ARCHIVE_FILE);
+}
+
+
+fn archive_game(game_record: &GameRecord, ai_status: &AIStatus, controller: &AIController) -> Result<(), String> {
+    fs::create_dir_all(save_dir()).map_err(|e| e.to_string())?;
+    let ai_name = controller.0.name();
+    let (black_name, red_name) = match (ai_status.enabled, ai_status.color) {
+        (false, _) => ("Human", "Human"),
+        (true, PieceColor::Red) => ("Human", ai_name.as_str()),
+        (true, PieceColor::Black) => (ai_name.as_str(), "Human")
+    };
+    let pdn = game_record.to_pdn_game(black_name, red_name).to_pdn();
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(archive_path()).map_err(|e| e.to_string())?;
+    file.write_all(pdn.as_bytes()).map_err(|e| e.to_string())?;
+    return Ok(());
+}
+
+
+pub fn write_session(session: &SavedSession, path: &PathBuf) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let data = serde_json::to_string(session).map_err(|e| e.to_string())?;
+    // write to a temporary file first so a crash can't leave a half written save
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, data).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())?;
+    return Ok(());
+}
+
+
+pub fn read_session(path: &PathBuf) -> Result<SavedSession, String> {
+    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let session: SavedSession = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    if session.version != SAVE_VERSION {
+        return Err(format!("Unsupported save version {}, expected {}", session.version, SAVE_VERSION));
+    }
+    return Ok(session);
+}
+
+
+// True if there is an unfinished game to continue
+pub fn can_continue() -> bool {
+    match read_session(&autosave_path()) {
+        Ok(session) => session.record.result.is_none(),
+        Err(_) => false
+    }
+}
+
+
+fn snapshot(
+    checkers_state: &CheckersState,
+    game_record: &GameRecord,
+    ai_status: &AIStatus,
+    controller: &AIController,
+    clock: &GameClock,
+    possible_moves: &PossibleMoves
+) -> SavedSession {
+    return SavedSession {
+        version: SAVE_VERSION,
+        state: checkers_state.clone(),
+        record: game_record.clone(),
+        ai_enabled: ai_status.enabled,
+        ai_contempt: ai_status.contempt,
+        ai_color: ai_status.color,
+        controller: controller.0.settings(),
+        clock: clock.clone(),
+        possible_moves: possible_moves.moves.clone()
+    };
+}
+
+
+fn is_in_game(game_state: &State<GameState>) -> bool {
+    match game_state.current() {
+        GameState::Input | GameState::TryMove | GameState::Move | GameState::RestrictedInput | GameState::AIMove
+        | GameState::GameOver | GameState::Animating | GameState::DrawOffer => true,
+        _ => false
+    }
+}
+
+
+// Saves after every move and when the game ends
+fn autosave(
+    game_state: Res<State<GameState>>,
+    checkers_state: Res<CheckersState>,
+    game_record: Res<GameRecord>,
+    ai_status: Res<AIStatus>,
+    clock: Res<GameClock>,
+    possible_moves: Res<PossibleMoves>,
+    controller: Res<AIController>
+){
+    if !is_in_game(&game_state) || !(game_record.is_changed() || possible_moves.is_changed()) {
+        return;
+    }
+    let session = snapshot(&checkers_state, &game_record, &ai_status, &controller, &clock, &possible_moves);
+    if let Err(e) = write_session(&session, &autosave_path()) {
+        info!("Autosave failed: {}", e);
+    }
+    if game_record.is_changed() && game_record.result.is_some() {
+        match archive_game(&game_record, &ai_status, &controller) {
+            Ok(_) => info!("Game archived to {:?}", archive_path()),
+            Err(e) => info!("Archiving game failed: {}", e)
+        }
+    }
+}
+
+
+// Keeps the clocks up to date when the window is closed
+fn autosave_on_exit(
+    keys: Res<Input<KeyCode>>,
+    mut close_events: EventReader<WindowCloseRequested>,
+    mut exit_events: EventReader<AppExit>,
+    game_state: Res<State<GameState>>,
+    checkers_state: Res<CheckersState>,
+    game_record: Res<GameRecord>,
+    ai_status: Res<AIStatus>,
+    clock: Res<GameClock>,
+    possible_moves: Res<PossibleMoves>,
+    controller: Res<AIController>
+){
+    let closing = keys.just_pressed(KeyCode::Escape) || close_events.iter().count() > 0 || exit_events.iter().count() > 0;
+    if !closing || !is_in_game(&game_state) {
+        return;
+    }
+    let session = snapshot(&checkers_state, &game_record, &ai_status, &controller, &clock, &possible_moves);
+    match write_session(&session, &autosave_path()) {
+        Ok(_) => info!("Autosaved on exit"),
+        Err(e) => info!("Autosave failed: {}", e)
+    }
+}
+
+
+fn handle_save_event(
+    mut events: EventReader<SaveGameEvent>,
+    checkers_state: Res<CheckersState>,
+    game_record: Res<GameRecord>,
+    ai_status: Res<AIStatus>,
+    clock: Res<GameClock>,
+    possible_moves: Res<PossibleMoves>,
+    controller: Res<AIController>
+){
+    for _ in events.iter() {
+        let session = snapshot(&checkers_state, &game_record, &ai_status, &controller, &clock, &possible_moves);
+        match write_session(&session, &save_path()) {
+            Ok(_) => info!("Game saved to {:?}", save_path()),
+            Err(e) => info!("Saving failed: {}", e)
+        }
+    }
+}
+
+
+fn load_game(
+    mut load_request: ResMut<LoadRequest>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut game_record: ResMut<GameRecord>,
+    mut ai_status: ResMut<AIStatus>,
+    mut clock: ResMut<GameClock>,
+    mut possible_moves: ResMut<PossibleMoves>,
+    mut board_config: ResMut<BoardConfig>,
+    mut controller: ResMut<AIController>,
+    mut game_state: ResMut<State<GameState>>
+){
+    if let Some(path) = load_request.path.take() {
+        match read_session(&path) {
+            Ok(session) => {
+                info!("Loaded game from {:?}", path);
+                board_config.board_dim = session.state.rules.board_dim;
+                *checkers_state = session.state;
+                *game_record = session.record;
+                ai_status.enabled = session.ai_enabled;
+                ai_status.contempt = session.ai_contempt;
+                ai_status.color = session.ai_color;
+                // the engine or connection is only started again when the game was played against another one
+                if controller.0.settings() != session.controller {
+                    match AIController::from_settings(&session.controller) {
+                        Ok(restored) => *controller = restored,
+                        Err(e) => info!("Could not restore {:?}, playing on with {}: {}", session.controller, controller.0.name(), e)
+                    }
+                }
+                *clock = session.clock;
+                possible_moves.moves = session.possible_moves;
+                game_state.set(GameState::BoardSetup).unwrap();
+            },
+            Err(e) => {
+                info!("Could not load {:?}: {}", path, e);
+                game_state.set(GameState::Menu).unwrap();
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::{rules::Variant, logic::Position, state::{CheckersPiece, PieceType}, notation::to_fen, controller::SearchSettings, clock::TimeControl};
+
+    fn session_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("checkers_{}_{}.json", name, std::process::id()));
+    }
+
+    #[test]
+    fn session_survives_saving_mid_jump() {
+        // Red takes two men, the game is saved after the first jump
+        let mut state = CheckersState::new(Variant::American.rules());
+        for square in state.board.iter_mut().flatten() {
+            *square = None;
+        }
+        state.turn = PieceColor::Red;
+        state.board[2][3] = Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::Man });
+        state.board[3][4] = Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man });
+        state.board[5][6] = Some(CheckersPiece { col: PieceColor::Black, typ: PieceType::Man });
+        let first = Move { from: Position::new(2, 3), to: Position::new(4, 5) };
+        let (_, _, next_moves) = state.update_with_move(&first);
+        assert_eq!(state.turn, PieceColor::Red);
+
+        let mut record = GameRecord::default();
+        record.push_move(PieceColor::Red, first, false);
+        let settings = ControllerSettings::BuiltIn(SearchSettings { mcts: true, use_book: false });
+        let session = SavedSession {
+            version: SAVE_VERSION,
+            state: state.clone(),
+            record,
+            ai_enabled: true,
+            ai_contempt: 25.,
+            ai_color: PieceColor::Black,
+            controller: settings.clone(),
+            clock: GameClock::new(TimeControl::Fischer { base: Duration::from_secs(60), increment: Duration::from_secs(1) }),
+            possible_moves: Some(next_moves.clone())
+        };
+        let path = session_path("mid_jump");
+        write_session(&session, &path).unwrap();
+        let loaded = read_session(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(to_fen(&loaded.state), to_fen(&state));
+        assert_eq!(loaded.state.turn, PieceColor::Red);
+        assert_eq!(loaded.possible_moves, Some(next_moves));
+        assert_eq!(loaded.possible_moves.unwrap(), vec![Move { from: Position::new(4, 5), to: Position::new(6, 7) }]);
+        assert_eq!(loaded.record.turns.len(), 1);
+        assert!(!loaded.record.turns[0].complete);
+        assert_eq!(loaded.controller, settings);
+        assert_eq!((loaded.ai_contempt, loaded.ai_color), (25., PieceColor::Black));
+        assert_eq!(loaded.clock.control, session.clock.control);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = session_path("old_version");
+        let session = SavedSession {
+            version: SAVE_VERSION - 1,
+            state: CheckersState::new(Variant::American.rules()),
+            record: GameRecord::default(),
+            ai_enabled: false,
+            ai_contempt: 0.,
+            ai_color: PieceColor::Red,
+            controller: ControllerSettings::DxpOpponent { address: String::from("localhost:27531") },
+            clock: GameClock::new(TimeControl::Unlimited),
+            possible_moves: None
+        };
+        write_session(&session, &path).unwrap();
+        let result = read_session(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(e) if e.contains("Unsupported save version")));
+    }
+}
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use crate::{checkers_events::*, ai::AIStatus, state::PieceColor};
+use crate::{checkers_events::*, ai::AIStatus};
 
 
 pub struct CheckersSoundPlugin;
@@ -35,7 +35,7 @@ fn handle_capture(audio: Res<Audio>, asset_server: Res<AssetServer>, mut events:
 
 fn handle_move(audio: Res<Audio>, asset_server: Res<AssetServer>, mut events: EventReader<PieceMoveEvent>){
     for ev in events.iter(){
-        if !ev.game_move.is_jump(){
+        if !ev.is_capture {
             let sound = asset_server.load("sounds/move.mp3");
             audio.play_with_settings(sound, PlaybackSettings { volume: 0.1, ..default() });
         }
@@ -57,10 +57,13 @@ fn handle_invalid_input(audio: Res<Audio>, asset_server: Res<AssetServer>, mut e
 }
 
 
-fn handle_victory(audio: Res<Audio>, asset_server: Res<AssetServer>, mut events: EventReader<VictoryEvent>, ai_status: Res<AIStatus>){
+fn handle_victory(audio: Res<Audio>, asset_server: Res<AssetServer>, mut events: EventReader<GameOverEvent>, ai_status: Res<AIStatus>){
     for ev in events.iter(){
+        if ev.winner.is_none() {
+            continue;
+        }
         let mut sound = asset_server.load("sounds/celebration.mp3");
-        if ai_status.enabled && ev.winner == PieceColor::Red {
+        if ai_status.enabled && ev.winner == Some(ai_status.color) {
             sound = asset_server.load("sounds/loss.mp3");
         }
         audio.play_with_settings(sound, PlaybackSettings { volume: 0.5, ..default() });
@@ -0,0 +1,199 @@
+use bevy::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{
+    state::{GameState, CheckersState, CheckersPiece, PieceColor, PieceType},
+    logic::Position,
+    record::GameRecord,
+    notation::square_number,
+    ballot::{Ballot, play_opening}
+};
+
+
+pub struct CheckersStartPlugin;
+
+
+impl Plugin for CheckersStartPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .insert_resource(StartSettings { eleven_man: false, handicap: None, pending: false, removed: None })
+        .add_system_set(SystemSet::on_enter(GameState::BoardSetup).with_system(start_new_game));
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandicapKind {
+    // the stronger side starts a man short
+    Man,
+    // the weaker side starts with one of its men crowned
+    King,
+    // the stronger side skips its first turn
+    Move
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Handicap {
+    pub kind: HandicapKind,
+    // the stronger side, who gives the handicap
+    pub giver: PieceColor
+}
+
+
+impl Handicap {
+    const ALL: [Handicap; 6] = [
+        Handicap { kind: HandicapKind::Man, giver: PieceColor::Black },
+        Handicap { kind: HandicapKind::King, giver: PieceColor::Black },
+        Handicap { kind: HandicapKind::Move, giver: PieceColor::Black },
+        Handicap { kind: HandicapKind::Man, giver: PieceColor::Red },
+        Handicap { kind: HandicapKind::King, giver: PieceColor::Red },
+        Handicap { kind: HandicapKind::Move, giver: PieceColor::Red }
+    ];
+
+    // Cycles through all handicaps, None is an even game
+    pub fn next(handicap: Option<Handicap>) -> Option<Handicap> {
+        match handicap {
+            None => return Some(Handicap::ALL[0]),
+            Some(h) => {
+                let idx = Handicap::ALL.iter().position(|a| *a == h).unwrap();
+                return Handicap::ALL.get(idx + 1).copied();
+            }
+        }
+    }
+
+    pub fn label(handicap: Option<Handicap>) -> String {
+        let h = match handicap {
+            Some(h) => h,
+            None => return String::from("Handicap: None")
+        };
+        let given = match h.kind {
+            HandicapKind::Man => "a man",
+            HandicapKind::King => "a king",
+            HandicapKind::Move => "a move"
+        };
+        return format!("Handicap: {:?} gives {}", h.giver, given);
+    }
+
+    // Value of the PDN Handicap tag, e.g. "B man" when Black gives a man
+    pub fn to_tag(&self) -> String {
+        let side = match self.giver {
+            PieceColor::Black => "B",
+            PieceColor::Red => "W"
+        };
+        let kind = match self.kind {
+            HandicapKind::Man => "man",
+            HandicapKind::King => "king",
+            HandicapKind::Move => "move"
+        };
+        return format!("{} {}", side, kind);
+    }
+
+    pub fn from_tag(tag: &str) -> Option<Handicap> {
+        return Handicap::ALL.iter().find(|h| h.to_tag() == tag.trim()).copied();
+    }
+
+    pub fn apply(&self, state: &mut CheckersState) {
+        match self.kind {
+            HandicapKind::Man => {
+                if let Some(pos) = back_man(state, self.giver) {
+                    state.board[pos.row][pos.col] = None;
+                }
+            },
+            HandicapKind::King => {
+                if let Some(pos) = back_man(state, self.giver.opponent()) {
+                    state.board[pos.row][pos.col] = Some(CheckersPiece { col: self.giver.opponent(), typ: PieceType::King });
+                }
+            },
+            HandicapKind::Move => {
+                if state.turn == self.giver {
+                    state.turn = self.giver.opponent();
+                } else {
+                    state.passes = Some(self.giver);
+                }
+            }
+        }
+    }
+}
+
+
+// New game options the menu sets up, applied when the board is set up
+#[derive(Resource)]
+pub struct StartSettings {
+    pub eleven_man: bool,
+    pub handicap: Option<Handicap>,
+    // set when a new game starts, loaded games keep their own position
+    pub pending: bool,
+    // men taken off by the 11 man ballot, kept for the second game of a pair
+    pub removed: Option<Vec<Position>>
+}
+
+
+// The man nearest to the corner of the side's back row, Black's square 1 or Red's last square
+fn back_man(state: &CheckersState, col: PieceColor) -> Option<Position> {
+    let mut men = Vec::new();
+    for row in 0..state.board.len() {
+        for c in 0..state.board.len() {
+            if matches!(state.board[row][c], Some(p) if p.col == col && p.typ == PieceType::Man) {
+                men.push(Position::new(row, c));
+            }
+        }
+    }
+    men.sort_by_key(|p| square_number(p, &state.rules));
+    return match col {
+        PieceColor::Black => men.first().copied(),
+        PieceColor::Red => men.last().copied()
+    };
+}
+
+
+// One man of each side drawn at random
+fn eleven_man_ballot(state: &CheckersState) -> Vec<Position> {
+    let mut seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos() as usize).unwrap_or(0);
+    let mut removed = Vec::new();
+    for col in [PieceColor::Black, PieceColor::Red] {
+        let mut men = Vec::new();
+        for row in 0..state.board.len() {
+            for c in 0..state.board.len() {
+                if matches!(state.board[row][c], Some(p) if p.col == col) {
+                    men.push(Position::new(row, c));
+                }
+            }
+        }
+        if men.len() > 0 {
+            removed.push(men[seed % men.len()]);
+        }
+        seed /= men.len().max(1);
+    }
+    return removed;
+}
+
+
+fn start_new_game(
+    mut settings: ResMut<StartSettings>,
+    mut ballot: ResMut<Ballot>,
+    mut checkers_state: ResMut<CheckersState>,
+    mut game_record: ResMut<GameRecord>
+){
+    if !settings.pending {
+        return;
+    }
+    settings.pending = false;
+    if settings.eleven_man && settings.removed.is_none() {
+        settings.removed = Some(eleven_man_ballot(&checkers_state));
+    }
+    if let Some(ref removed) = settings.removed {
+        for pos in removed.iter() {
+            checkers_state.board[pos.row][pos.col] = None;
+        }
+        let squares: Vec<String> = removed.iter().map(|p| square_number(p, &checkers_state.rules).to_string()).collect();
+        info!("11 man ballot removed {}", squares.join(" "));
+        game_record.tags.push((String::from("ElevenManBallot"), squares.join(" ")));
+    }
+    if let Some(handicap) = settings.handicap {
+        handicap.apply(&mut checkers_state);
+        info!("{}", Handicap::label(Some(handicap)));
+        game_record.tags.push((String::from("Handicap"), handicap.to_tag()));
+    }
+    game_record.start = Some(checkers_state.clone());
+    play_opening(&mut ballot, &mut checkers_state, &mut game_record);
+}
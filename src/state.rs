@@ -1,5 +1,7 @@
 use bevy::prelude::*;
-use crate::logic::{Move, Position};
+use serde::{Serialize, Deserialize};
+use std::{cmp::Reverse, sync::Arc};
+use crate::{logic::{Move, Position}, rules::{RulesConfig, CaptureRule, Crowning, CaptureRemoval}, ai::{Evaluator, default_evaluator}};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum GameState {
@@ -11,44 +13,109 @@ pub enum GameState {
     RestrictedInput,
     AIMove,
     GameOver,
-    Animating
+    Animating,
+    DrawOffer,
+    Loading,
+    ReplaySetup,
+    Replay,
+    ReplayAnimating,
+    EditorSetup,
+    Editor
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum PieceType {
     Man,
     King
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum PieceColor {
     Black,
     Red
 }
 
-#[derive(Debug, Clone, Copy)]
+impl PieceColor {
+    pub fn index(&self) -> usize {
+        match self {
+            PieceColor::Black => 0,
+            PieceColor::Red => 1
+        }
+    }
+
+    pub fn opponent(&self) -> PieceColor {
+        match self {
+            PieceColor::Black => PieceColor::Red,
+            PieceColor::Red => PieceColor::Black
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct CheckersPiece {
     pub col: PieceColor,
     pub typ: PieceType
 }
 
-#[derive(Resource, Clone)]
+// How a capture sequence ranks under the capture rules, fields are compared in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CaptureValue {
+    pub pieces: usize,
+    pub by_king: bool,
+    pub kings: usize,
+    // the earlier a king is captured the better, so the index is reversed
+    pub first_king: Reverse<usize>
+}
+
+// Consecutive non capturing moves made by one king
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct KingStreak {
+    pub pos: Option<Position>,
+    pub moves: u32
+}
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct CheckersState {
     pub turn: PieceColor,
-    pub board: Vec<Vec<Option<CheckersPiece>>>
+    pub board: Vec<Vec<Option<CheckersPiece>>>,
+    pub rules: RulesConfig,
+    // pieces captured so far in a sequence that are still on the board
+    #[serde(default)]
+    pub pending_captures: Vec<Position>,
+    // direction of the last capture in a sequence in progress
+    #[serde(default)]
+    pub capture_direction: Option<(i32, i32)>,
+    // for each side, indexed by PieceColor::index
+    #[serde(default)]
+    pub king_streaks: [KingStreak; 2],
+    // side that skips its next turn, used to give a move as a handicap
+    #[serde(default)]
+    pub passes: Option<PieceColor>,
+    // evaluation the search uses from this position on, engines in a tournament can differ
+    #[serde(skip, default = "default_evaluator")]
+    pub evaluator: Arc<dyn Evaluator>
+}
+
+
+// Frisian capture value, kings count two with ties going to the capture with more men,
+// so a king is worth more than one man but less than two
+fn frisian_key(value: &CaptureValue) -> (usize, usize, bool) {
+    let men = value.pieces - value.kings;
+    return (men + 2 * value.kings, men, value.by_king);
 }
 
 
 impl CheckersState {
-    pub fn new(dim: usize) -> Self {
+    pub fn new(rules: RulesConfig) -> Self {
+        let dim = rules.board_dim;
         let mut board = Vec::new();
         for row in 0..dim {
             let mut board_row = Vec::<Option<CheckersPiece>>::new();
             for col in 0..dim {
-                if (row + (col % 2)) % 2 == 1 {
-                    if (row as f32) < dim as f32 / 2.0 - 1. {
+                if rules.is_playable(row, col) {
+                    if rules.starting_rows(PieceColor::Red).contains(&row) {
                         board_row.push(Some(CheckersPiece {col: PieceColor::Red, typ: PieceType::Man}));
-                    } else if (row as f32) > dim as f32 / 2.0 {
+                    } else if rules.starting_rows(PieceColor::Black).contains(&row) {
                         board_row.push(Some(CheckersPiece {col: PieceColor::Black, typ: PieceType::Man}));
                     } else {
                         board_row.push(None);
@@ -60,7 +127,7 @@ impl CheckersState {
             }
             board.push(board_row);
         }
-        return CheckersState { turn: PieceColor::Black, board };
+        return CheckersState { turn: rules.first_player, board, rules, pending_captures: Vec::new(), capture_direction: None, king_streaks: [KingStreak::default(); 2], passes: None, evaluator: default_evaluator() };
     }
 
     fn at(&self, pos: &Position) -> Option<CheckersPiece> {
@@ -81,112 +148,220 @@ impl CheckersState {
     }
 
 
-    fn candidate_moves(&self, pos: &Position, distance: u32, turn:PieceColor) -> Vec<Move>{
-        let mut moves = Vec::<Move>::new();
-        let piece = self.at(pos);
+    fn forward(&self, col: PieceColor) -> i32 {
+        match col {
+            PieceColor::Red => 1,
+            PieceColor::Black => -1
+        }
+    }
 
-        // check if valid piece and piece is owned by the player whose turn it is
-        if let Some(piece) = piece {
-            if piece.col != turn {
-                return moves;
+    // Directions a piece may step or capture in, as (row, col) deltas
+    fn directions(&self, piece: CheckersPiece, capture: bool) -> Vec<(i32, i32)> {
+        let forward = self.forward(piece.col);
+        if self.rules.orthogonal {
+            // men go forwards or sideways, never back
+            let mut directions = vec![(forward, 0), (0, -1), (0, 1)];
+            if piece.typ == PieceType::King || (capture && self.rules.men_capture_backwards) {
+                directions.push((-forward, 0));
             }
-
-            let row_delta_iter = match piece {
-                CheckersPiece{col: _, typ: PieceType::King} => {
-                    (-1..2).step_by(2)
-                },
-                CheckersPiece{col: PieceColor::Red, typ: PieceType::Man} => {
-                    (1..2).step_by(2)
-                },
-                CheckersPiece{col: PieceColor::Black, typ: PieceType::Man} => {
-                    (-1..0).step_by(2)
-                }
-            };
-
-            for row_delta in row_delta_iter{
-                for col_delta in (-1..2).step_by(2){
-                    let row = pos.row as i32 + distance as i32 * row_delta;
-                    let col = pos.col as i32 + distance as i32 * col_delta;
-                    if self.is_valid_dim(row) && self.is_valid_dim(col){
-                        moves.push(Move{ from: *pos, to: Position::new(row as usize, col as usize)});
-                    }
-                }
+            return directions;
+        }
+        let rows = match piece.typ {
+            PieceType::King => vec![-1, 1],
+            PieceType::Man if capture && self.rules.men_capture_backwards => vec![-1, 1],
+            PieceType::Man => vec![forward]
+        };
+        let mut directions = Vec::new();
+        for row_delta in rows.iter() {
+            for col_delta in [-1, 1] {
+                directions.push((*row_delta, col_delta));
             }
-            return moves;
         }
-
-        return moves;
+        // straight captures jump to the next dark square along the row or column
+        if capture && self.rules.orthogonal_captures {
+            directions.push((0, -2));
+            directions.push((0, 2));
+            for row_delta in rows {
+                directions.push((row_delta * 2, 0));
+            }
+        }
+        return directions;
     }
 
-    fn candidate_jumps(&self, pos: &Position, turn: PieceColor) -> Vec<Move> {
-        return self.candidate_moves(pos, 2, turn);
+    fn offset(&self, pos: &Position, delta: (i32, i32), distance: i32) -> Option<Position> {
+        let row = pos.row as i32 + delta.0 * distance;
+        let col = pos.col as i32 + delta.1 * distance;
+        if self.is_valid_dim(row) && self.is_valid_dim(col) {
+            return Some(Position::new(row as usize, col as usize));
+        }
+        return None;
     }
-    
-    fn candidate_steps(&self, pos: &Position, turn: PieceColor) -> Vec<Move>{
-        return self.candidate_moves(pos, 1, turn);
+
+    fn is_flying(&self, piece: CheckersPiece) -> bool {
+        return piece.typ == PieceType::King && self.rules.flying_kings;
     }
 
     pub fn valid_jumps(&self, pos: &Position, turn: PieceColor) -> Vec<Move> {
-        return self.candidate_jumps(pos, turn)
-                    .into_iter()
-                    .filter(|m: &Move| -> bool {
-                        if !self.is_empty(&m.to){
-                            return false;
-                        }
-                        let middle_pos: Position = m.middle_pos().unwrap();
-                        if let Some(piece) = self.at(&middle_pos){
-                            if piece.col != turn {
-                                return true;
-                            }
-                        }
-                        false
-                    })
-                    .collect()
+        let mut moves = Vec::<Move>::new();
+        let piece = match self.at(pos) {
+            Some(piece) if piece.col == turn => piece,
+            _ => return moves
+        };
+        let max_distance = if self.is_flying(piece) { self.board.len() as i32 } else { 1 };
+
+        for delta in self.directions(piece, true) {
+            // orthogonal captures may not turn straight back
+            if self.rules.orthogonal && self.capture_direction == Some((-delta.0, -delta.1)) {
+                continue;
+            }
+            // find the first piece along the direction, flying kings may start from afar
+            let mut distance = 1;
+            let mut target = None;
+            while let Some(p) = self.offset(pos, delta, distance) {
+                if !self.is_empty(&p) {
+                    target = Some(p);
+                    break;
+                }
+                if distance >= max_distance {
+                    break;
+                }
+                distance += 1;
+            }
+            // a piece already captured in this sequence blocks the way
+            let target = match target {
+                Some(p) if self.at(&p).unwrap().col != turn && !self.pending_captures.contains(&p) => p,
+                _ => continue
+            };
+            if piece.typ == PieceType::Man && self.at(&target).unwrap().typ == PieceType::King && !self.rules.men_capture_kings {
+                continue;
+            }
+            // then every empty square behind it is a landing square
+            let mut landing_distance = 1;
+            while let Some(p) = self.offset(&target, delta, landing_distance) {
+                if !self.is_empty(&p) {
+                    break;
+                }
+                moves.push(Move { from: *pos, to: p });
+                if landing_distance >= max_distance {
+                    break;
+                }
+                landing_distance += 1;
+            }
+        }
+        return moves;
     }
 
     pub fn valid_steps(&self, pos: &Position, turn: PieceColor) -> Vec<Move> {
-        return self.candidate_steps(pos, turn)
-                        .into_iter()
-                        .filter(|m| self.is_empty(&m.to))
-                        .collect()
+        let mut moves = Vec::<Move>::new();
+        let piece = match self.at(pos) {
+            Some(piece) if piece.col == turn => piece,
+            _ => return moves
+        };
+        let max_distance = if self.is_flying(piece) { self.board.len() as i32 } else { 1 };
+
+        for delta in self.directions(piece, false) {
+            let mut distance = 1;
+            while let Some(p) = self.offset(pos, delta, distance) {
+                if !self.is_empty(&p) {
+                    break;
+                }
+                moves.push(Move { from: *pos, to: p });
+                if distance >= max_distance {
+                    break;
+                }
+                distance += 1;
+            }
+        }
+        return moves;
+    }
+
+    // The opponent piece jumped by a move, found by walking from the start towards the landing square
+    pub fn captured_by(&self, m: &Move) -> Option<Position> {
+        let row_delta = m.to.row as i32 - m.from.row as i32;
+        let col_delta = m.to.col as i32 - m.from.col as i32;
+        let is_line = row_delta == 0 || col_delta == 0 || row_delta.abs() == col_delta.abs();
+        if !is_line || m.from == m.to {
+            return None;
+        }
+        let delta = (row_delta.signum(), col_delta.signum());
+        let mover = self.at(&m.from)?;
+        for distance in 1..row_delta.abs().max(col_delta.abs()) {
+            let p = self.offset(&m.from, delta, distance).unwrap();
+            if let Some(piece) = self.at(&p) {
+                if piece.col != mover.col && !self.pending_captures.contains(&p) {
+                    return Some(p);
+                }
+                return None;
+            }
+        }
+        return None;
     }
 
     pub fn update_with_move(&mut self, m: &Move) -> (Option<Position>, bool, Vec<Move>){
-        let mut capture_pos: Option<Position> = None;
-        let mut is_capture: bool = false;
         let mut is_upgrade: bool = false;
         let mut next_capture_moves = Vec::<Move>::new();
+        let capture_pos = self.captured_by(m);
+        let king_moved = self.at(&m.from).map(|p| p.typ == PieceType::King).unwrap_or(false);
+        let streak = &mut self.king_streaks[self.turn.index()];
+        if capture_pos.is_none() && king_moved {
+            streak.moves = if streak.pos == Some(m.from) { streak.moves + 1 } else { 1 };
+            streak.pos = Some(m.to);
+        } else {
+            *streak = KingStreak::default();
+        }
 
         // update board
         self.board[m.to.row][m.to.col] = self.board[m.from.row][m.from.col];
         self.board[m.from.row][m.from.col] = None;
 
         // if capture
-        if let Some(middle_pos) = m.middle_pos() {
-            self.board[middle_pos.row][middle_pos.col] = None;
-            capture_pos = Some(middle_pos);
-            is_capture = true;
+        if let Some(capture_pos) = capture_pos {
+            let row_delta = m.to.row as i32 - m.from.row as i32;
+            let col_delta = m.to.col as i32 - m.from.col as i32;
+            self.capture_direction = Some((row_delta.signum(), col_delta.signum()));
+            match self.rules.capture_removal {
+                CaptureRemoval::Immediate => self.board[capture_pos.row][capture_pos.col] = None,
+                CaptureRemoval::AfterSequence => self.pending_captures.push(capture_pos)
+            }
         }
 
         // if upgraded, piece already moved
         if self.final_row(m.to.row) && self.at(&m.to).unwrap().typ == PieceType::Man {
-            self.board[m.to.row][m.to.col] = Some(CheckersPiece {
-                col: self.turn,
-                typ: PieceType::King
-            });
-            is_upgrade = true;
+            // a man only passing through the last row mid capture stays a man
+            let passes_through = self.rules.crowning == Crowning::PassesThrough
+                && capture_pos.is_some()
+                && self.valid_jumps(&m.to, self.turn).len() > 0;
+            if !passes_through {
+                self.board[m.to.row][m.to.col] = Some(CheckersPiece {
+                    col: self.turn,
+                    typ: PieceType::King
+                });
+                is_upgrade = true;
+            }
         }
 
         // switch turn
-        if is_capture && !is_upgrade{
+        let continues = match self.rules.crowning {
+            Crowning::EndsTurn | Crowning::PassesThrough => !is_upgrade,
+            Crowning::ContinuesAsKing => true
+        };
+        if capture_pos.is_some() && continues {
             next_capture_moves.append(&mut self.valid_jumps(&m.to, self.turn));
         }
 
         if next_capture_moves.len() == 0 {
+            for pos in self.pending_captures.drain(..) {
+                self.board[pos.row][pos.col] = None;
+            }
+            self.capture_direction = None;
             self.turn = match self.turn {
                 PieceColor::Red => PieceColor::Black,
                 PieceColor::Black => PieceColor::Red
             };
+            if self.passes == Some(self.turn) {
+                self.passes = None;
+                self.turn = self.turn.opponent();
+            }
 
         }
         return (capture_pos, is_upgrade, next_capture_moves);
@@ -206,6 +381,173 @@ impl CheckersState {
         return possible_captures;
     }
 
+    // Every way of playing out the captures starting with first_moves, one sequence per path
+    fn capture_sequences(&self, first_moves: &Vec<Move>) -> Vec<Vec<Move>> {
+        let mut sequences = Vec::new();
+        for m in first_moves.iter() {
+            let mut next_state = self.clone();
+            let (_, _, next_jumps) = next_state.update_with_move(m);
+            if next_jumps.len() == 0 {
+                sequences.push(vec![*m]);
+                continue;
+            }
+            for mut rest in next_state.capture_sequences(&next_jumps) {
+                rest.insert(0, *m);
+                sequences.push(rest);
+            }
+        }
+        return sequences;
+    }
+
+    // Pieces captured earlier in the sequence are still on the board while it is in progress,
+    // so they count towards the value as well
+    pub fn capture_value(&self, sequence: &Vec<Move>) -> CaptureValue {
+        let mut captured: Vec<CheckersPiece> = self.pending_captures.iter().map(|p| self.at(p).unwrap()).collect();
+        let by_king = self.at(&sequence[0].from).map(|p| p.typ == PieceType::King).unwrap_or(false);
+        let mut state = self.clone();
+        for m in sequence.iter() {
+            if let Some(pos) = state.captured_by(m) {
+                captured.push(state.at(&pos).unwrap());
+            }
+            state.update_with_move(m);
+        }
+        let first_king = captured.iter().position(|p| p.typ == PieceType::King).unwrap_or(usize::MAX);
+        return CaptureValue {
+            pieces: captured.len(),
+            by_king,
+            kings: captured.iter().filter(|p| p.typ == PieceType::King).count(),
+            first_king: Reverse(first_king)
+        };
+    }
+
+    fn apply_capture_rule(&self, sequences: Vec<Vec<Move>>) -> Vec<Vec<Move>> {
+        match self.rules.capture_rule {
+            CaptureRule::Free => sequences,
+            CaptureRule::Majority => {
+                let most = sequences.iter().map(|s| s.len()).max().unwrap_or(0);
+                sequences.into_iter().filter(|s| s.len() == most).collect()
+            },
+            CaptureRule::Italian => {
+                let values: Vec<CaptureValue> = sequences.iter().map(|s| self.capture_value(s)).collect();
+                let best = values.iter().max().copied();
+                sequences.into_iter().zip(values).filter(|(_, v)| Some(*v) == best).map(|(s, _)| s).collect()
+            },
+            CaptureRule::Frisian => {
+                let keys: Vec<(usize, usize, bool)> = sequences.iter().map(|s| frisian_key(&self.capture_value(s))).collect();
+                let best = keys.iter().max().copied();
+                sequences.into_iter().zip(keys).filter(|(_, k)| Some(*k) == best).map(|(s, _)| s).collect()
+            }
+        }
+    }
+
+    // Explains which rule a move breaks, None if it doesn't break any
+    pub fn rule_violation(&self, m: &Move, capturing: Option<Position>) -> Option<String> {
+        // the tired king may still move when nothing else can, as in legal_sequences
+        let others_can_step = || self.steps().iter().any(|s| s[0].from != m.from);
+        if capturing.is_none() && self.tired_king() == Some(m.from) && self.valid_steps(&m.from, self.turn).contains(m) && others_can_step() {
+            return Some(format!("may not move the same king more than {} times in a row", self.rules.king_move_limit.unwrap()));
+        }
+        let first_moves = match capturing {
+            Some(pos) => self.valid_jumps(&pos, self.turn),
+            None => self.possible_captures()
+        };
+        if !first_moves.contains(m) {
+            return None;
+        }
+        let sequences = self.capture_sequences(&first_moves);
+        let best = sequences.iter().map(|s| self.capture_value(s)).max()?;
+        let chosen = sequences.iter().filter(|s| s[0] == *m).map(|s| self.capture_value(s)).max()?;
+        let pieces_rule = format!("must capture the most pieces, {} rather than {}", best.pieces, chosen.pieces);
+        match self.rules.capture_rule {
+            CaptureRule::Free => return None,
+            CaptureRule::Majority => {
+                if chosen.pieces < best.pieces {
+                    return Some(pieces_rule);
+                }
+            },
+            CaptureRule::Italian => {
+                if chosen.pieces < best.pieces {
+                    return Some(pieces_rule);
+                }
+                if chosen.by_king < best.by_king {
+                    return Some(String::from("must capture with a king when a king can take as many pieces"));
+                }
+                if chosen.kings < best.kings {
+                    return Some(format!("must capture the most kings, {} rather than {}", best.kings, chosen.kings));
+                }
+                if chosen.first_king < best.first_king {
+                    return Some(String::from("must capture a king as early in the sequence as possible"));
+                }
+            },
+            CaptureRule::Frisian => {
+                let (best_value, best_men, best_by_king) = frisian_key(&best);
+                let (chosen_value, chosen_men, chosen_by_king) = frisian_key(&chosen);
+                if (chosen_value, chosen_men) < (best_value, best_men) {
+                    return Some(String::from("must capture the most valuable pieces, a king counts almost as two men"));
+                }
+                if chosen_by_king < best_by_king {
+                    return Some(String::from("must capture with a king when a king can take as much"));
+                }
+            }
+        }
+        return None;
+    }
+
+    // King that has used up its consecutive moves, it has to wait while its side still has men
+    fn tired_king(&self) -> Option<Position> {
+        let limit = self.rules.king_move_limit?;
+        let streak = self.king_streaks[self.turn.index()];
+        let has_men = self.board.iter().flatten().any(|sq| matches!(sq, Some(p) if p.col == self.turn && p.typ == PieceType::Man));
+        if streak.moves >= limit && has_men {
+            return streak.pos;
+        }
+        return None;
+    }
+
+    // Every move without a capture for the player to move
+    fn steps(&self) -> Vec<Vec<Move>> {
+        let mut sequences = Vec::new();
+        for row in 0..self.board.len(){
+            for col in 0..self.board.len(){
+                for m in self.valid_steps(&Position::new(row, col), self.turn) {
+                    sequences.push(vec![m]);
+                }
+            }
+        }
+        return sequences;
+    }
+
+    // Complete turns the player to move may choose from
+    pub fn legal_sequences(&self) -> Vec<Vec<Move>> {
+        let captures = self.capture_sequences(&self.possible_captures());
+        if captures.len() > 0 {
+            return self.apply_capture_rule(captures);
+        }
+        let sequences = self.steps();
+        if let Some(tired) = self.tired_king() {
+            let rested: Vec<Vec<Move>> = sequences.iter().filter(|s| s[0].from != tired).cloned().collect();
+            // the king may still move if nothing else can
+            if rested.len() > 0 {
+                return rested;
+            }
+        }
+        return sequences;
+    }
+
+    // Moves allowed next, when in the middle of a capture only the capturing piece may go on
+    pub fn legal_moves(&self, capturing: Option<Position>) -> Vec<Move> {
+        let sequences = match capturing {
+            Some(pos) => self.apply_capture_rule(self.capture_sequences(&self.valid_jumps(&pos, self.turn))),
+            None => self.legal_sequences()
+        };
+        let mut moves = Vec::<Move>::new();
+        for sequence in sequences.iter() {
+            if !moves.contains(&sequence[0]) {
+                moves.push(sequence[0]);
+            }
+        }
+        return moves;
+    }
 
     fn final_row(&self, row: usize) -> bool{
         match self.turn {
@@ -235,6 +577,14 @@ impl CheckersState {
     }
 
     pub fn is_loser(&self, player: PieceColor) -> bool {
+        if self.rules.giveaway {
+            return self.is_out_of_play(player.opponent());
+        }
+        return self.is_out_of_play(player);
+    }
+
+    // No pieces left or none of them can move
+    pub fn is_out_of_play(&self, player: PieceColor) -> bool {
         let mut player_pieces = 0;
 
         for row in 0..self.board.len(){
@@ -264,7 +614,264 @@ impl CheckersState {
         return None;
     }
 
+    // Most pieces a side may have, the number it starts the game with
+    pub fn max_pieces(&self) -> usize {
+        return CheckersState::new(self.rules).count_pieces(PieceColor::Black);
+    }
+
+    pub fn count_pieces(&self, player: PieceColor) -> usize {
+        return self.board.iter().flatten().filter(|sq| matches!(sq, Some(piece) if piece.col == player)).count();
+    }
+
+    // Checks a position set up by hand can be played from
+    pub fn validate_setup(&self) -> Result<(), String> {
+        let dim = self.board.len();
+        for row in 0..dim {
+            for col in 0..dim {
+                if let Some(piece) = self.board[row][col] {
+                    if !self.rules.is_playable(row, col) {
+                        return Err(String::from("Pieces can only stand on dark squares"));
+                    }
+                    let crowning_row = match piece.col {
+                        PieceColor::Black => 0,
+                        PieceColor::Red => dim - 1
+                    };
+                    if piece.typ == PieceType::Man && row == crowning_row {
+                        return Err(format!("{:?} man on its crowning row should be a king", piece.col));
+                    }
+                }
+            }
+        }
+        for player in [PieceColor::Black, PieceColor::Red] {
+            let count = self.count_pieces(player);
+            if count == 0 {
+                return Err(format!("{:?} has no pieces", player));
+            }
+            if count > self.max_pieces() {
+                return Err(format!("{:?} has {} pieces, at most {} are allowed", player, count, self.max_pieces()));
+            }
+        }
+        if !self.possible_to_move(self.turn) {
+            return Err(format!("{:?} is to move but has no legal moves", self.turn));
+        }
+        return Ok(());
+    }
+
     pub fn is_in_middle(&self, pos: Position) -> bool{
         return self.is_valid_dim(pos.row as i32 + 2) && self.is_valid_dim(pos.row as i32 - 2) && self.is_valid_dim(pos.col as i32 + 2) && self.is_valid_dim(pos.col as i32 - 2);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Variant;
+
+    fn empty_board(variant: Variant, turn: PieceColor) -> CheckersState {
+        let mut state = CheckersState::new(variant.rules());
+        for square in state.board.iter_mut().flatten() {
+            *square = None;
+        }
+        state.turn = turn;
+        return state;
+    }
+
+    fn put(state: &mut CheckersState, row: usize, col: usize, color: PieceColor, typ: PieceType) {
+        assert!(state.rules.is_playable(row, col));
+        state.board[row][col] = Some(CheckersPiece { col: color, typ });
+    }
+
+    fn step(from: (usize, usize), to: (usize, usize)) -> Move {
+        return Move { from: Position::new(from.0, from.1), to: Position::new(to.0, to.1) };
+    }
+
+    #[test]
+    fn international_takes_the_most_pieces() {
+        // the man on (2, 3) can take one man to the left or two to the right
+        for (variant, sequences) in [(Variant::International, 1), (Variant::Brazilian, 1), (Variant::Russian, 2), (Variant::Pool, 2)] {
+            let mut state = empty_board(variant, PieceColor::Red);
+            put(&mut state, 2, 3, PieceColor::Red, PieceType::Man);
+            put(&mut state, 3, 2, PieceColor::Black, PieceType::Man);
+            put(&mut state, 3, 4, PieceColor::Black, PieceType::Man);
+            put(&mut state, 5, 6, PieceColor::Black, PieceType::Man);
+            let legal = state.legal_sequences();
+            assert_eq!(legal.len(), sequences, "{:?}", variant);
+            assert!(legal.contains(&vec![step((2, 3), (4, 5)), step((4, 5), (6, 7))]));
+        }
+    }
+
+    #[test]
+    fn crowning_in_the_middle_of_a_capture() {
+        // the man crowns on (7, 4), only a king can go on to take the man on (5, 6)
+        let mut state = empty_board(Variant::Russian, PieceColor::Red);
+        put(&mut state, 5, 2, PieceColor::Red, PieceType::Man);
+        put(&mut state, 6, 3, PieceColor::Black, PieceType::Man);
+        put(&mut state, 5, 6, PieceColor::Black, PieceType::Man);
+        let sequence = vec![step((5, 2), (7, 4)), step((7, 4), (4, 7))];
+        assert_eq!(state.legal_sequences(), vec![sequence.clone()]);
+        let mut after = state.clone();
+        for m in sequence.iter() {
+            after.update_with_move(m);
+        }
+        assert_eq!(after.at(&Position::new(4, 7)), Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::King }));
+        assert_eq!(after.turn, PieceColor::Black);
+
+        // in pool the man is crowned and stops there
+        let mut pool = state.clone();
+        pool.rules = Variant::Pool.rules();
+        assert_eq!(pool.legal_sequences(), vec![vec![step((5, 2), (7, 4))]]);
+
+        // and when it can capture on as a man it passes through the last row without crowning
+        pool.board[5][6] = None;
+        put(&mut pool, 6, 5, PieceColor::Black, PieceType::Man);
+        let sequence = vec![step((5, 2), (7, 4)), step((7, 4), (5, 6))];
+        assert_eq!(pool.legal_sequences(), vec![sequence.clone()]);
+        let mut after = pool.clone();
+        let (_, crowned, next) = after.update_with_move(&sequence[0]);
+        assert!(!crowned);
+        assert_eq!(next, vec![sequence[1]]);
+        after.update_with_move(&sequence[1]);
+        assert_eq!(after.at(&Position::new(5, 6)), Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::Man }));
+    }
+
+    #[test]
+    fn italian_capture_precedence() {
+        // a king has to capture rather than a man
+        let mut state = empty_board(Variant::Italian, PieceColor::Red);
+        put(&mut state, 2, 2, PieceColor::Red, PieceType::Man);
+        put(&mut state, 3, 3, PieceColor::Black, PieceType::Man);
+        put(&mut state, 5, 5, PieceColor::Red, PieceType::King);
+        put(&mut state, 4, 6, PieceColor::Black, PieceType::Man);
+        assert_eq!(state.legal_sequences(), vec![vec![step((5, 5), (3, 7))]]);
+
+        // then it has to capture a king rather than a man
+        let mut state = empty_board(Variant::Italian, PieceColor::Red);
+        put(&mut state, 5, 5, PieceColor::Red, PieceType::King);
+        put(&mut state, 4, 6, PieceColor::Black, PieceType::Man);
+        put(&mut state, 4, 4, PieceColor::Black, PieceType::King);
+        assert_eq!(state.legal_sequences(), vec![vec![step((5, 5), (3, 3))]]);
+
+        // and with as many kings either way, take the king first
+        let mut state = empty_board(Variant::Italian, PieceColor::Red);
+        put(&mut state, 3, 3, PieceColor::Red, PieceType::King);
+        put(&mut state, 4, 4, PieceColor::Black, PieceType::King);
+        put(&mut state, 6, 6, PieceColor::Black, PieceType::Man);
+        put(&mut state, 4, 2, PieceColor::Black, PieceType::Man);
+        put(&mut state, 6, 2, PieceColor::Black, PieceType::King);
+        assert_eq!(state.legal_sequences(), vec![vec![step((3, 3), (5, 5)), step((5, 5), (7, 7))]]);
+
+        // men may not capture kings at all
+        let mut state = empty_board(Variant::Italian, PieceColor::Red);
+        put(&mut state, 2, 2, PieceColor::Red, PieceType::Man);
+        put(&mut state, 3, 3, PieceColor::Black, PieceType::King);
+        assert_eq!(state.possible_captures(), vec![]);
+    }
+
+    #[test]
+    fn turkish_captures_never_turn_back() {
+        // after taking the man on the right the king may not come back for the one on the left
+        let mut state = empty_board(Variant::Turkish, PieceColor::Red);
+        put(&mut state, 3, 3, PieceColor::Red, PieceType::King);
+        put(&mut state, 3, 4, PieceColor::Black, PieceType::Man);
+        put(&mut state, 3, 1, PieceColor::Black, PieceType::Man);
+        let legal = state.legal_sequences();
+        assert_eq!(legal.len(), 4);
+        assert!(legal.iter().all(|sequence| sequence.len() == 1));
+
+        let mut after = state.clone();
+        let (_, _, next) = after.update_with_move(&step((3, 3), (3, 5)));
+        assert_eq!(after.capture_direction, None);
+        assert_eq!(next, vec![]);
+        assert_eq!(after.turn, PieceColor::Black);
+    }
+
+    #[test]
+    fn frisian_captures_along_rows_and_columns() {
+        let mut state = empty_board(Variant::Frisian, PieceColor::Red);
+        put(&mut state, 4, 3, PieceColor::Red, PieceType::Man);
+        put(&mut state, 4, 5, PieceColor::Black, PieceType::Man);
+        put(&mut state, 6, 3, PieceColor::Black, PieceType::Man);
+        let legal = state.legal_sequences();
+        assert_eq!(legal.len(), 2);
+        assert!(legal.contains(&vec![step((4, 3), (4, 7))]));
+        assert!(legal.contains(&vec![step((4, 3), (8, 3))]));
+        assert_eq!(state.captured_by(&step((4, 3), (4, 7))), Some(Position::new(4, 5)));
+    }
+
+    #[test]
+    fn frisian_king_rests_after_three_moves() {
+        let mut state = empty_board(Variant::Frisian, PieceColor::Red);
+        put(&mut state, 4, 5, PieceColor::Red, PieceType::King);
+        put(&mut state, 1, 0, PieceColor::Red, PieceType::Man);
+        put(&mut state, 9, 8, PieceColor::Black, PieceType::Man);
+        let king = Position::new(4, 5);
+        state.king_streaks[PieceColor::Red.index()] = KingStreak { pos: Some(king), moves: 3 };
+        let legal = state.legal_sequences();
+        assert!(legal.len() > 0);
+        assert!(legal.iter().all(|sequence| sequence[0].from != king));
+        assert!(state.rule_violation(&step((4, 5), (5, 6)), None).is_some());
+
+        // without men left the king can go on
+        state.board[1][0] = None;
+        assert!(state.legal_sequences().iter().any(|sequence| sequence[0].from == king));
+
+        // and so can it when the only man left is blocked
+        put(&mut state, 8, 1, PieceColor::Red, PieceType::Man);
+        put(&mut state, 9, 0, PieceColor::Black, PieceType::Man);
+        put(&mut state, 9, 2, PieceColor::Black, PieceType::Man);
+        assert_eq!(state.tired_king(), Some(king));
+        let legal = state.legal_sequences();
+        assert!(legal.len() > 0);
+        assert!(legal.iter().all(|sequence| sequence[0].from == king));
+        assert_eq!(state.rule_violation(&step((4, 5), (5, 6)), None), None);
+    }
+
+    #[test]
+    fn editor_positions_are_checked() {
+        assert_eq!(CheckersState::new(Variant::American.rules()).validate_setup(), Ok(()));
+
+        let mut state = empty_board(Variant::American, PieceColor::Black);
+        put(&mut state, 5, 2, PieceColor::Black, PieceType::Man);
+        assert_eq!(state.validate_setup(), Err(String::from("Red has no pieces")));
+
+        put(&mut state, 2, 1, PieceColor::Red, PieceType::Man);
+        assert_eq!(state.validate_setup(), Ok(()));
+
+        let mut light = state.clone();
+        light.board[0][0] = Some(CheckersPiece { col: PieceColor::Red, typ: PieceType::King });
+        assert_eq!(light.validate_setup(), Err(String::from("Pieces can only stand on dark squares")));
+
+        let mut uncrowned = state.clone();
+        put(&mut uncrowned, 0, 1, PieceColor::Black, PieceType::Man);
+        assert_eq!(uncrowned.validate_setup(), Err(String::from("Black man on its crowning row should be a king")));
+
+        let mut crowded = CheckersState::new(Variant::American.rules());
+        put(&mut crowded, 3, 0, PieceColor::Red, PieceType::Man);
+        assert_eq!(crowded.validate_setup(), Err(String::from("Red has 13 pieces, at most 12 are allowed")));
+
+        // Red's man is blocked in by the Black men in front of it
+        let mut blocked = empty_board(Variant::American, PieceColor::Red);
+        put(&mut blocked, 6, 1, PieceColor::Red, PieceType::Man);
+        put(&mut blocked, 7, 0, PieceColor::Black, PieceType::Man);
+        put(&mut blocked, 7, 2, PieceColor::Black, PieceType::Man);
+        assert_eq!(blocked.validate_setup(), Err(String::from("Red is to move but has no legal moves")));
+    }
+
+    #[test]
+    fn giveaway_wins_by_running_out_of_moves() {
+        // Red's man is blocked by the two Black men in front of it
+        let mut state = empty_board(Variant::American, PieceColor::Red);
+        put(&mut state, 6, 1, PieceColor::Red, PieceType::Man);
+        put(&mut state, 7, 0, PieceColor::Black, PieceType::Man);
+        put(&mut state, 7, 2, PieceColor::Black, PieceType::Man);
+        assert_eq!(state.get_winner(), Some(PieceColor::Black));
+        state.rules.giveaway = true;
+        assert_eq!(state.get_winner(), Some(PieceColor::Red));
+
+        // losing every piece wins as well
+        let mut state = empty_board(Variant::American, PieceColor::Red);
+        state.rules.giveaway = true;
+        put(&mut state, 2, 1, PieceColor::Red, PieceType::Man);
+        assert_eq!(state.get_winner(), Some(PieceColor::Black));
+    }
+}
@@ -0,0 +1,658 @@
+use bevy::prelude::*;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock}
+};
+use crate::{
+    state::{CheckersState, CheckersPiece, PieceColor, PieceType},
+    rules::RulesConfig,
+    logic::{Move, Position},
+    notation::{square_number, square_position},
+    alphabeta::TwoPlayerGameState,
+    save::save_dir
+};
+
+
+// Endgame databases made by retrograde analysis. Positions are grouped by material, and each
+// group is a table with a value for every way of placing the pieces and either side to move.
+// Groups are solved with fewer pieces first, then fewer men, so every capture or crowning
+// leads to a group that is already solved. Within a group results spread back from the
+// decided positions by undoing simple moves, the shortest distances first.
+
+const FILE_PREFIX: &str = "tablebase_";
+const FILE_MAGIC: &[u8; 4] = b"CKTB";
+const FILE_VERSION: u8 = 2;
+
+// Values stored for a position, from the side to move
+const UNKNOWN: u16 = 0;
+const INVALID: u16 = 1;
+
+
+fn win_value(distance: u16) -> u16 {
+    return 2 + 2 * distance;
+}
+
+
+fn loss_value(distance: u16) -> u16 {
+    return 3 + 2 * distance;
+}
+
+
+// Result for the side to move, with the number of plies to the end of the game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TablebaseResult {
+    Win(u32),
+    Loss(u32),
+    Draw
+}
+
+
+impl TablebaseResult {
+    fn from_value(value: u16) -> Option<Self> {
+        match value {
+            UNKNOWN => return Some(TablebaseResult::Draw),
+            INVALID => return None,
+            v if v % 2 == 0 => return Some(TablebaseResult::Win(((v - 2) / 2) as u32)),
+            v => return Some(TablebaseResult::Loss(((v - 3) / 2) as u32))
+        }
+    }
+}
+
+
+// Pieces of each kind, Black men and kings then Red men and kings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Material([u8; 4]);
+
+
+impl Material {
+    pub fn of(state: &CheckersState) -> Material {
+        let mut counts = [0u8; 4];
+        for piece in state.board.iter().flatten().flatten() {
+            counts[kind_index(piece)] += 1;
+        }
+        return Material(counts);
+    }
+
+    pub fn pieces(&self) -> usize {
+        return self.0.iter().map(|&c| c as usize).sum();
+    }
+
+    pub fn label(&self) -> String {
+        return format!("B {}m {}k v R {}m {}k", self.0[0], self.0[1], self.0[2], self.0[3]);
+    }
+
+    // Every split of up to max_pieces between the sides, each with at least one piece, in the order they are solved
+    fn all(max_pieces: usize) -> Vec<Material> {
+        let mut all = Vec::new();
+        for pieces in 2..=max_pieces {
+            for black in 1..pieces {
+                let red = pieces - black;
+                for black_men in 0..=black {
+                    for red_men in 0..=red {
+                        all.push(Material([black_men as u8, (black - black_men) as u8, red_men as u8, (red - red_men) as u8]));
+                    }
+                }
+            }
+        }
+        all.sort_by_key(|m| (m.pieces(), m.0[0] + m.0[2]));
+        return all;
+    }
+}
+
+
+fn kind_index(piece: &CheckersPiece) -> usize {
+    match (piece.col, piece.typ) {
+        (PieceColor::Black, PieceType::Man) => 0,
+        (PieceColor::Black, PieceType::King) => 1,
+        (PieceColor::Red, PieceType::Man) => 2,
+        (PieceColor::Red, PieceType::King) => 3
+    }
+}
+
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    let mut result = 1;
+    for i in 0..k {
+        result = result * (n - i) / (i + 1);
+    }
+    return result;
+}
+
+
+// Position of a set of squares, given in increasing order, among all sets of that size
+fn rank(squares: &[usize]) -> usize {
+    return squares.iter().enumerate().map(|(i, &s)| binomial(s, i + 1)).sum();
+}
+
+
+fn unrank(mut rank: usize, count: usize, squares: usize) -> Vec<usize> {
+    let mut result = vec![0; count];
+    let mut top = squares;
+    for i in (1..=count).rev() {
+        let mut s = top - 1;
+        while binomial(s, i) > rank {
+            s -= 1;
+        }
+        result[i - 1] = s;
+        rank -= binomial(s, i);
+        top = s;
+    }
+    return result;
+}
+
+
+fn table_size(material: &Material, squares: usize) -> usize {
+    return material.0.iter().map(|&c| binomial(squares, c as usize)).product::<usize>() * 2;
+}
+
+
+fn board_squares(rules: &RulesConfig) -> usize {
+    return rules.squares_per_row() * rules.board_dim;
+}
+
+
+// Only whole turns from positions the board alone describes can be looked up
+fn can_probe(state: &CheckersState) -> bool {
+    return state.pending_captures.len() == 0 && state.capture_direction.is_none() && state.passes.is_none() && state.rules.king_move_limit.is_none();
+}
+
+
+fn index_of(state: &CheckersState, material: &Material) -> usize {
+    let squares = board_squares(&state.rules);
+    let mut groups: [Vec<usize>; 4] = Default::default();
+    for row in 0..state.board.len() {
+        for col in 0..state.board.len() {
+            if let Some(piece) = state.board[row][col] {
+                groups[kind_index(&piece)].push(square_number(&Position::new(row, col), &state.rules) - 1);
+            }
+        }
+    }
+    let mut index = 0;
+    for (kind, group) in groups.iter_mut().enumerate() {
+        group.sort();
+        index = index * binomial(squares, material.0[kind] as usize) + rank(group);
+    }
+    return index * 2 + if state.turn == PieceColor::Black { 0 } else { 1 };
+}
+
+
+// The position at an index, None if pieces share a square or a man stands on its crowning row
+fn position_at(index: usize, material: &Material, rules: &RulesConfig) -> Option<CheckersState> {
+    let squares = board_squares(rules);
+    let mut state = CheckersState::new(*rules);
+    for row in state.board.iter_mut() {
+        for square in row.iter_mut() {
+            *square = None;
+        }
+    }
+    state.turn = if index % 2 == 0 { PieceColor::Black } else { PieceColor::Red };
+    let mut rest = index / 2;
+    let mut ranks = [0; 4];
+    for kind in (0..4).rev() {
+        let size = binomial(squares, material.0[kind] as usize);
+        ranks[kind] = rest % size;
+        rest /= size;
+    }
+    let dim = rules.board_dim;
+    for kind in 0..4 {
+        let (col, typ) = match kind {
+            0 => (PieceColor::Black, PieceType::Man),
+            1 => (PieceColor::Black, PieceType::King),
+            2 => (PieceColor::Red, PieceType::Man),
+            _ => (PieceColor::Red, PieceType::King)
+        };
+        for square in unrank(ranks[kind], material.0[kind] as usize, squares) {
+            let pos = square_position(square + 1, rules)?;
+            if state.board[pos.row][pos.col].is_some() {
+                return None;
+            }
+            let crowning_row = if col == PieceColor::Black { 0 } else { dim - 1 };
+            if typ == PieceType::Man && pos.row == crowning_row {
+                return None;
+            }
+            state.board[pos.row][pos.col] = Some(CheckersPiece { col, typ });
+        }
+    }
+    return Some(state);
+}
+
+
+// How a table came out for the side to move
+#[derive(Debug, Default)]
+pub struct TableCounts {
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+    // plies of the slowest win
+    pub longest: u32
+}
+
+
+impl TableCounts {
+    fn of(values: &[u16]) -> TableCounts {
+        let mut counts = TableCounts::default();
+        for value in values.iter() {
+            match TablebaseResult::from_value(*value) {
+                Some(TablebaseResult::Win(plies)) => {
+                    counts.wins += 1;
+                    counts.longest = counts.longest.max(plies);
+                },
+                Some(TablebaseResult::Loss(_)) => counts.losses += 1,
+                Some(TablebaseResult::Draw) => counts.draws += 1,
+                None => {}
+            }
+        }
+        return counts;
+    }
+}
+
+
+// Tables bigger than this aren't read for probing, the six piece tables on 8x8 would take gigabytes
+const MAX_PROBE_TABLE_BYTES: usize = 256 << 20;
+// Marks a position that can't be lost, one of its replies is a draw or a win
+const CANNOT_LOSE: u16 = u16::MAX;
+
+
+fn read_bytes<R: Read>(file: &mut R, count: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; count];
+    file.read_exact(&mut bytes).map_err(|_| String::from("Tablebase file is cut short"))?;
+    return Ok(bytes);
+}
+
+
+fn read_table(path: &Path, offset: u64, len: usize) -> Result<Vec<u16>, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    file.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    let mut bytes = vec![0u8; len * 2];
+    file.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    return Ok(bytes.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect());
+}
+
+
+// Unresolved positions of the same table with a simple move leading to this one
+fn predecessors(state: &CheckersState, material: &Material, values: &[u16]) -> Vec<usize> {
+    let mover = state.turn.opponent();
+    let dim = state.board.len() as i32;
+    let mut result = Vec::new();
+    for row in 0..dim {
+        for col in 0..dim {
+            let piece = match state.board[row as usize][col as usize] {
+                Some(piece) if piece.col == mover => piece,
+                _ => continue
+            };
+            let flying = piece.typ == PieceType::King && state.rules.flying_kings;
+            for (dr, dc) in [(1, 1), (1, -1), (-1, 1), (-1, -1), (1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let (mut r, mut c) = (row + dr, col + dc);
+                while r >= 0 && c >= 0 && r < dim && c < dim && state.rules.is_playable(r as usize, c as usize) && state.board[r as usize][c as usize].is_none() {
+                    let mut before = state.clone();
+                    before.board[row as usize][col as usize] = None;
+                    before.board[r as usize][c as usize] = Some(piece);
+                    before.turn = mover;
+                    let index = index_of(&before, material);
+                    if values[index] == UNKNOWN {
+                        let step = Move { from: Position::new(r as usize, c as usize), to: Position::new(row as usize, col as usize) };
+                        if before.get_possible_moves().contains(&vec![step]) {
+                            result.push(index);
+                        }
+                    }
+                    if !flying {
+                        break;
+                    }
+                    r += dr;
+                    c += dc;
+                }
+            }
+        }
+    }
+    return result;
+}
+
+
+pub struct Tablebase {
+    pub rules: RulesConfig,
+    pub max_pieces: usize,
+    path: PathBuf,
+    // where each table's values start in the file and how many there are
+    index: HashMap<Material, (u64, usize)>,
+    // tables read so far, None for those too big to read
+    tables: RwLock<HashMap<Material, Option<Arc<Vec<u16>>>>>,
+    max_table_bytes: usize
+}
+
+
+impl Tablebase {
+    // Solves every table up to max_pieces into the file, reporting each one as it is done. Only
+    // the table being solved and the ones its captures and crownings lead to are kept in memory.
+    pub fn generate<F: FnMut(&Material, &TableCounts)>(rules: &RulesConfig, max_pieces: usize, path: &Path, mut report: F) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let mut file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+        let header = file_header(rules, max_pieces)?;
+        file.write_all(&header).map_err(|e| e.to_string())?;
+        let mut offset = header.len() as u64;
+        let mut tablebase = Tablebase { rules: *rules, max_pieces, path: path.to_path_buf(), index: HashMap::new(), tables: RwLock::new(HashMap::new()), max_table_bytes: usize::MAX };
+        let squares = board_squares(rules);
+
+        for material in Material::all(max_pieces) {
+            let size = table_size(&material, squares);
+            let mut values = vec![INVALID; size];
+            // replies in this table still to be resolved, and the longest win among those resolved
+            let mut remaining = vec![0u8; size];
+            let mut longest_win = vec![0u16; size];
+            // positions to resolve, by distance, the same position can be queued more than once
+            let mut queue: Vec<Vec<(u32, u16)>> = Vec::new();
+            let push = |queue: &mut Vec<Vec<(u32, u16)>>, index: usize, value: u16| {
+                let distance = ((value - 2) / 2) as usize;
+                if queue.len() <= distance {
+                    queue.resize(distance + 1, Vec::new());
+                }
+                queue[distance].push((index as u32, value));
+            };
+
+            for index in 0..size {
+                let state = match position_at(index, &material, rules) {
+                    Some(state) => state,
+                    None => continue
+                };
+                if let Some(winner) = state.get_winner() {
+                    values[index] = if winner == state.turn { win_value(0) } else { loss_value(0) };
+                    continue;
+                }
+                values[index] = UNKNOWN;
+                let mut shortest_loss: Option<u16> = None;
+                for turn in state.get_possible_moves() {
+                    let next = state.next_state_with_move(&turn);
+                    let next_material = Material::of(&next);
+                    let value = match next.get_winner() {
+                        Some(winner) if winner == next.turn => win_value(0),
+                        Some(_) => loss_value(0),
+                        None if next_material == material => {
+                            remaining[index] += 1;
+                            continue;
+                        },
+                        None => tablebase.table(&next_material).map(|t| t[index_of(&next, &next_material)]).unwrap_or(UNKNOWN)
+                    };
+                    match TablebaseResult::from_value(value) {
+                        Some(TablebaseResult::Loss(d)) => shortest_loss = Some(shortest_loss.map_or(d as u16, |s| s.min(d as u16))),
+                        Some(TablebaseResult::Win(d)) if longest_win[index] != CANNOT_LOSE => longest_win[index] = longest_win[index].max(d as u16),
+                        _ => longest_win[index] = CANNOT_LOSE
+                    }
+                }
+                if let Some(d) = shortest_loss {
+                    longest_win[index] = CANNOT_LOSE;
+                    push(&mut queue, index, win_value(d + 1));
+                } else if remaining[index] == 0 && longest_win[index] != CANNOT_LOSE {
+                    push(&mut queue, index, loss_value(longest_win[index] + 1));
+                }
+            }
+
+            // resolve positions in order of distance, each one settling the positions that can move to it
+            let mut distance = 0;
+            while distance < queue.len() {
+                let resolved = std::mem::take(&mut queue[distance]);
+                for (index, value) in resolved {
+                    let index = index as usize;
+                    if values[index] != UNKNOWN {
+                        continue;
+                    }
+                    values[index] = value;
+                    let state = position_at(index, &material, rules).unwrap();
+                    for before in predecessors(&state, &material, &values) {
+                        match TablebaseResult::from_value(value) {
+                            Some(TablebaseResult::Loss(d)) => push(&mut queue, before, win_value(d as u16 + 1)),
+                            Some(TablebaseResult::Win(d)) => {
+                                remaining[before] -= 1;
+                                if longest_win[before] != CANNOT_LOSE {
+                                    longest_win[before] = longest_win[before].max(d as u16);
+                                    if remaining[before] == 0 {
+                                        push(&mut queue, before, loss_value(longest_win[before] + 1));
+                                    }
+                                }
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+                distance += 1;
+            }
+
+            report(&material, &TableCounts::of(&values));
+            file.write_all(&material.0).map_err(|e| e.to_string())?;
+            file.write_all(&(size as u32).to_le_bytes()).map_err(|e| e.to_string())?;
+            for value in values.iter() {
+                file.write_all(&value.to_le_bytes()).map_err(|e| e.to_string())?;
+            }
+            file.flush().map_err(|e| e.to_string())?;
+            tablebase.index.insert(material, (offset + 8, size));
+            offset += 8 + size as u64 * 2;
+            // the next table reads what it needs from the file again
+            tablebase.tables.write().unwrap().clear();
+        }
+        return Ok(());
+    }
+
+    // Reads where the tables are in the file, the tables themselves are read when first probed
+    pub fn open(path: &Path) -> Result<Tablebase, String> {
+        let mut file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+        if read_bytes(&mut file, 4)? != FILE_MAGIC || read_bytes(&mut file, 1)?[0] != FILE_VERSION {
+            return Err(String::from("Not a tablebase file of this version"));
+        }
+        let max_pieces = read_bytes(&mut file, 1)?[0] as usize;
+        let rules_len = u16::from_le_bytes(read_bytes(&mut file, 2)?.try_into().unwrap()) as usize;
+        let rules: RulesConfig = serde_json::from_slice(&read_bytes(&mut file, rules_len)?).map_err(|e| e.to_string())?;
+        let mut offset = (4 + 1 + 1 + 2 + rules_len) as u64;
+        let total = fs::metadata(path).map_err(|e| e.to_string())?.len();
+        let mut index = HashMap::new();
+        while offset < total {
+            let material = Material(read_bytes(&mut file, 4)?.try_into().unwrap());
+            let len = u32::from_le_bytes(read_bytes(&mut file, 4)?.try_into().unwrap()) as usize;
+            index.insert(material, (offset + 8, len));
+            offset += 8 + len as u64 * 2;
+            file.seek_relative(len as i64 * 2).map_err(|e| e.to_string())?;
+        }
+        if offset != total {
+            return Err(String::from("Tablebase file is cut short"));
+        }
+        return Ok(Tablebase { rules, max_pieces, path: path.to_path_buf(), index, tables: RwLock::new(HashMap::new()), max_table_bytes: MAX_PROBE_TABLE_BYTES });
+    }
+
+    fn table(&self, material: &Material) -> Option<Arc<Vec<u16>>> {
+        if let Some(table) = self.tables.read().unwrap().get(material) {
+            return table.clone();
+        }
+        let table = match self.index.get(material) {
+            Some(&(_, len)) if len * 2 > self.max_table_bytes => {
+                info!("Not reading the {} table, it is {} MB", material.label(), (len * 2) >> 20);
+                None
+            },
+            Some(&(offset, len)) => match read_table(&self.path, offset, len) {
+                Ok(values) => Some(Arc::new(values)),
+                Err(e) => {
+                    info!("Could not read the {} table: {}", material.label(), e);
+                    None
+                }
+            },
+            None => None
+        };
+        self.tables.write().unwrap().insert(*material, table.clone());
+        return table;
+    }
+
+    pub fn probe_state(&self, state: &CheckersState) -> Option<TablebaseResult> {
+        if !can_probe(state) {
+            return None;
+        }
+        let material = Material::of(state);
+        if material.pieces() > self.max_pieces || state.rules != self.rules {
+            return None;
+        }
+        let table = self.table(&material)?;
+        return TablebaseResult::from_value(table[index_of(state, &material)]);
+    }
+}
+
+
+fn file_header(rules: &RulesConfig, max_pieces: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(FILE_MAGIC);
+    bytes.push(FILE_VERSION);
+    bytes.push(max_pieces as u8);
+    let rules = serde_json::to_string(rules).map_err(|e| e.to_string())?;
+    bytes.extend_from_slice(&(rules.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(rules.as_bytes());
+    return Ok(bytes);
+}
+
+
+pub fn tablebase_path(rules: &RulesConfig) -> PathBuf {
+    return save_dir().join(format!("{}{}.bin", FILE_PREFIX, rules.name().to_lowercase().replace(' ', "_")));
+}
+
+
+static LOADED: OnceLock<Vec<Tablebase>> = OnceLock::new();
+
+
+// Every tablebase in the save folder, opened the first time a position is looked up
+fn loaded() -> &'static Vec<Tablebase> {
+    return LOADED.get_or_init(|| {
+        let mut tablebases = Vec::new();
+        if let Ok(entries) = fs::read_dir(save_dir()) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with(FILE_PREFIX) {
+                    match Tablebase::open(&entry.path()) {
+                        Ok(tablebase) => tablebases.push(tablebase),
+                        Err(e) => info!("Could not open {}: {}", name, e)
+                    }
+                }
+            }
+        }
+        return tablebases;
+    });
+}
+
+
+pub fn probe(state: &CheckersState) -> Option<TablebaseResult> {
+    return loaded().iter().find_map(|tablebase| tablebase.probe_state(state));
+}
+
+
+// The turn keeping the best result, the quickest win or the slowest loss, if every reply can be looked up
+pub fn tablebase_move(state: &CheckersState) -> Option<Vec<Move>> {
+    if loaded().len() == 0 || probe(state).is_none() {
+        return None;
+    }
+    return best_move(state, probe);
+}
+
+
+fn best_move<P: Fn(&CheckersState) -> Option<TablebaseResult>>(state: &CheckersState, probe: P) -> Option<Vec<Move>> {
+    let mut best: Option<(i64, Vec<Move>)> = None;
+    for turn in state.get_possible_moves() {
+        let next = state.next_state_with_move(&turn);
+        let result = match next.get_winner() {
+            Some(winner) if winner == next.turn => TablebaseResult::Win(0),
+            Some(_) => TablebaseResult::Loss(0),
+            None => probe(&next)?
+        };
+        // the reply's result is the opponent's, so their losses rank first
+        let rank = match result {
+            TablebaseResult::Loss(d) => 1000000 - d as i64,
+            TablebaseResult::Draw => 0,
+            TablebaseResult::Win(d) => -1000000 + d as i64
+        };
+        if best.as_ref().map_or(true, |(b, _)| rank > *b) {
+            best = Some((rank, turn));
+        }
+    }
+    return best.map(|(_, turn)| turn);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rules::Variant, notation::from_fen};
+
+    // Three piece American tables, solved once for all the tests
+    fn three_pieces() -> &'static Tablebase {
+        static TABLEBASE: OnceLock<Tablebase> = OnceLock::new();
+        return TABLEBASE.get_or_init(|| {
+            let path = std::env::temp_dir().join(format!("{}test_{}.bin", FILE_PREFIX, std::process::id()));
+            Tablebase::generate(&Variant::American.rules(), 3, &path, |_, _| {}).unwrap();
+            let tablebase = Tablebase::open(&path).unwrap();
+            // every table is read before the file goes
+            for material in Material::all(3) {
+                tablebase.table(&material).unwrap();
+            }
+            fs::remove_file(&path).unwrap();
+            return tablebase;
+        });
+    }
+
+    fn probe_fen(fen: &str) -> Option<TablebaseResult> {
+        return three_pieces().probe_state(&from_fen(fen, Variant::American.rules()).unwrap());
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let rules = Variant::American.rules();
+        let material = Material([1, 1, 0, 1]);
+        let mut positions = 0;
+        for index in 0..table_size(&material, board_squares(&rules)) {
+            if let Some(state) = position_at(index, &material, &rules) {
+                assert_eq!(Material::of(&state), material);
+                assert_eq!(index_of(&state, &material), index);
+                positions += 1;
+            }
+        }
+        // the man can't stand on its crowning row or share a square with a king
+        assert_eq!(positions, 28 * 31 * 30 * 2);
+    }
+
+    #[test]
+    fn kings_results_and_distances() {
+        // far apart a lone king can't be caught, next to each other the side to move takes
+        assert_eq!(probe_fen("B:WK32:BK1"), Some(TablebaseResult::Draw));
+        assert_eq!(probe_fen("B:WK18:BK15"), Some(TablebaseResult::Win(1)));
+        assert_eq!(probe_fen("W:WK18:BK15"), Some(TablebaseResult::Win(1)));
+        // two kings run down one, the lone king taking one of them first only draws
+        assert!(matches!(probe_fen("B:WK32:BK1,K5"), Some(TablebaseResult::Win(d)) if d > 1 && d % 2 == 1));
+        assert!(matches!(probe_fen("W:WK32:BK1,K5"), Some(TablebaseResult::Loss(d)) if d > 1 && d % 2 == 0));
+        assert_eq!(probe_fen("W:WK18:BK15,K1"), Some(TablebaseResult::Draw));
+    }
+
+    #[test]
+    fn tablebase_move_wins_fastest() {
+        let tablebase = three_pieces();
+        let rules = Variant::American.rules();
+        let material = Material([0, 2, 0, 1]);
+        let mut wins = 0;
+        for index in 0..table_size(&material, board_squares(&rules)) {
+            let state = match position_at(index, &material, &rules) {
+                Some(state) if state.turn == PieceColor::Black && state.get_winner().is_none() => state,
+                _ => continue
+            };
+            let distance = match tablebase.probe_state(&state) {
+                Some(TablebaseResult::Win(d)) => d,
+                _ => continue
+            };
+            let turn = best_move(&state, |s| tablebase.probe_state(s)).unwrap();
+            let next = state.next_state_with_move(&turn);
+            let reply = match next.get_winner() {
+                Some(_) => TablebaseResult::Loss(0),
+                None => tablebase.probe_state(&next).unwrap()
+            };
+            assert_eq!(reply, TablebaseResult::Loss(distance - 1));
+            wins += 1;
+        }
+        assert!(wins > 0);
+    }
+}
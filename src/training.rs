@@ -0,0 +1,105 @@
+use std::fs;
+use crate::{
+    state::CheckersState,
+    alphabeta::TwoPlayerGameState,
+    notation::parse_pdn
+};
+
+
+// A quiet position from a finished game, with the points the side to move got from it
+pub struct LabelledPosition {
+    pub state: CheckersState,
+    pub result: f32
+}
+
+
+// Points for the side that moves first, None for unfinished games
+pub fn first_player_points(result: &str) -> Option<f32> {
+    match result {
+        "2-0" | "1-0" => Some(1.),
+        "0-2" | "0-1" => Some(0.),
+        "1-1" | "1/2-1/2" => Some(0.5),
+        _ => None
+    }
+}
+
+
+// Positions of every finished game in the PDN files, leaving out the first turns of each game
+// and positions with a capture to make, where counting material is misleading. Also returns why
+// any file or game was skipped.
+pub fn read_labelled_positions(files: &[String], skip_turns: usize) -> (Vec<LabelledPosition>, Vec<String>) {
+    let mut positions = Vec::new();
+    let mut skipped = Vec::new();
+    for file in files.iter() {
+        let games = match fs::read_to_string(file).map_err(|e| e.to_string()).and_then(|text| parse_pdn(&text)) {
+            Ok(games) => games,
+            Err(e) => {
+                skipped.push(format!("Skipping {}: {}", file, e));
+                continue;
+            }
+        };
+        for game in games.iter() {
+            let result = game.tag("Result").or(game.result.as_deref()).and_then(first_player_points);
+            let (result, start) = match (result, game.start_state()) {
+                (Some(result), Ok(start)) => (result, start),
+                _ => continue
+            };
+            // the evaluation is turned around in giveaway, the games say nothing about normal play
+            if start.rules.giveaway {
+                continue;
+            }
+            let turns = match game.resolve_turns(&start) {
+                Ok(turns) => turns,
+                Err(e) => {
+                    skipped.push(format!("Skipping a game in {}: {}", file, e));
+                    continue;
+                }
+            };
+            let first_player = start.rules.first_player;
+            let mut state = start;
+            for (idx, turn) in turns.iter().enumerate() {
+                if idx >= skip_turns && state.possible_captures().len() == 0 {
+                    let points = if state.turn == first_player { result } else { 1. - result };
+                    positions.push(LabelledPosition { state: state.clone(), result: points });
+                }
+                state = state.next_state_with_move(turn);
+            }
+        }
+    }
+    return (positions, skipped);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_are_labelled_for_the_side_to_move() {
+        let path = std::env::temp_dir().join(format!("checkers_training_{}.pdn", std::process::id()));
+        // Black wins the first game, the second one isn't finished
+        let pdn = "[Result \"2-0\"]\n1. 11-15 22-18 2. 15x22 25x18 2-0\n\n[Result \"*\"]\n1. 9-13 *\n";
+        fs::write(&path, pdn).unwrap();
+        let files = vec![path.to_string_lossy().to_string(), String::from("missing.pdn")];
+        let (positions, skipped) = read_labelled_positions(&files, 0);
+        let (later, _) = read_labelled_positions(&files[..1], 1);
+        fs::remove_file(&path).unwrap();
+
+        // the last two positions have captures to make
+        let labels: Vec<f32> = positions.iter().map(|p| p.result).collect();
+        assert_eq!(labels, vec![1., 0.]);
+        assert_eq!(positions[1].state.turn, positions[0].state.turn.opponent());
+        assert_eq!(later.len(), 1);
+        assert_eq!(later[0].result, 0.);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].starts_with("Skipping missing.pdn"));
+    }
+
+    #[test]
+    fn results_from_the_first_player() {
+        assert_eq!(first_player_points("2-0"), Some(1.));
+        assert_eq!(first_player_points("0-1"), Some(0.));
+        assert_eq!(first_player_points("1-1"), Some(0.5));
+        assert_eq!(first_player_points("*"), None);
+    }
+}
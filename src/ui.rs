@@ -0,0 +1,53 @@
+use bevy::{prelude::*, ecs::system::EntityCommands};
+
+
+// Buttons look the same in the menu and the panels around the board
+
+pub const BUTTON_BACKGROUND: Color = Color::rgb(0.1, 0.1, 0.1);
+pub const BUTTON_HOVERED: Color = Color::rgb(0.2, 0.2, 0.2);
+pub const BUTTON_SELECTED: Color = Color::rgb(0.35, 0.35, 0.35);
+pub const BUTTON_FONT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+pub const BUTTON_FONT_SIZE: f32 = 16.;
+
+
+// Size and spacing of a kind of button
+#[derive(Clone, Copy)]
+pub struct ButtonLayout {
+    pub size: Size,
+    pub margin: UiRect,
+    pub font_size: f32
+}
+
+
+impl ButtonLayout {
+    pub fn new(size: Size, margin: UiRect) -> Self {
+        return ButtonLayout { size, margin, font_size: BUTTON_FONT_SIZE };
+    }
+}
+
+
+// The name on the text is used by the button systems to tell buttons apart
+pub fn spawn_button<'w, 's, 'a>(parent: &'a mut ChildBuilder<'w, 's, '_>, font: &Handle<Font>, label: &str, name: &str, layout: ButtonLayout) -> EntityCommands<'w, 's, 'a> {
+    let mut button = parent.spawn(ButtonBundle {
+        style: Style {
+            size: layout.size,
+            margin: layout.margin,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: BUTTON_BACKGROUND.into(),
+        ..default()
+    });
+    button.with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size: layout.font_size,
+                color: BUTTON_FONT_COLOR,
+            },
+        )).insert(Name::new(name.to_string()));
+    });
+    return button;
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+use checkers::{
+    state::CheckersState,
+    rules::Variant,
+    alphabeta::TwoPlayerGameState,
+    controller::{ExternalEngine, PlayerController},
+    notation::resolve_move
+};
+
+
+// The mock engine plays the moves on its command line in order, then reports it is out of moves
+fn launch_mock(moves: &[&str]) -> ExternalEngine {
+    let script = format!("{}/scripts/mock_hub_engine.py", env!("CARGO_MANIFEST_DIR"));
+    let mut args = vec![script];
+    args.extend(moves.iter().map(|m| m.to_string()));
+    return ExternalEngine::launch("python3", &args).unwrap();
+}
+
+
+#[test]
+fn plays_the_scripted_moves() {
+    let engine = launch_mock(&["11-15", "23-19"]);
+    assert_eq!(engine.name(), "Mock");
+    let mut state = CheckersState::new(Variant::American.rules());
+    for token in ["11-15", "23-19"] {
+        let moves = engine.choose_moves(&state, Some(Duration::from_millis(100))).unwrap();
+        assert_eq!(moves, resolve_move(&state, token).unwrap());
+        state = state.next_state_with_move(&moves);
+    }
+}
+
+
+#[test]
+fn out_of_moves_is_an_error() {
+    let engine = launch_mock(&[]);
+    let state = CheckersState::new(Variant::American.rules());
+    assert_eq!(engine.choose_moves(&state, None), Err(String::from("out of moves")));
+}
+
+
+#[test]
+fn hung_engine_times_out() {
+    let engine = launch_mock(&["hang"]);
+    let state = CheckersState::new(Variant::American.rules());
+    let started = Instant::now();
+    let result = engine.choose_moves(&state, Some(Duration::from_millis(100)));
+    assert_eq!(result, Err(String::from("Engine did not answer in time")));
+    assert!(started.elapsed() < Duration::from_secs(10));
+    // the engine was shut down, so it can't be asked again
+    assert!(engine.choose_moves(&state, Some(Duration::from_millis(100))).is_err());
+}